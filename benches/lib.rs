@@ -156,3 +156,40 @@ fn smoke_bench_3(b: &mut Bencher) {
         }
     });
 }
+
+#[bench]
+fn timer_cancel_bench(b: &mut Bencher) {
+    use may::sync::mpsc::channel;
+    use std::time::Duration;
+
+    b.iter(|| {
+        scope(|s| {
+            // each blocked recv adds a timer and cancels it when woken up
+            for _ in 0..100 {
+                let (tx1, rx1) = channel();
+                let (tx2, rx2) = channel();
+                go!(s, move || for i in 0..100 {
+                    tx1.send(i).unwrap();
+                    rx2.recv_timeout(Duration::from_secs(10)).unwrap();
+                });
+                go!(s, move || for i in 0..100 {
+                    rx1.recv_timeout(Duration::from_secs(10)).unwrap();
+                    tx2.send(i).unwrap();
+                });
+            }
+        });
+    });
+}
+
+#[bench]
+fn timer_sleep_bench(b: &mut Bencher) {
+    use std::time::Duration;
+
+    b.iter(|| {
+        scope(|s| {
+            for i in 0..10000 {
+                go!(s, move || sleep(Duration::from_micros(i % 1000)));
+            }
+        });
+    });
+}
@@ -0,0 +1,115 @@
+#![cfg(nightly)]
+#![feature(test)]
+
+// compare the timing wheel of the workers with the timer list of the old
+// timer thread, both are driven by the bench thread as the owner
+
+extern crate test;
+
+// the timer lists refer to `crate::sync::AtomicOption`
+mod sync {
+    pub use may::sync::AtomicOption;
+}
+
+mod old;
+// only the timer list part of the module is benched
+#[allow(dead_code)]
+#[path = "../../src/timeout_list.rs"]
+mod wheel;
+
+use std::cell::Cell;
+use std::sync::Arc;
+use std::time::Duration;
+
+use test::{black_box, Bencher};
+use wheel::Clock;
+
+const TIMERS: usize = 10_000;
+
+// add the timers of the same duration, like the socket timeouts, and
+// cancel them all before they expire
+#[bench]
+fn old_cancel_bench(b: &mut Bencher) {
+    let list = old::TimeOutList::with_clock(Arc::new(Clock::new()));
+    b.iter(|| {
+        let handles: Vec<_> = (0..TIMERS)
+            .map(|i| list.add_timer(Duration::from_secs(10), i).0)
+            .collect();
+        for h in handles {
+            black_box(h.remove());
+        }
+        list.schedule_timer(list.clock().now(), &|_| {});
+    });
+}
+
+#[bench]
+fn wheel_cancel_bench(b: &mut Bencher) {
+    let list = wheel::TimeOutList::with_clock(Arc::new(Clock::new()));
+    b.iter(|| {
+        let handles: Vec<_> = (0..TIMERS)
+            .map(|i| list.add_timer(Duration::from_secs(10), i).0)
+            .collect();
+        for h in handles {
+            black_box(h.remove());
+        }
+        list.schedule_timer(list.clock().now(), &|_| {});
+    });
+}
+
+// add the timers of different durations and fire them all
+#[bench]
+fn old_fire_bench(b: &mut Bencher) {
+    let list = old::TimeOutList::with_clock(Arc::new(Clock::new()));
+    b.iter(|| {
+        let fired = Cell::new(0);
+        for i in 0..TIMERS {
+            list.add_timer(Duration::from_micros((i % 1000) as u64 * 997), i);
+        }
+        let now = list.clock().now() + 1_000_000_000;
+        list.schedule_timer(now, &|_| fired.set(fired.get() + 1));
+        assert_eq!(fired.get(), TIMERS);
+    });
+}
+
+#[bench]
+fn wheel_fire_bench(b: &mut Bencher) {
+    let list = wheel::TimeOutList::with_clock(Arc::new(Clock::new()));
+    b.iter(|| {
+        let fired = Cell::new(0);
+        for i in 0..TIMERS {
+            list.add_timer(Duration::from_micros((i % 1000) as u64 * 997), i);
+        }
+        let now = list.clock().now() + 1_000_000_000;
+        while list.schedule_timer(now, &|_| fired.set(fired.get() + 1)) == Some(0) {}
+        assert_eq!(fired.get(), TIMERS);
+    });
+}
+
+// the timers of all different durations, like the ones from deadlines
+#[bench]
+fn old_fire_distinct_bench(b: &mut Bencher) {
+    let list = old::TimeOutList::with_clock(Arc::new(Clock::new()));
+    b.iter(|| {
+        let fired = Cell::new(0);
+        for i in 0..TIMERS {
+            list.add_timer(Duration::from_nanos(i as u64 * 99_991), i);
+        }
+        let now = list.clock().now() + 1_000_000_000;
+        list.schedule_timer(now, &|_| fired.set(fired.get() + 1));
+        assert_eq!(fired.get(), TIMERS);
+    });
+}
+
+#[bench]
+fn wheel_fire_distinct_bench(b: &mut Bencher) {
+    let list = wheel::TimeOutList::with_clock(Arc::new(Clock::new()));
+    b.iter(|| {
+        let fired = Cell::new(0);
+        for i in 0..TIMERS {
+            list.add_timer(Duration::from_nanos(i as u64 * 99_991), i);
+        }
+        let now = list.clock().now() + 1_000_000_000;
+        while list.schedule_timer(now, &|_| fired.set(fired.get() + 1)) == Some(0) {}
+        assert_eq!(fired.get(), TIMERS);
+    });
+}
@@ -325,6 +325,12 @@ impl<T> Queue<T> {
 
     /// pop from the queue, if it's empty return None
     pub fn bulk_pop(&self) -> SmallVec<[T; BLOCK_SIZE]> {
+        self.bulk_pop_max(BLOCK_SIZE)
+    }
+
+    /// pop at most `max` elements from the queue, `max` must not be zero
+    pub fn bulk_pop_max(&self, max: usize) -> SmallVec<[T; BLOCK_SIZE]> {
+        debug_assert!(max > 0);
         let mut head = self.head.0.load(Ordering::Acquire);
         let mut push_index = self.tail.index.load(Ordering::Acquire);
         let mut tail_block = self.tail.block.load(Ordering::Acquire);
@@ -338,7 +344,18 @@ impl<T> Queue<T> {
                 return SmallVec::new();
             }
 
-            let new_id = if block != tail_block { 0 } else { push_id };
+            let limit = if block != tail_block {
+                BLOCK_SIZE
+            } else {
+                push_id
+            };
+            let new_id = if id + max < limit {
+                id + max
+            } else if block != tail_block {
+                0
+            } else {
+                push_id
+            };
 
             let new_head = if new_id == 0 {
                 (head as usize | (1 << 63)) as *mut BlockNode<T>
@@ -516,15 +533,32 @@ impl<T> Steal<T> {
     /// Steals block of tasks from self and place them into `dst`.
     #[inline]
     pub fn steal_into(&self, dst: &mut Local<T>) -> Option<T> {
-        if std::ptr::eq(&self.0, &dst.0) {
-            return None;
+        self.steal_into_counted(dst).0
+    }
+
+    /// Steals block of tasks from self and place them into `dst`,
+    /// also return the number of stolen tasks.
+    #[inline]
+    pub fn steal_into_counted(&self, dst: &mut Local<T>) -> (Option<T>, usize) {
+        self.steal_batch_into(dst, BLOCK_SIZE)
+    }
+
+    /// Steals at most `max` tasks from self and place them into `dst`,
+    /// also return the number of stolen tasks.
+    ///
+    /// No more than one block of tasks is stolen at a time.
+    #[inline]
+    pub fn steal_batch_into(&self, dst: &mut Local<T>, max: usize) -> (Option<T>, usize) {
+        if std::ptr::eq(&self.0, &dst.0) || max == 0 {
+            return (None, 0);
         }
-        let mut v = self.0.bulk_pop();
+        let mut v = self.0.bulk_pop_max(max);
+        let n = v.len();
         let ret = v.pop();
         for t in v {
             dst.push_back(t);
         }
-        ret
+        (ret, n)
     }
 }
 
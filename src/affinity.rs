@@ -0,0 +1,65 @@
+//! worker thread cpu affinity settings
+//!
+
+use core_affinity::CoreId;
+
+/// How the worker threads are pinned to the cpu cores
+///
+/// The workers are assigned to the selected cores in a round-robin way.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum CpuAffinity {
+    /// don't pin the worker threads
+    Disabled,
+    /// pin the workers to all the cores that the scheduler starting thread
+    /// can run on, this is the default
+    #[default]
+    All,
+    /// pin the workers to the given core ids
+    Cores(Vec<usize>),
+    /// pin the workers to the cores in the current process affinity mask
+    ProcessMask,
+}
+
+impl CpuAffinity {
+    /// get the cores that the workers are pinned to, `None` means no pinning
+    pub(crate) fn core_ids(&self) -> Option<Vec<CoreId>> {
+        let ids = match self {
+            CpuAffinity::Disabled => return None,
+            CpuAffinity::All => core_affinity::get_core_ids(),
+            CpuAffinity::Cores(ids) => Some(ids.iter().map(|&id| CoreId { id }).collect()),
+            CpuAffinity::ProcessMask => process_core_ids(),
+        };
+        match ids {
+            Some(ids) if !ids.is_empty() => Some(ids),
+            _ => {
+                warn!(
+                    "no cpu cores available for {:?}, workers are not pinned",
+                    self
+                );
+                None
+            }
+        }
+    }
+}
+
+// the cores of the process (main thread) affinity mask
+#[cfg(target_os = "linux")]
+fn process_core_ids() -> Option<Vec<CoreId>> {
+    use std::mem;
+
+    let mut set: libc::cpu_set_t = unsafe { mem::zeroed() };
+    let size = mem::size_of::<libc::cpu_set_t>();
+    if unsafe { libc::sched_getaffinity(libc::getpid(), size, &mut set) } != 0 {
+        return None;
+    }
+    let ids = (0..libc::CPU_SETSIZE as usize)
+        .filter(|&id| unsafe { libc::CPU_ISSET(id, &set) })
+        .map(|id| CoreId { id })
+        .collect();
+    Some(ids)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn process_core_ids() -> Option<Vec<CoreId>> {
+    core_affinity::get_core_ids()
+}
@@ -0,0 +1,223 @@
+//! thread pool for running blocking functions out of the worker threads
+//!
+//! The pool grows on demand up to the max thread number, and an idle thread
+//! exits after waiting for new tasks longer than the idle timeout.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::panic;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::scheduler::{get_scheduler, Scheduler};
+use crate::sync::{AtomicOption, SyncFlag};
+use parking_lot::{Condvar, Mutex};
+
+type Task = Box<dyn FnOnce() + Send>;
+
+struct State {
+    queue: VecDeque<Task>,
+    // number of the live threads
+    threads: usize,
+    // number of the threads that are waiting for tasks
+    idle: usize,
+    // number of the notified idle threads that not yet woke up
+    wakeups: usize,
+    shutdown: bool,
+}
+
+pub(crate) struct BlockingPool {
+    state: Mutex<State>,
+    cond: Condvar,
+    max_threads: usize,
+    idle_timeout: Duration,
+}
+
+impl BlockingPool {
+    pub fn new(max_threads: usize, idle_timeout: Duration) -> Self {
+        BlockingPool {
+            state: Mutex::new(State {
+                queue: VecDeque::new(),
+                threads: 0,
+                idle: 0,
+                wakeups: 0,
+                shutdown: false,
+            }),
+            cond: Condvar::new(),
+            max_threads,
+            idle_timeout,
+        }
+    }
+
+    /// run the task on one of the pool threads
+    ///
+    /// the threads are spawned by the scheduler so that they run the thread
+    /// hooks and use the thread stack size of the runtime
+    pub fn spawn(&'static self, sched: &Scheduler, task: Task) -> io::Result<()> {
+        let mut state = self.state.lock();
+        if state.shutdown {
+            return Err(io::Error::other("the blocking pool is shut down"));
+        }
+        state.queue.push_back(task);
+        if state.idle > 0 {
+            state.idle -= 1;
+            state.wakeups += 1;
+            self.cond.notify_one();
+        } else if state.threads < self.max_threads {
+            match sched.spawn_thread("may-blocking".to_owned(), move || self.run()) {
+                Ok(_) => state.threads += 1,
+                // the busy threads would pick up the task later
+                Err(e) if state.threads > 0 => warn!("failed to spawn blocking thread: {}", e),
+                Err(e) => {
+                    state.queue.pop_back();
+                    return Err(e);
+                }
+            }
+        }
+        // otherwise the task would be picked up by a busy thread later
+        Ok(())
+    }
+
+    // the pool thread main loop
+    fn run(&self) {
+        let mut state = self.state.lock();
+        'outer: loop {
+            while let Some(task) = state.queue.pop_front() {
+                drop(state);
+                task();
+                state = self.state.lock();
+            }
+
+            if state.shutdown {
+                break;
+            }
+
+            state.idle += 1;
+            loop {
+                let ret = self.cond.wait_for(&mut state, self.idle_timeout);
+                if state.wakeups > 0 {
+                    state.wakeups -= 1;
+                    continue 'outer;
+                }
+                if state.shutdown || ret.timed_out() {
+                    state.idle -= 1;
+                    break 'outer;
+                }
+                // spurious wakeup
+            }
+        }
+        state.threads -= 1;
+    }
+
+    /// number of the live pool threads
+    pub fn threads(&self) -> usize {
+        self.state.lock().threads
+    }
+
+    /// return true if any task is queued or running
+    #[cfg(feature = "sim")]
+    pub fn is_busy(&self) -> bool {
+        let state = self.state.lock();
+        !state.queue.is_empty() || state.threads > state.idle + state.wakeups
+    }
+
+    /// reject the new tasks and let all the idle threads exit
+    ///
+    /// the queued tasks are still run by the busy threads
+    pub fn shutdown(&self) {
+        self.state.lock().shutdown = true;
+        self.cond.notify_all();
+    }
+}
+
+struct Packet<T> {
+    result: AtomicOption<thread::Result<T>>,
+    done: SyncFlag,
+}
+
+/// A handle to the result of a blocking function
+///
+/// created by [`spawn_blocking`](fn.spawn_blocking.html)
+pub struct BlockingJoinHandle<T> {
+    packet: Arc<Packet<T>>,
+}
+
+impl<T> BlockingJoinHandle<T> {
+    /// return true if the blocking function is finished
+    pub fn is_done(&self) -> bool {
+        self.packet.done.is_fired()
+    }
+
+    /// block until the blocking function is done
+    pub fn wait(&self) {
+        self.packet.done.wait();
+    }
+
+    /// Join the blocking function, returning the result it produced.
+    ///
+    /// The calling coroutine is parked until the result is ready, `Err` is
+    /// returned if the function panicked.
+    pub fn join(self) -> thread::Result<T> {
+        self.packet.done.wait();
+        self.packet
+            .result
+            .take()
+            .expect("blocking result is missing")
+    }
+}
+
+impl<T> fmt::Debug for BlockingJoinHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("BlockingJoinHandle { .. }")
+    }
+}
+
+/// Runs a blocking function on a dedicated thread pool
+///
+/// Blocking calls like file io, blocking C libraries or DNS lookup would stall
+/// the worker threads if called in coroutines directly. Offload them to the
+/// pool and park the coroutine on the returned handle instead.
+///
+/// The pool size and idle timeout can be set by
+/// [`Config::set_blocking_threads`] and [`Config::set_blocking_idle_timeout`]
+///
+/// An error is returned if the pool thread can't be spawned or the runtime
+/// is shut down.
+///
+/// # Examples
+///
+/// ```
+/// use may::coroutine;
+///
+/// let h = may::go!(|| {
+///     let h = coroutine::spawn_blocking(|| std::fs::metadata(".").is_ok()).unwrap();
+///     h.join().unwrap()
+/// });
+/// assert!(h.join().unwrap());
+/// ```
+///
+/// [`Config::set_blocking_threads`]: ../struct.Config.html#method.set_blocking_threads
+/// [`Config::set_blocking_idle_timeout`]: ../struct.Config.html#method.set_blocking_idle_timeout
+pub fn spawn_blocking<F, T>(f: F) -> io::Result<BlockingJoinHandle<T>>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let packet = Arc::new(Packet {
+        result: AtomicOption::none(),
+        done: SyncFlag::new(),
+    });
+    let their_packet = packet.clone();
+    let sched = get_scheduler();
+    sched.blocking_pool.spawn(
+        sched,
+        Box::new(move || {
+            let ret = panic::catch_unwind(panic::AssertUnwindSafe(f));
+            their_packet.result.store(ret);
+            their_packet.done.fire();
+        }),
+    )?;
+    Ok(BlockingJoinHandle { packet })
+}
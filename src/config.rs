@@ -1,34 +1,298 @@
 //! `May` Configuration interface
 //!
 
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Once};
+use std::time::Duration;
+
+use crate::affinity::CpuAffinity;
+use crate::io::IdlePolicy;
+use crate::scheduler::is_default_started;
+use crate::watchdog::{WatchdogCallback, WatchdogReport};
+use parking_lot::Mutex;
 
 // default stack size, in usize
 // windows has a minimal size as 0x4a8!!!!
 const DEFAULT_STACK_SIZE: usize = 0x1000;
 const DEFAULT_POOL_CAPACITY: usize = 1000;
+const DEFAULT_BLOCKING_THREADS: usize = 512;
+const DEFAULT_BLOCKING_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+// the max number of coroutines stolen at a time, the same as the queue block size
+const DEFAULT_STEAL_BATCH: usize = 32;
 
 static WORKERS: AtomicUsize = AtomicUsize::new(0);
+// 0 means the same as the workers
+static MAX_WORKERS: AtomicUsize = AtomicUsize::new(0);
 static STACK_SIZE: AtomicUsize = AtomicUsize::new(DEFAULT_STACK_SIZE);
 static POOL_CAPACITY: AtomicUsize = AtomicUsize::new(DEFAULT_POOL_CAPACITY);
+static BLOCKING_THREADS: AtomicUsize = AtomicUsize::new(DEFAULT_BLOCKING_THREADS);
+// blocking thread idle timeout in ms
+static BLOCKING_IDLE_TIMEOUT: AtomicU64 =
+    AtomicU64::new(DEFAULT_BLOCKING_IDLE_TIMEOUT.as_millis() as u64);
+static CPU_AFFINITY: Mutex<CpuAffinity> = Mutex::new(CpuAffinity::All);
+// watchdog threshold in ns, 0 means disabled
+static WATCHDOG_THRESHOLD: AtomicU64 = AtomicU64::new(0);
+static WATCHDOG_CALLBACK: Mutex<Option<WatchdogCallback>> = Mutex::new(None);
+// os stack size of the runtime threads, 0 means the std default
+static THREAD_STACK_SIZE: AtomicUsize = AtomicUsize::new(0);
+static STEAL_BATCH: AtomicUsize = AtomicUsize::new(DEFAULT_STEAL_BATCH);
+static LIFO_SLOT: AtomicBool = AtomicBool::new(true);
+static IDLE_POLICY: Mutex<IdlePolicy> = Mutex::new(IdlePolicy::Default);
+static THREAD_START: Mutex<Option<ThreadHook>> = Mutex::new(None);
+static THREAD_STOP: Mutex<Option<ThreadHook>> = Mutex::new(None);
+
+/// callback that runs on the runtime threads
+#[derive(Clone)]
+pub(crate) struct ThreadHook(Arc<dyn Fn() + Send + Sync>);
+
+impl ThreadHook {
+    pub fn new<F>(f: F) -> Self
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        ThreadHook(Arc::new(f))
+    }
+
+    #[inline]
+    pub fn call(&self) {
+        (self.0)()
+    }
+}
+
+impl fmt::Debug for ThreadHook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("ThreadHook")
+    }
+}
 
 /// `May` Configuration type
+///
+/// The initial settings are loaded from the environment variables below if
+/// they are present, explicit setter calls always take precedence.
+///
+/// | variable | setting |
+/// |---|---|
+/// | `MAY_WORKERS` | [`set_workers`](#method.set_workers) |
+/// | `MAY_MAX_WORKERS` | [`set_max_workers`](#method.set_max_workers) |
+/// | `MAY_STACK_SIZE` | [`set_stack_size`](#method.set_stack_size) |
+/// | `MAY_POOL_CAPACITY` | [`set_pool_capacity`](#method.set_pool_capacity) |
+/// | `MAY_BLOCKING_THREADS` | [`set_blocking_threads`](#method.set_blocking_threads) |
+/// | `MAY_BLOCKING_IDLE_TIMEOUT_MS` | [`set_blocking_idle_timeout`](#method.set_blocking_idle_timeout) |
+/// | `MAY_CPU_AFFINITY` | [`set_cpu_affinity`](#method.set_cpu_affinity), `disabled`, `all`, `process` or a core id list like `0,2,4` |
+/// | `MAY_WATCHDOG_MS` | [`set_watchdog`](#method.set_watchdog), 0 to disable |
+/// | `MAY_THREAD_STACK_SIZE` | [`set_thread_stack_size`](#method.set_thread_stack_size) |
+/// | `MAY_STEAL_BATCH` | [`set_steal_batch`](#method.set_steal_batch) |
+/// | `MAY_IDLE_POLICY` | [`set_idle_policy`](#method.set_idle_policy), `default`, `block` or `spin:<us>` like `spin:50` |
+/// | `MAY_LIFO_SLOT` | [`set_lifo_slot`](#method.set_lifo_slot), `1`/`true`/`on` or `0`/`false`/`off` |
+///
+/// the sizes can also be written in hex like `0x2000`
+///
+/// The settings can also be put in a file that `MAY_CONFIG` points to, one
+/// `NAME = value` line per setting with the same names as the variables
+/// above, and `#` starts a comment line. The environment variables take
+/// precedence over the file.
+///
+/// ```text
+/// # may.conf
+/// MAY_WORKERS = 4
+/// MAY_STACK_SIZE = 0x2000
+/// ```
 pub struct Config;
 
 /// get the may configuration instance
 pub fn config() -> Config {
+    static ENV: Once = Once::new();
+    ENV.call_once(load_env);
     Config
 }
 
+// the max worker number for the setting, 0 for the default that is twice
+// the workers, and never less than the workers
+pub(crate) fn resolve_max_workers(max_workers: usize, workers: usize) -> usize {
+    match max_workers {
+        0 => workers * 2,
+        n => std::cmp::max(n, workers),
+    }
+}
+
+// parse an integer that could be written in hex
+fn parse_usize(s: &str) -> Option<usize> {
+    let s = s.trim();
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => usize::from_str(s).ok(),
+    }
+}
+
+fn parse_bool(s: &str) -> Option<bool> {
+    match s.trim() {
+        "1" | "true" | "on" => Some(true),
+        "0" | "false" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+fn parse_idle_policy(s: &str) -> Option<IdlePolicy> {
+    match s.trim() {
+        "default" => Some(IdlePolicy::Default),
+        "block" => Some(IdlePolicy::Block),
+        s => {
+            let us = parse_usize(s.strip_prefix("spin:")?)?;
+            Some(IdlePolicy::Spin(Duration::from_micros(us as u64)))
+        }
+    }
+}
+
+fn parse_affinity(s: &str) -> Option<CpuAffinity> {
+    match s.trim() {
+        "disabled" | "off" => Some(CpuAffinity::Disabled),
+        "all" => Some(CpuAffinity::All),
+        "process" => Some(CpuAffinity::ProcessMask),
+        list => list
+            .split(',')
+            .map(parse_usize)
+            .collect::<Option<Vec<_>>>()
+            .map(CpuAffinity::Cores),
+    }
+}
+
+// parse the `NAME = value` lines of the config file
+fn parse_file(content: &str) -> io::Result<HashMap<String, String>> {
+    let mut map = HashMap::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.split_once('=') {
+            Some((name, value)) => {
+                map.insert(name.trim().to_owned(), value.trim().to_owned());
+            }
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid config line {}: {:?}", i + 1, line),
+                ))
+            }
+        }
+    }
+    Ok(map)
+}
+
+fn read_file(path: &Path) -> io::Result<HashMap<String, String>> {
+    parse_file(&fs::read_to_string(path)?)
+}
+
+// read the setting from the source, the invalid value is ignored with a
+// warning
+fn setting<T>(
+    get: &impl Fn(&str) -> Option<String>,
+    name: &str,
+    parse: impl Fn(&str) -> Option<T>,
+) -> Option<T> {
+    let value = get(name)?;
+    let ret = parse(&value);
+    if ret.is_none() {
+        warn!("invalid value {:?} of {}, ignored", value, name);
+    }
+    ret
+}
+
+// load the settings from the source
+fn load(get: impl Fn(&str) -> Option<String>) {
+    if let Some(v) = setting(&get, "MAY_WORKERS", parse_usize) {
+        WORKERS.store(v, Ordering::Relaxed);
+    }
+    if let Some(v) = setting(&get, "MAY_MAX_WORKERS", parse_usize) {
+        MAX_WORKERS.store(v, Ordering::Relaxed);
+    }
+    if let Some(v) = setting(&get, "MAY_STACK_SIZE", parse_usize).filter(|&v| v != 0) {
+        STACK_SIZE.store(v, Ordering::Release);
+    }
+    if let Some(v) = setting(&get, "MAY_POOL_CAPACITY", parse_usize) {
+        POOL_CAPACITY.store(v, Ordering::Release);
+    }
+    if let Some(v) = setting(&get, "MAY_BLOCKING_THREADS", parse_usize).filter(|&v| v != 0) {
+        BLOCKING_THREADS.store(v, Ordering::Release);
+    }
+    if let Some(v) = setting(&get, "MAY_BLOCKING_IDLE_TIMEOUT_MS", parse_usize) {
+        BLOCKING_IDLE_TIMEOUT.store(v as u64, Ordering::Release);
+    }
+    if let Some(v) = setting(&get, "MAY_CPU_AFFINITY", parse_affinity) {
+        *CPU_AFFINITY.lock() = v;
+    }
+    if let Some(v) = setting(&get, "MAY_WATCHDOG_MS", parse_usize) {
+        let ns = Duration::from_millis(v as u64).as_nanos() as u64;
+        WATCHDOG_THRESHOLD.store(ns, Ordering::Release);
+    }
+    if let Some(v) = setting(&get, "MAY_THREAD_STACK_SIZE", parse_usize) {
+        THREAD_STACK_SIZE.store(v, Ordering::Release);
+    }
+    if let Some(v) = setting(&get, "MAY_STEAL_BATCH", parse_usize).filter(|&v| v != 0) {
+        STEAL_BATCH.store(v, Ordering::Release);
+    }
+    if let Some(v) = setting(&get, "MAY_LIFO_SLOT", parse_bool) {
+        LIFO_SLOT.store(v, Ordering::Release);
+    }
+    if let Some(v) = setting(&get, "MAY_IDLE_POLICY", parse_idle_policy) {
+        *IDLE_POLICY.lock() = v;
+    }
+}
+
+// load the initial settings from the `MAY_CONFIG` file and then the env
+// variables, so that the env variables take precedence
+fn load_env() {
+    if let Some(path) = env::var_os("MAY_CONFIG") {
+        match read_file(path.as_ref()) {
+            Ok(map) => load(|name| map.get(name).cloned()),
+            Err(e) => warn!("failed to load the config file {:?}: {}", path, e),
+        }
+    }
+    load(|name| env::var(name).ok());
+}
+
+// the settings are only read when the default scheduler is created
+fn check_started(setter: &str) {
+    if is_default_started() {
+        warn!(
+            "`Config::{}` is called after the default scheduler has started, \
+             it has no effect on the default runtime!",
+            setter
+        );
+    }
+}
+
 /// the config should be called at the program beginning
 ///
 /// successive call would not tack effect for that the scheduler
-/// is already started
+/// is already started, a warning is logged for such calls
 impl Config {
+    /// load the settings from a config file
+    ///
+    /// the file has the same format as the `MAY_CONFIG` file, its settings
+    /// apply like the setter calls, so they override the environment
+    /// variables and the later setter calls override them
+    pub fn load_file<P: AsRef<Path>>(&self, path: P) -> io::Result<&Self> {
+        let path = path.as_ref();
+        let map = read_file(path)?;
+        check_started("load_file");
+        info!("load config file {:?}", path);
+        load(|name| map.get(name).cloned());
+        Ok(self)
+    }
+
     /// set the worker thread number
     ///
     /// the minimum worker thread is 1, if you pass 0 to it, will use internal default
     pub fn set_workers(&self, workers: usize) -> &Self {
+        check_started("set_workers");
         info!("set workers={:?}", workers);
         WORKERS.store(workers, Ordering::Relaxed);
         self
@@ -46,6 +310,33 @@ impl Config {
         }
     }
 
+    /// set the max worker thread number
+    ///
+    /// all the worker threads up to this limit are started with the default
+    /// scheduler, the workers beyond [`set_workers`](#method.set_workers)
+    /// start retired and can be reactivated at runtime by
+    /// [`set_active_workers`](fn.set_active_workers.html), which can't go
+    /// beyond this limit. The default is twice the workers. If you pass 0 to
+    /// it, the default is used, a value less than the workers is the same as
+    /// the workers, so that no worker can be added later
+    pub fn set_max_workers(&self, workers: usize) -> &Self {
+        check_started("set_max_workers");
+        info!("set max workers={:?}", workers);
+        MAX_WORKERS.store(workers, Ordering::Relaxed);
+        self
+    }
+
+    /// get the max worker thread number
+    pub fn get_max_workers(&self) -> usize {
+        resolve_max_workers(self.max_workers_setting(), self.get_workers())
+    }
+
+    // the max worker number as it's set, 0 for the default, so that the
+    // default follows the workers of a runtime builder
+    pub(crate) fn max_workers_setting(&self) -> usize {
+        MAX_WORKERS.load(Ordering::Relaxed)
+    }
+
     /// set the io worker thread number
     #[deprecated(since = "0.3.13", note = "use `set_workers` only")]
     pub fn set_io_workers(&self, _workers: usize) -> &Self {
@@ -56,6 +347,7 @@ impl Config {
     ///
     /// if you pass 0 to it, will use internal default
     pub fn set_pool_capacity(&self, capacity: usize) -> &Self {
+        check_started("set_pool_capacity");
         info!("set pool capacity={:?}", capacity);
         POOL_CAPACITY.store(capacity, Ordering::Release);
         self
@@ -75,6 +367,7 @@ impl Config {
     ///
     /// if you pass 0 to it, will use internal default
     pub fn set_stack_size(&self, size: usize) -> &Self {
+        check_started("set_stack_size");
         info!("set stack size={:?}", size);
         STACK_SIZE.store(size, Ordering::Release);
         self
@@ -84,4 +377,212 @@ impl Config {
     pub fn get_stack_size(&self) -> usize {
         STACK_SIZE.load(Ordering::Acquire)
     }
+
+    /// set the max thread number of the `spawn_blocking` pool
+    ///
+    /// if you pass 0 to it, will use internal default
+    pub fn set_blocking_threads(&self, threads: usize) -> &Self {
+        check_started("set_blocking_threads");
+        info!("set blocking threads={:?}", threads);
+        let threads = if threads != 0 {
+            threads
+        } else {
+            DEFAULT_BLOCKING_THREADS
+        };
+        BLOCKING_THREADS.store(threads, Ordering::Release);
+        self
+    }
+
+    /// get the max thread number of the `spawn_blocking` pool
+    pub fn get_blocking_threads(&self) -> usize {
+        BLOCKING_THREADS.load(Ordering::Acquire)
+    }
+
+    /// set how long an idle thread of the `spawn_blocking` pool is kept alive
+    pub fn set_blocking_idle_timeout(&self, timeout: Duration) -> &Self {
+        check_started("set_blocking_idle_timeout");
+        info!("set blocking idle timeout={:?}", timeout);
+        BLOCKING_IDLE_TIMEOUT.store(timeout.as_millis() as u64, Ordering::Release);
+        self
+    }
+
+    /// get the idle timeout of the `spawn_blocking` pool threads
+    pub fn get_blocking_idle_timeout(&self) -> Duration {
+        Duration::from_millis(BLOCKING_IDLE_TIMEOUT.load(Ordering::Acquire))
+    }
+
+    /// set how the worker threads are pinned to the cpu cores
+    ///
+    /// the default is pinning the workers to all the available cores in a
+    /// round-robin way, see [`CpuAffinity`](enum.CpuAffinity.html)
+    pub fn set_cpu_affinity(&self, affinity: CpuAffinity) -> &Self {
+        check_started("set_cpu_affinity");
+        info!("set cpu affinity={:?}", affinity);
+        *CPU_AFFINITY.lock() = affinity;
+        self
+    }
+
+    /// get the worker threads cpu affinity setting
+    pub fn get_cpu_affinity(&self) -> CpuAffinity {
+        CPU_AFFINITY.lock().clone()
+    }
+
+    /// enable the watchdog that reports the coroutines which run longer than
+    /// `threshold` without switching out, i.e. blocking the worker thread
+    ///
+    /// each report is logged as a warning, pass `None` to disable it
+    pub fn set_watchdog(&self, threshold: Option<Duration>) -> &Self {
+        check_started("set_watchdog");
+        info!("set watchdog threshold={:?}", threshold);
+        let ns = threshold.map_or(0, |t| (t.as_nanos() as u64).max(1));
+        WATCHDOG_THRESHOLD.store(ns, Ordering::Release);
+        self
+    }
+
+    /// get the watchdog threshold, `None` means the watchdog is disabled
+    pub fn get_watchdog(&self) -> Option<Duration> {
+        match WATCHDOG_THRESHOLD.load(Ordering::Acquire) {
+            0 => None,
+            ns => Some(Duration::from_nanos(ns)),
+        }
+    }
+
+    /// set the callback that is called by the watchdog thread for each report
+    pub fn set_watchdog_callback<F>(&self, f: F) -> &Self
+    where
+        F: Fn(&WatchdogReport) + Send + Sync + 'static,
+    {
+        check_started("set_watchdog_callback");
+        *WATCHDOG_CALLBACK.lock() = Some(WatchdogCallback::new(f));
+        self
+    }
+
+    pub(crate) fn get_watchdog_callback(&self) -> Option<WatchdogCallback> {
+        WATCHDOG_CALLBACK.lock().clone()
+    }
+
+    /// set a callback that runs on each worker and watchdog thread of the
+    /// runtime when the thread starts, before it runs any coroutine
+    pub fn on_thread_start<F>(&self, f: F) -> &Self
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        check_started("on_thread_start");
+        *THREAD_START.lock() = Some(ThreadHook::new(f));
+        self
+    }
+
+    /// set a callback that runs on each worker and watchdog thread of the
+    /// runtime right before the thread exits
+    pub fn on_thread_stop<F>(&self, f: F) -> &Self
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        check_started("on_thread_stop");
+        *THREAD_STOP.lock() = Some(ThreadHook::new(f));
+        self
+    }
+
+    pub(crate) fn get_thread_hooks(&self) -> (Option<ThreadHook>, Option<ThreadHook>) {
+        (THREAD_START.lock().clone(), THREAD_STOP.lock().clone())
+    }
+
+    /// set the os stack size of the worker, timer and watchdog threads
+    ///
+    /// if you pass 0 to it, will use the std default
+    pub fn set_thread_stack_size(&self, size: usize) -> &Self {
+        check_started("set_thread_stack_size");
+        info!("set thread stack size={:?}", size);
+        THREAD_STACK_SIZE.store(size, Ordering::Release);
+        self
+    }
+
+    /// get the os stack size of the runtime threads, 0 means the std default
+    pub fn get_thread_stack_size(&self) -> usize {
+        THREAD_STACK_SIZE.load(Ordering::Acquire)
+    }
+
+    /// set the max number of coroutines that an idle worker steals from
+    /// another one at a time
+    ///
+    /// the batch is capped to the queue block size 32. if you pass 0 to it,
+    /// will use internal default
+    pub fn set_steal_batch(&self, batch: usize) -> &Self {
+        check_started("set_steal_batch");
+        info!("set steal batch={:?}", batch);
+        STEAL_BATCH.store(batch, Ordering::Release);
+        self
+    }
+
+    /// get the max number of coroutines stolen at a time
+    pub fn get_steal_batch(&self) -> usize {
+        let batch = STEAL_BATCH.load(Ordering::Acquire);
+        if batch != 0 {
+            batch
+        } else {
+            DEFAULT_STEAL_BATCH
+        }
+    }
+
+    /// enable or disable the worker LIFO slot, it's enabled by default
+    ///
+    /// with the slot a coroutine that is woken up by another one on the same
+    /// worker, e.g. the receiver of a channel `send`, runs right after the
+    /// current one instead of going to the back of the local queue. This
+    /// improves the cache locality of the message passing patterns.
+    pub fn set_lifo_slot(&self, enable: bool) -> &Self {
+        check_started("set_lifo_slot");
+        info!("set lifo slot={:?}", enable);
+        LIFO_SLOT.store(enable, Ordering::Release);
+        self
+    }
+
+    /// get if the worker LIFO slot is enabled
+    pub fn get_lifo_slot(&self) -> bool {
+        LIFO_SLOT.load(Ordering::Acquire)
+    }
+
+    /// set what the idle workers do when there are no ready coroutines
+    ///
+    /// see [`IdlePolicy`](enum.IdlePolicy.html) for the trade-offs, the wakeups
+    /// of each worker can be checked in the [`metrics`](fn.metrics.html)
+    pub fn set_idle_policy(&self, policy: IdlePolicy) -> &Self {
+        check_started("set_idle_policy");
+        info!("set idle policy={:?}", policy);
+        *IDLE_POLICY.lock() = policy;
+        self
+    }
+
+    /// get the idle worker policy
+    pub fn get_idle_policy(&self) -> IdlePolicy {
+        *IDLE_POLICY.lock()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_env_value() {
+        assert_eq!(parse_usize("16"), Some(16));
+        assert_eq!(parse_usize(" 0x2000"), Some(0x2000));
+        assert_eq!(parse_usize("abc"), None);
+        assert_eq!(parse_bool(" on"), Some(true));
+        assert_eq!(parse_bool("0"), Some(false));
+        assert_eq!(parse_bool("yes"), None);
+        assert_eq!(parse_idle_policy("block"), Some(IdlePolicy::Block));
+        assert_eq!(
+            parse_idle_policy("spin:50"),
+            Some(IdlePolicy::Spin(Duration::from_micros(50)))
+        );
+        assert_eq!(parse_idle_policy("spin"), None);
+        assert_eq!(parse_affinity("off"), Some(CpuAffinity::Disabled));
+        assert_eq!(parse_affinity("process"), Some(CpuAffinity::ProcessMask));
+        assert_eq!(
+            parse_affinity("0, 2,4"),
+            Some(CpuAffinity::Cores(vec![0, 2, 4]))
+        );
+        assert_eq!(parse_affinity("0,x"), None);
+    }
 }
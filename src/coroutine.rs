@@ -1,10 +1,15 @@
 // re-export coroutine interface
+pub use crate::blocking_pool::{spawn_blocking, BlockingJoinHandle};
 pub use crate::cancel::trigger_cancel_panic;
 pub use crate::coroutine_impl::{
-    current, is_coroutine, park, park_timeout, spawn, Builder, Coroutine,
+    clear_deadline, current, deadline, is_coroutine, migrate_to, park, park_timeout, set_deadline,
+    spawn, Builder, Coroutine, CoroutineId, Priority,
 };
+#[cfg(feature = "task_dump")]
+pub use crate::dump::{dump, CoroutineInfo, CoroutineState, Dump};
 pub use crate::join::JoinHandle;
 pub use crate::park::ParkError;
-pub use crate::scoped::scope;
-pub use crate::sleep::sleep;
+pub use crate::scoped::{cancellation_token, scope};
+pub use crate::sleep::{sleep, sleep_until};
+pub use crate::timeout::{timeout, Elapsed};
 pub use crate::yield_now::yield_now;
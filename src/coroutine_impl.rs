@@ -1,15 +1,19 @@
 use std::fmt;
 use std::io;
+use std::num::NonZeroU64;
+use std::panic::Location;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use crate::cancel::Cancel;
-use crate::config::config;
+#[cfg(feature = "task_dump")]
+use crate::dump::{BlockState, CoroutineState, StateCell};
 use crate::join::{make_join_handle, Join, JoinHandle};
 use crate::local::get_co_local_data;
 use crate::local::CoroutineLocal;
 use crate::park::Park;
-use crate::scheduler::get_scheduler;
+use crate::scheduler::{get_scheduler, set_current_sched, Scheduler};
 use crate::sync::AtomicOption;
 use generator::{Generator, Gn};
 
@@ -28,8 +32,14 @@ pub struct EventSubscriber {
 unsafe impl Send for EventSubscriber {}
 
 impl EventSubscriber {
-    pub fn new(r: *mut dyn EventSource) -> Self {
-        EventSubscriber { resource: r }
+    pub fn new<'a>(r: *mut (dyn EventSource + 'a)) -> Self {
+        // SAFETY: only the trait object lifetime is erased, the pointer and
+        // vtable are unchanged. The resource lives on the blocked coroutine's
+        // stack and is only dereferenced before the coroutine is resumed
+        let resource = unsafe {
+            std::mem::transmute::<*mut (dyn EventSource + 'a), *mut (dyn EventSource + 'static)>(r)
+        };
+        EventSubscriber { resource }
     }
 
     pub fn subscribe(self, c: CoroutineImpl) {
@@ -41,6 +51,11 @@ impl EventSubscriber {
 pub trait EventSource {
     /// kernel handler of the event
     fn subscribe(&mut self, _c: CoroutineImpl);
+    /// the state of the coroutine that blocked on the event
+    #[cfg(feature = "task_dump")]
+    fn block_state(&self) -> BlockState {
+        BlockState::Suspended
+    }
     /// after yield back process
     fn yield_back(&self, cancel: &'static Cancel) {
         // after return back we should re-check the panic and clear it
@@ -60,21 +75,26 @@ impl Done {
         // just consume the coroutine
         // destroy the local storage
         let local = unsafe { Box::from_raw(get_co_local(&co)) };
+        let id = local.get_co().id();
         let name = local.get_co().name();
+        let sched = local.get_sched();
+        sched.remove_coroutine(local.get_co());
 
         // recycle the coroutine
         let (size, used) = co.stack_usage();
         if used == size {
-            eprintln!("stack overflow detected, size={size}");
+            eprintln!("stack overflow detected, coroutine id = {id}, name = {name:?}, size={size}");
             ::std::process::exit(1);
         }
         // show the actual used stack size in debug log
         if local.get_co().stack_size() & 1 == 1 {
-            println!("coroutine name = {name:?}, stack size = {size},  used size = {used}");
+            println!(
+                "coroutine id = {id}, name = {name:?}, stack size = {size},  used size = {used}"
+            );
         }
 
-        if size == config().get_stack_size() {
-            get_scheduler().pool.put(co);
+        if size == sched.stack_size() {
+            sched.pool.put(co);
         }
     }
 }
@@ -95,16 +115,96 @@ fn get_co_local(co: &CoroutineImpl) -> *mut CoroutineLocal {
     co.get_local_data() as *mut CoroutineLocal
 }
 
+// the wrapper that sends the coroutine closure to the generator, the spawn
+// functions check the `Send` bounds when needed
+struct AssertSend<F>(F);
+
+unsafe impl<F> Send for AssertSend<F> {}
+
+impl<F: FnOnce() -> R, R> AssertSend<F> {
+    // take the whole wrapper so that the closure captures it but not the field
+    fn call(self) -> R {
+        (self.0)()
+    }
+}
+
 /// /////////////////////////////////////////////////////////////////////////////
 /// Coroutine
 /// /////////////////////////////////////////////////////////////////////////////
 
+/// The scheduling priority of a coroutine
+///
+/// The ready high priority coroutines run before the normal ones, and the
+/// normal ones run before the low ones. The scheduler still picks the lower
+/// classes periodically so that they are not starved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum Priority {
+    /// latency sensitive coroutines
+    High,
+    /// the default priority
+    #[default]
+    Normal,
+    /// background coroutines
+    Low,
+}
+
+// the pinned worker id of a coroutine that is not pinned
+const NOT_PINNED: usize = usize::MAX;
+
+/// A unique identifier of a coroutine
+///
+/// The ids are assigned in the spawn order and never reused in the process,
+/// so they can tell the coroutines apart in the logs and be used as the map
+/// keys. It's printed as a plain number, e.g. in a panic message.
+///
+/// # Examples
+///
+/// ```
+/// use may::coroutine;
+///
+/// let h = may::go!(|| coroutine::current().id());
+/// let id = h.coroutine().id();
+/// assert_eq!(h.join().unwrap(), id);
+/// println!("coroutine {id} is done");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CoroutineId(NonZeroU64);
+
+impl CoroutineId {
+    // generate a new id
+    fn new() -> CoroutineId {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        CoroutineId(NonZeroU64::new(id).expect("coroutine id overflow"))
+    }
+
+    /// Gets the id as a number
+    pub fn as_u64(&self) -> NonZeroU64 {
+        self.0
+    }
+}
+
+impl fmt::Display for CoroutineId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
 /// The internal representation of a `Coroutine` handle
 struct Inner {
+    id: CoroutineId,
     name: Option<String>,
     stack_size: usize,
+    location: &'static Location<'static>,
+    priority: Priority,
+    // the worker that the coroutine always runs on
+    pinned: AtomicUsize,
+    // the slot in the live set of the scheduler
+    slot: AtomicUsize,
     park: Park,
     cancel: Cancel,
+    #[cfg(feature = "task_dump")]
+    state: StateCell,
 }
 
 #[derive(Clone)]
@@ -115,13 +215,27 @@ pub struct Coroutine {
 
 impl Coroutine {
     // Used only internally to construct a coroutine object without spawning
-    fn new(name: Option<String>, stack_size: usize) -> Coroutine {
+    fn new(
+        id: CoroutineId,
+        name: Option<String>,
+        stack_size: usize,
+        location: &'static Location<'static>,
+        priority: Priority,
+        pinned: Option<usize>,
+    ) -> Coroutine {
         Coroutine {
             inner: Arc::new(Inner {
+                id,
                 name,
                 stack_size,
+                location,
+                priority,
+                pinned: AtomicUsize::new(pinned.unwrap_or(NOT_PINNED)),
+                slot: AtomicUsize::new(0),
                 park: Park::new(),
                 cancel: Cancel::new(),
+                #[cfg(feature = "task_dump")]
+                state: StateCell::default(),
             }),
         }
     }
@@ -146,11 +260,70 @@ impl Coroutine {
         self.inner.cancel.cancel();
     }
 
+    /// Gets the unique id of the coroutine.
+    pub fn id(&self) -> CoroutineId {
+        self.inner.id
+    }
+
     /// Gets the coroutine name.
     pub fn name(&self) -> Option<&str> {
         self.inner.name.as_deref()
     }
 
+    /// Gets the scheduling priority of the coroutine.
+    pub fn priority(&self) -> Priority {
+        self.inner.priority
+    }
+
+    /// Gets the worker that the coroutine is pinned to, if any.
+    pub fn pinned_worker(&self) -> Option<usize> {
+        match self.inner.pinned.load(Ordering::Relaxed) {
+            NOT_PINNED => None,
+            id => Some(id),
+        }
+    }
+
+    // pin the coroutine to the worker, `None` to unpin it
+    fn set_pinned_worker(&self, worker: Option<usize>) {
+        let id = worker.unwrap_or(NOT_PINNED);
+        self.inner.pinned.store(id, Ordering::Relaxed);
+    }
+
+    /// Gets the location where the coroutine is spawned.
+    pub fn location(&self) -> &'static Location<'static> {
+        self.inner.location
+    }
+
+    // get the recorded state of the coroutine
+    #[cfg(feature = "task_dump")]
+    pub(crate) fn state(&self) -> CoroutineState {
+        self.inner.state.get()
+    }
+
+    // set the reason that overrides the parked state until cleared
+    #[cfg(feature = "task_dump")]
+    pub(crate) fn set_block_reason(&self, reason: Option<BlockState>) {
+        self.inner.state.set_reason(reason);
+    }
+
+    // the address of the inner data, it's unique among the live coroutines
+    #[cfg(feature = "task_dump")]
+    #[inline]
+    pub(crate) fn as_ptr(&self) -> usize {
+        Arc::as_ptr(&self.inner) as usize
+    }
+
+    // the slot in the live set of the scheduler
+    #[inline]
+    pub(crate) fn live_slot(&self) -> usize {
+        self.inner.slot.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    pub(crate) fn set_live_slot(&self, slot: usize) {
+        self.inner.slot.store(slot, Ordering::Relaxed);
+    }
+
     /// Get the internal cancel
     #[cfg(unix)]
     #[cfg(feature = "io_cancel")]
@@ -161,7 +334,10 @@ impl Coroutine {
 
 impl fmt::Debug for Coroutine {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        fmt::Debug::fmt(&self.name(), f)
+        f.debug_struct("Coroutine")
+            .field("id", &self.id())
+            .field("name", &self.name())
+            .finish()
     }
 }
 
@@ -218,6 +394,10 @@ pub struct Builder {
     stack_size: Option<usize>,
     // The associated id of the coroutine, would select a specific thread to run
     id: Option<usize>,
+    // The scheduling priority of the coroutine
+    priority: Priority,
+    // The worker that the coroutine is pinned to
+    pinned: Option<usize>,
 }
 
 impl Builder {
@@ -228,6 +408,8 @@ impl Builder {
             name: None,
             stack_size: None,
             id: None,
+            priority: Priority::Normal,
+            pinned: None,
         }
     }
 
@@ -250,19 +432,60 @@ impl Builder {
         self
     }
 
+    /// Sets the scheduling priority of the coroutine
+    ///
+    /// The priority is kept whenever the coroutine is rescheduled. A high or
+    /// low priority coroutine is not bound to the thread selected by `id`.
+    pub fn priority(mut self, priority: Priority) -> Builder {
+        self.priority = priority;
+        self
+    }
+
+    /// Pins the coroutine to the worker thread with the given id
+    ///
+    /// A pinned coroutine always runs on the worker, no matter which thread
+    /// wakes it up, and it's never stolen by the other workers. This is for
+    /// the code that holds worker affine state like per core caches. The
+    /// priority of a pinned coroutine is ignored. The worker id must be less
+    /// than the max workers of the runtime, a retired worker still runs its
+    /// pinned coroutines. The coroutine can move itself to another worker by
+    /// [`migrate_to`](fn.migrate_to.html).
+    pub fn pin_to_worker(mut self, id: usize) -> Builder {
+        self.pinned = Some(id);
+        self
+    }
+
     /// Spawns a new coroutine, and returns a join handle for it.
     /// The join handle can be used to block on
     /// termination of the child coroutine, including recovering its panics.
-    fn spawn_impl<F, T>(self, f: F) -> io::Result<(CoroutineImpl, JoinHandle<T>)>
+    ///
+    /// the `Send` bounds are checked by the callers, the coroutines of a
+    /// current thread scheduler never leave the thread
+    #[track_caller]
+    fn spawn_impl<F, T>(
+        mut self,
+        sched: &'static Scheduler,
+        f: F,
+    ) -> io::Result<(CoroutineImpl, JoinHandle<T>)>
     where
-        F: FnOnce() -> T + Send + 'static,
-        T: Send + 'static,
+        F: FnOnce() -> T + 'static,
+        T: 'static,
     {
         static DONE: Done = Done {};
 
-        let sched = get_scheduler();
+        if sched.is_closed() {
+            return Err(io::Error::other("the scheduler is shut down"));
+        }
+
+        if let Some(id) = self.pinned {
+            check_worker_id(sched, id)?;
+        } else if sched.is_current_thread() {
+            // all the coroutines run on the thread that drives the scheduler
+            self.pinned = Some(0);
+        }
+
         let name = self.name;
-        let stack_size = self.stack_size.unwrap_or_else(|| config().get_stack_size());
+        let stack_size = self.stack_size.unwrap_or_else(|| sched.stack_size());
 
         // create a join resource, shared by waited coroutine and *this* coroutine
         let panic = Arc::new(AtomicOption::none());
@@ -287,8 +510,10 @@ impl Builder {
             their_join.trigger();
             subscriber
         };
+        let closure = AssertSend(closure);
+        let closure = move || closure.call();
 
-        let mut co = if stack_size == config().get_stack_size() {
+        let mut co = if stack_size == sched.stack_size() {
             let mut co = sched.pool.get();
             co.init_code(closure);
             co
@@ -296,9 +521,17 @@ impl Builder {
             Gn::new_opt(stack_size, closure)
         };
 
-        let handle = Coroutine::new(name, stack_size);
+        let handle = Coroutine::new(
+            CoroutineId::new(),
+            name,
+            stack_size,
+            Location::caller(),
+            self.priority,
+            self.pinned,
+        );
+        sched.add_coroutine(&handle);
         // create the local storage
-        let local = CoroutineLocal::new(handle.clone(), join.clone());
+        let local = CoroutineLocal::new(handle.clone(), join.clone(), sched);
         // attache the local storage to the coroutine
         co.set_local_data(Box::into_raw(local) as *mut u8);
 
@@ -345,15 +578,29 @@ impl Builder {
     /// [`TLS`]: ./index.html#TLS
     /// [`go!`]: ../macro.go.html
     /// [`spawn`]: ./fn.spawn.html
+    #[track_caller]
     pub unsafe fn spawn<F, T>(self, f: F) -> io::Result<JoinHandle<T>>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        self.spawn_on(get_scheduler(), f)
+    }
+
+    /// Spawns a new coroutine on the given scheduler
+    #[track_caller]
+    pub(crate) unsafe fn spawn_on<F, T>(
+        self,
+        s: &'static Scheduler,
+        f: F,
+    ) -> io::Result<JoinHandle<T>>
     where
         F: FnOnce() -> T + Send + 'static,
         T: Send + 'static,
     {
         // we will still get optimizations in spawn_impl
         let id = self.id;
-        let (co, handle) = self.spawn_impl(f)?;
-        let s = get_scheduler();
+        let (co, handle) = self.spawn_impl(s, f)?;
 
         match id {
             None => s.schedule_global(co),
@@ -363,6 +610,24 @@ impl Builder {
         Ok(handle)
     }
 
+    /// Spawns a new coroutine on the current thread scheduler, which runs
+    /// all its coroutines on the thread that drives it
+    #[track_caller]
+    pub(crate) unsafe fn spawn_on_current_thread<F, T>(
+        self,
+        s: &'static Scheduler,
+        f: F,
+    ) -> io::Result<JoinHandle<T>>
+    where
+        F: FnOnce() -> T + 'static,
+        T: 'static,
+    {
+        debug_assert!(s.is_current_thread());
+        let (co, handle) = self.spawn_impl(s, f)?;
+        s.schedule_global(co);
+        Ok(handle)
+    }
+
     /// first run the coroutine in current thread, you should always use
     /// `spawn` instead of this API.
     ///
@@ -371,13 +636,14 @@ impl Builder {
     /// Cancel would drop all the resource of the coroutine.
     /// Normally this is safe but for some cases you should
     /// take care of the side effect
+    #[track_caller]
     pub unsafe fn spawn_local<F, T>(self, f: F) -> io::Result<JoinHandle<T>>
     where
         F: FnOnce() -> T + Send + 'static,
         T: Send + 'static,
     {
         // we will still get optimizations in spawn_impl
-        let (co, handle) = self.spawn_impl(f)?;
+        let (co, handle) = self.spawn_impl(get_scheduler(), f)?;
         // first run the coroutine in current thread
         run_coroutine(co);
         Ok(handle)
@@ -434,6 +700,7 @@ impl Builder {
 /// [`join`]: struct.JoinHandle.html#method.join
 /// [`Builder::spawn`]: struct.Builder.html#method.spawn
 /// [`Builder`]: struct.Builder.html
+#[track_caller]
 pub unsafe fn spawn<F, T>(f: F) -> JoinHandle<T>
 where
     F: FnOnce() -> T + Send + 'static,
@@ -471,12 +738,89 @@ pub(crate) fn current_cancel_data() -> &'static Cancel {
     }
 }
 
+#[inline]
+#[cfg(windows)]
+pub(crate) fn co_id(co: &CoroutineImpl) -> CoroutineId {
+    let local = unsafe { &*get_co_local(co) };
+    local.get_co().id()
+}
+
 #[inline]
 pub(crate) fn co_cancel_data(co: &CoroutineImpl) -> &'static Cancel {
     let local = unsafe { &*get_co_local(co) };
     &local.get_co().inner.cancel
 }
 
+// bound the timeout by the time left to the coroutine deadline
+fn deadline_timeout(local: &CoroutineLocal, dur: Option<Duration>) -> Option<Duration> {
+    // the deadline can't unwind the coroutine when the cancel is disabled or
+    // it's already unwinding
+    if local.get_co().inner.cancel.is_disabled() || std::thread::panicking() {
+        return dur;
+    }
+    let left = match local.get_deadline() {
+        Some(d) => d.saturating_duration_since(crate::time::now()),
+        None => return dur,
+    };
+    Some(dur.map_or(left, |dur| dur.min(left)))
+}
+
+/// get the timeout of a blocking call in the current coroutine, which is
+/// bounded by the coroutine deadline
+#[inline]
+pub(crate) fn current_timeout(dur: Option<Duration>) -> Option<Duration> {
+    match get_co_local_data() {
+        Some(local) => deadline_timeout(unsafe { &*local.as_ptr() }, dur),
+        None => dur,
+    }
+}
+
+/// get the timeout of a blocking call in the coroutine, which is bounded by
+/// the coroutine deadline
+#[inline]
+#[cfg(feature = "io_timeout")]
+pub(crate) fn co_timeout(co: &CoroutineImpl, dur: Option<Duration>) -> Option<Duration> {
+    deadline_timeout(unsafe { &*get_co_local(co) }, dur)
+}
+
+/// record the state of the coroutine for the task dump
+#[inline]
+#[cfg(feature = "task_dump")]
+pub(crate) fn co_set_state(co: &CoroutineImpl, state: BlockState) {
+    let local = unsafe { &*get_co_local(co) };
+    local.get_co().inner.state.set(state);
+}
+
+/// record the state of the current coroutine for the task dump
+#[inline]
+#[cfg(feature = "task_dump")]
+pub(crate) fn current_set_state(state: BlockState) {
+    if let Some(local) = get_co_local_data() {
+        unsafe { local.as_ref() }.get_co().inner.state.set(state);
+    }
+}
+
+/// get the scheduling priority of the coroutine
+#[inline]
+pub(crate) fn co_priority(co: &CoroutineImpl) -> Priority {
+    let local = unsafe { &*get_co_local(co) };
+    local.get_co().inner.priority
+}
+
+/// get the worker that the coroutine is pinned to
+#[inline]
+pub(crate) fn co_pinned(co: &CoroutineImpl) -> Option<usize> {
+    let local = unsafe { &*get_co_local(co) };
+    local.get_co().pinned_worker()
+}
+
+/// get the scheduler that the coroutine belongs to
+#[inline]
+pub(crate) fn co_scheduler(co: &CoroutineImpl) -> &'static Scheduler {
+    let local = unsafe { &*get_co_local(co) };
+    local.get_sched()
+}
+
 // windows use delay drop instead
 #[cfg(unix)]
 #[cfg(feature = "io_cancel")]
@@ -509,9 +853,101 @@ pub fn park_timeout(dur: Duration) {
     park_timeout_impl(Some(dur));
 }
 
+/// Sets the deadline of the current coroutine
+///
+/// All the may blocking calls in the coroutine, e.g. the socket read and
+/// write, the channel `recv`, `Condvar::wait`, `Semphore::wait`, `sleep` and
+/// `park_timeout`, are bounded by the deadline. A call with its own timeout
+/// uses the earlier one and returns its usual timeout result, e.g. `sleep`
+/// just returns at the deadline. The io calls
+/// always return an `io::ErrorKind::TimedOut` error at the deadline, and the
+/// other calls without a timeout panic at the deadline like a cancelled
+/// coroutine. The coroutines spawned by a [`scope`] inherit the deadline.
+///
+/// The io calls are only bounded with the `io_timeout` feature, which is on
+/// by default. The deadline is ignored while the cancel is disabled or the
+/// coroutine is unwinding, so that the cleanup code can still block.
+///
+/// The deadline follows the clock of the runtime, see [`time::now`]. It has
+/// no effect in a thread context.
+///
+/// [`scope`]: fn.scope.html
+/// [`time::now`]: ../time/fn.now.html
+pub fn set_deadline(deadline: Instant) {
+    if let Some(local) = get_co_local_data() {
+        unsafe { local.as_ref() }.set_deadline(Some(deadline));
+    }
+}
+
+/// Clears the deadline of the current coroutine
+pub fn clear_deadline() {
+    if let Some(local) = get_co_local_data() {
+        unsafe { local.as_ref() }.set_deadline(None);
+    }
+}
+
+/// Gets the deadline of the current coroutine
+///
+/// return none if there is no deadline or it's called in a thread context
+pub fn deadline() -> Option<Instant> {
+    let local = get_co_local_data()?;
+    unsafe { local.as_ref() }.get_deadline()
+}
+
+fn check_worker_id(sched: &Scheduler, id: usize) -> io::Result<()> {
+    if id >= sched.max_workers() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("worker id must be less than {}", sched.max_workers()),
+        ));
+    }
+    Ok(())
+}
+
+/// Moves the current coroutine to the worker thread with the given id
+///
+/// The coroutine is rescheduled on the worker and returns from this function
+/// there. A pinned coroutine is pinned to the new worker, otherwise it can
+/// still be stolen by the other workers later. Returns an error if it's not
+/// called in a coroutine or the worker id is not less than the max workers.
+pub fn migrate_to(worker: usize) -> io::Result<()> {
+    if !is_coroutine() {
+        return Err(io::Error::other("migrate_to must be called in a coroutine"));
+    }
+    let sched = get_scheduler();
+    check_worker_id(sched, worker)?;
+    if sched.current_worker() == Some(worker) {
+        return Ok(());
+    }
+
+    let co = current();
+    let pinned = co.pinned_worker();
+    // the pinned coroutine is always rescheduled to its worker
+    co.set_pinned_worker(Some(worker));
+    crate::yield_now::yield_now();
+    if pinned.is_none() {
+        co.set_pinned_worker(None);
+    }
+    Ok(())
+}
+
 /// run the coroutine
 #[inline]
 pub(crate) fn run_coroutine(mut co: CoroutineImpl) {
+    // the coroutine and its event subscription always work on its own scheduler
+    let sched = co_scheduler(&co);
+    // the pinned coroutine is never resumed on the other threads
+    if let Some(worker) = co_pinned(&co) {
+        if sched.current_worker() != Some(worker) {
+            return sched.schedule_pinned(co, worker);
+        }
+    }
+    let prev = set_current_sched(sched);
+    // record the switch in time for the watchdog
+    let clock = sched.watchdog_clock(prev);
+    let last = clock.map(|c| c.enter(unsafe { &*get_co_local(&co) }.get_co()));
+    #[cfg(feature = "task_dump")]
+    co_set_state(&co, BlockState::Running);
     match co.resume() {
         Some(ev) => ev.subscribe(co),
         None => {
@@ -520,6 +956,15 @@ pub(crate) fn run_coroutine(mut co: CoroutineImpl) {
             let join = local.get_join();
             // set the panic data
             if let Some(panic) = co.get_panic_data() {
+                // the cancel also unwinds the coroutine by a panic
+                if !matches!(panic.downcast_ref(), Some(generator::Error::Cancel)) {
+                    let handle = local.get_co();
+                    warn!(
+                        "coroutine id = {}, name = {:?} panicked",
+                        handle.id(),
+                        handle.name()
+                    );
+                }
                 join.set_panic_data(panic);
             }
             // trigger the join here
@@ -527,4 +972,8 @@ pub(crate) fn run_coroutine(mut co: CoroutineImpl) {
             Done::drop_coroutine(co);
         }
     }
+    if let (Some(clock), Some(last)) = (clock, last) {
+        clock.leave(last);
+    }
+    set_current_sched(prev);
 }
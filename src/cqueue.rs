@@ -1,7 +1,7 @@
 use std::panic;
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
 use crate::cancel::Cancel;
 use crate::coroutine_impl::{
@@ -11,6 +11,7 @@ use crate::join::JoinHandle;
 use crate::scoped::spawn_unsafe;
 use crate::sync::Mutex;
 use crate::sync::{AtomicOption, Blocker};
+use crate::time;
 use crate::yield_now::yield_with;
 
 use may_queue::mpsc::Queue;
@@ -240,7 +241,7 @@ impl Cqueue {
             }};
         }
 
-        let deadline = timeout.map(|dur| Instant::now() + dur);
+        let deadline = timeout.map(|dur| time::now() + dur);
         loop {
             match self.ev_queue.pop() {
                 Some(mut ev) => run_ev!(ev),
@@ -267,7 +268,7 @@ impl Cqueue {
 
             // check the timeout
             match deadline {
-                Some(d) if Instant::now() >= d => return Err(PollError::Timeout),
+                Some(d) if time::now() >= d => return Err(PollError::Timeout),
                 _ => {}
             }
         }
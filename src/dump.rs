@@ -0,0 +1,200 @@
+//! dump the live coroutines for debugging
+//!
+//! The registry of the live coroutines and their blocking states are only
+//! kept when the `task_dump` feature is enabled.
+
+use std::fmt;
+use std::panic::Location;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Instant;
+
+use crate::coroutine_impl::Coroutine;
+use crate::scheduler::get_scheduler;
+use crate::timeout_list::ns_to_instant;
+
+/// The state of a coroutine in the dump
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoroutineState {
+    /// the state is not recorded yet
+    Unknown,
+    /// the coroutine is running on a thread
+    Running,
+    /// the coroutine is in the ready queue, waiting for a worker
+    Queued,
+    /// the coroutine is blocked on a park, e.g. `park`, `Mutex` or channels
+    Parked,
+    /// the coroutine is waiting on an io event of the file descriptor
+    Io(Option<u64>),
+    /// the coroutine is sleeping until the time
+    Sleeping(Instant),
+    /// the coroutine is waiting on a `JoinHandle`
+    Joining,
+    /// the coroutine is blocked on some other event source
+    Suspended,
+}
+
+/// The raw state that recorded at the blocking point
+#[derive(Debug, Clone, Copy)]
+pub enum BlockState {
+    Running,
+    Queued,
+    Parked,
+    Io(Option<u64>),
+    // the wake up time in ns of the timer clock
+    Sleeping(u64),
+    Joining,
+    Suspended,
+}
+
+impl BlockState {
+    fn pack(self) -> (usize, u64) {
+        match self {
+            BlockState::Running => (1, 0),
+            BlockState::Queued => (2, 0),
+            BlockState::Parked => (3, 0),
+            BlockState::Io(None) => (4, 0),
+            BlockState::Io(Some(fd)) => (5, fd),
+            BlockState::Sleeping(t) => (6, t),
+            BlockState::Joining => (7, 0),
+            BlockState::Suspended => (8, 0),
+        }
+    }
+
+    fn unpack(kind: usize, data: u64) -> CoroutineState {
+        match kind {
+            1 => CoroutineState::Running,
+            2 => CoroutineState::Queued,
+            3 => CoroutineState::Parked,
+            4 => CoroutineState::Io(None),
+            5 => CoroutineState::Io(Some(data)),
+            6 => CoroutineState::Sleeping(ns_to_instant(data)),
+            7 => CoroutineState::Joining,
+            8 => CoroutineState::Suspended,
+            _ => CoroutineState::Unknown,
+        }
+    }
+}
+
+/// the state storage in each coroutine
+#[derive(Default)]
+pub(crate) struct StateCell {
+    kind: AtomicUsize,
+    data: AtomicU64,
+    // high level reason that override the parked state, e.g. join
+    reason: AtomicUsize,
+}
+
+impl StateCell {
+    #[inline]
+    pub fn set(&self, state: BlockState) {
+        let (kind, data) = match (state, self.reason.load(Ordering::Relaxed)) {
+            (BlockState::Parked, reason) if reason != 0 => (reason, 0),
+            (state, _) => state.pack(),
+        };
+        self.data.store(data, Ordering::Relaxed);
+        self.kind.store(kind, Ordering::Relaxed);
+    }
+
+    // set the reason for the following parks, clear it with `None`
+    #[inline]
+    pub fn set_reason(&self, reason: Option<BlockState>) {
+        let kind = reason.map(|r| r.pack().0).unwrap_or(0);
+        self.reason.store(kind, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn get(&self) -> CoroutineState {
+        let kind = self.kind.load(Ordering::Relaxed);
+        let data = self.data.load(Ordering::Relaxed);
+        BlockState::unpack(kind, data)
+    }
+}
+
+/// Information of a live coroutine
+#[derive(Debug, Clone)]
+pub struct CoroutineInfo {
+    /// the coroutine handle
+    pub co: Coroutine,
+    /// where the coroutine is spawned
+    pub location: &'static Location<'static>,
+    /// the current state of the coroutine
+    pub state: CoroutineState,
+}
+
+impl fmt::Display for CoroutineInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "coroutine {} {:?} spawned at {}: ",
+            self.co.id(),
+            self.co.name().unwrap_or("<unnamed>"),
+            self.location
+        )?;
+        match self.state {
+            CoroutineState::Io(Some(fd)) => write!(f, "waiting io on fd {fd}"),
+            CoroutineState::Sleeping(t) => {
+                let now = Instant::now();
+                if t > now {
+                    write!(f, "sleeping, wake up in {:?}", t - now)
+                } else {
+                    write!(f, "sleeping, wake up {:?} ago", now - t)
+                }
+            }
+            state => write!(f, "{state:?}"),
+        }
+    }
+}
+
+/// A snapshot of all the live coroutines of a runtime
+#[derive(Debug, Clone)]
+pub struct Dump {
+    coroutines: Vec<CoroutineInfo>,
+}
+
+impl Dump {
+    pub(crate) fn new(coroutines: Vec<Coroutine>) -> Self {
+        let coroutines = coroutines
+            .into_iter()
+            .map(|co| CoroutineInfo {
+                location: co.location(),
+                state: co.state(),
+                co,
+            })
+            .collect();
+        Dump { coroutines }
+    }
+
+    /// get all the coroutines in the dump
+    pub fn coroutines(&self) -> &[CoroutineInfo] {
+        &self.coroutines
+    }
+}
+
+impl fmt::Display for Dump {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} live coroutines:", self.coroutines.len())?;
+        for info in self.coroutines.iter() {
+            writeln!(f, "  {info}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Dump all the live coroutines of the current runtime
+///
+/// in thread context this is the default runtime. The returned value can
+/// be printed by `{}` format, for example in your own signal handling thread.
+///
+/// # Examples
+///
+/// ```
+/// use may::coroutine;
+///
+/// let h = may::go!(|| coroutine::park());
+/// println!("{}", coroutine::dump());
+/// h.coroutine().unpark();
+/// h.join().unwrap();
+/// ```
+pub fn dump() -> Dump {
+    Dump::new(get_scheduler().registry.snapshot())
+}
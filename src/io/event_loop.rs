@@ -1,9 +1,32 @@
 use std::io;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use super::sys::{Selector, SysEvent};
 use crate::scheduler::{get_scheduler, WORKER_ID};
+use crate::timeout_list::Clock;
 
 const IO_POLLS_MAX: usize = 128;
+// the max time to block in select when idle, in ns
+const MAX_IDLE_WAIT: u64 = 1_000_000_000;
+
+/// What an idle worker thread does when there are no ready coroutines
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IdlePolicy {
+    /// block in the io selector until an event, a wakeup or the next timer,
+    /// but wake up at least once a second to steal from the busy workers,
+    /// this is the default
+    #[default]
+    Default,
+    /// block in the io selector right away, and only wake up for an event,
+    /// a wakeup or the next timer. This saves power for the mostly idle
+    /// deployments, an idle worker never steals from the busy ones by itself
+    Block,
+    /// keep polling the io events and the ready queues without blocking for
+    /// the given time after the last work is done, then block like `Default`.
+    /// This cuts the wakeup latency at the cost of burning the cpu
+    Spin(Duration),
+}
 
 /// Single threaded IO event loop.
 pub struct EventLoop {
@@ -11,12 +34,12 @@ pub struct EventLoop {
 }
 
 impl EventLoop {
-    pub fn new(io_workers: usize) -> io::Result<EventLoop> {
-        Selector::new(io_workers).map(|selector| EventLoop { selector })
+    pub fn new(io_workers: usize, clock: &Arc<Clock>) -> io::Result<EventLoop> {
+        Selector::new(io_workers, clock).map(|selector| EventLoop { selector })
     }
 
-    /// Keep spinning the event loop indefinitely, and notify the handler whenever
-    /// any of the registered handles are ready.
+    /// Keep spinning the event loop until the scheduler is stopped, and notify
+    /// the handler whenever any of the registered handles are ready.
     pub fn run(&self, id: usize) {
         #[cfg(nightly)]
         WORKER_ID.set(id);
@@ -27,18 +50,58 @@ impl EventLoop {
         let mut next_expire = None;
         let selector = &self.selector;
         let scheduler = get_scheduler();
+        let policy = scheduler.idle_policy();
+        let stats = scheduler.worker_stats(id);
+        // keep polling without blocking until this time
+        let mut spin_until = None;
+
+        while !scheduler.is_stopped() {
+            // fire the expired timers of the worker, the coroutines would run
+            // in the select below
+            let next_timer = scheduler.run_timers(id);
+            let next = [next_expire, next_timer].into_iter().flatten().min();
 
-        loop {
-            next_expire = match selector.select(scheduler, id, &mut events_buf, next_expire) {
-                Ok(t) => t.or(Some(1_000_000_000)),
+            let spinning = spin_until.is_some_and(|t| Instant::now() < t);
+            let timeout = match policy {
+                _ if spinning => Some(0),
+                IdlePolicy::Block => next,
+                _ => next.or(Some(MAX_IDLE_WAIT)),
+            };
+            if !spinning {
+                stats.parks.add(1);
+            }
+
+            let activity = stats.activity();
+            next_expire = match selector.select(scheduler, id, &mut events_buf, timeout) {
+                Ok(t) => t,
                 Err(e) => {
                     error!("select error = {:?}", e);
                     continue;
                 }
+            };
+
+            let active = stats.activity() != activity;
+            if spinning && active {
+                stats.spin_hits.add(1);
+            } else if !spinning && !active {
+                stats.idle_wakeups.add(1);
+            }
+            if let IdlePolicy::Spin(dur) = policy {
+                if active {
+                    spin_until = Some(Instant::now() + dur);
+                }
             }
         }
     }
 
+    /// Poll the io events of the worker once and run the ready coroutines,
+    /// return the time in ns for the next io timer expiration
+    pub fn poll(&self, id: usize, timeout: Option<u64>) -> io::Result<Option<u64>> {
+        let mut events_buf: [SysEvent; IO_POLLS_MAX] = unsafe { std::mem::zeroed() };
+        self.selector
+            .select(get_scheduler(), id, &mut events_buf, timeout)
+    }
+
     // get the internal selector
     #[inline]
     pub fn get_selector(&self) -> &Selector {
@@ -18,6 +18,7 @@ pub(crate) mod thread;
 use std::ops::Deref;
 
 pub(crate) use self::event_loop::EventLoop;
+pub use self::event_loop::IdlePolicy;
 #[cfg(feature = "io_cancel")]
 pub(crate) use self::sys::cancel;
 pub use self::sys::co_io::CoIo;
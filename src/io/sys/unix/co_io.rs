@@ -258,4 +258,17 @@ mod tests {
         io.read_exact(&mut buf).unwrap();
         assert_eq!(buf, [0x55u8; 100]);
     }
+
+    #[test]
+    fn co_io_register_failed() {
+        // epoll refuses the regular files and /dev/null, the failed
+        // registration must be cleaned up without hanging
+        for path in ["/dev/null", "Cargo.toml"] {
+            let file = std::fs::File::open(path).unwrap();
+            let io = CoIo::new(file);
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            assert!(io.is_err());
+            drop(io);
+        }
+    }
 }
@@ -1,23 +1,45 @@
 use std::io;
 use std::os::unix::io::RawFd;
-use std::sync::atomic::Ordering;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::thread;
 #[cfg(feature = "io_timeout")]
 use std::time::Duration;
 
-use super::{from_nix_error, EventData, IoData};
+use super::{fd_key, from_nix_error, EventData, FdMap, IoData, MOVING};
 #[cfg(feature = "io_timeout")]
 use super::{timeout_handler, TimerList};
 use crate::scheduler::Scheduler;
-#[cfg(feature = "io_timeout")]
-use crate::timeout_list::{now, ns_to_ms};
+use crate::timeout_list::Clock;
 
 use libc::{eventfd, EFD_NONBLOCK};
 use may_queue::mpsc::Queue;
 use nix::sys::epoll::*;
 use nix::unistd::{close, read, write};
+use parking_lot::{Mutex, MutexGuard};
 use smallvec::SmallVec;
 
+// round the ns up to ms so that the epoll never wakes up too early
+#[inline]
+fn ns_to_ms(ns: u64) -> u64 {
+    ns.div_ceil(1_000_000)
+}
+
+// the epoll event of the fd for its registered interest
+fn fd_event(ev: &EventData) -> EpollEvent {
+    let flags = match ev.interest() {
+        None => {
+            EpollFlags::EPOLLIN
+                | EpollFlags::EPOLLOUT
+                | EpollFlags::EPOLLRDHUP
+                | EpollFlags::EPOLLET
+        }
+        Some(true) => EpollFlags::EPOLLIN | EpollFlags::EPOLLRDHUP | EpollFlags::EPOLLET,
+        Some(false) => EpollFlags::EPOLLOUT | EpollFlags::EPOLLHUP | EpollFlags::EPOLLET,
+    };
+    EpollEvent::new(flags, ev as *const _ as _)
+}
+
 fn create_eventfd() -> io::Result<RawFd> {
     let fd = unsafe { eventfd(0, EFD_NONBLOCK) };
     if fd < 0 {
@@ -34,10 +56,14 @@ struct SingleSelector {
     #[cfg(feature = "io_timeout")]
     timer_list: TimerList,
     free_ev: Queue<Arc<EventData>>,
+    // the registered fds
+    fds: Mutex<FdMap>,
+    // the active worker count that the fds are placed for
+    placed: AtomicUsize,
 }
 
 impl SingleSelector {
-    pub fn new() -> io::Result<Self> {
+    pub fn new(_clock: &Arc<Clock>) -> io::Result<Self> {
         // wakeup data is 0
         let mut info = EpollEvent::new(EpollFlags::EPOLLIN, 0);
 
@@ -62,7 +88,9 @@ impl SingleSelector {
             evfd,
             free_ev: Queue::new(),
             #[cfg(feature = "io_timeout")]
-            timer_list: TimerList::new(),
+            timer_list: TimerList::with_clock(_clock.clone()),
+            fds: Mutex::new(FdMap::new()),
+            placed: AtomicUsize::new(0),
         })
     }
 }
@@ -80,13 +108,13 @@ pub struct Selector {
 }
 
 impl Selector {
-    pub fn new(io_workers: usize) -> io::Result<Self> {
+    pub fn new(io_workers: usize, clock: &Arc<Clock>) -> io::Result<Self> {
         let mut s = Selector {
             vec: SmallVec::new(),
         };
 
         for _ in 0..io_workers {
-            let ss = SingleSelector::new()?;
+            let ss = SingleSelector::new(clock)?;
             s.vec.push(ss);
         }
 
@@ -99,14 +127,11 @@ impl Selector {
         scheduler: &Scheduler,
         id: usize,
         events: &mut [SysEvent],
-        _timeout: Option<u64>,
+        timeout: Option<u64>,
     ) -> io::Result<Option<u64>> {
-        #[cfg(feature = "io_timeout")]
-        let timeout_ms = _timeout
+        let timeout_ms = timeout
             .map(|to| std::cmp::min(ns_to_ms(to), isize::MAX as u64) as isize)
             .unwrap_or(-1);
-        #[cfg(not(feature = "io_timeout"))]
-        let timeout_ms = -1;
         // info!("select; timeout={:?}", timeout_ms);
 
         let single_selector = unsafe { self.vec.get_unchecked(id) };
@@ -115,6 +140,9 @@ impl Selector {
         // Wait for epoll events for at most timeout_ms milliseconds
         let n = epoll_wait(epfd, events, timeout_ms).map_err(from_nix_error)?;
         // println!("epoll_wait = {}", n);
+        let stats = scheduler.worker_stats(id);
+        stats.selects.add(1);
+        stats.events.add(n);
 
         // collect coroutines
         for event in unsafe { events.get_unchecked(..n) } {
@@ -123,6 +151,7 @@ impl Selector {
                 let mut buf = [0u8; 8];
                 // clear the eventfd, ignore the result
                 read(single_selector.evfd, &mut buf).ok();
+                stats.notified.add(1);
                 // info!("got wakeup event in select, id={}", id);
                 scheduler.collect_global(id);
                 continue;
@@ -137,23 +166,27 @@ impl Selector {
                 None => continue,
             };
 
-            // it's safe to remove the timer since we are running the timer_list in the same thread
+            // cancel the timer, the timer handler would not get the event data
             #[cfg(feature = "io_timeout")]
-            data.timer.borrow_mut().take().map(|h| {
-                unsafe {
-                    // tell the timer handler not to cancel the io
-                    // it's not always true that you can really remove the timer entry
-                    h.with_mut_data(|value| value.data.event_data = std::ptr::null_mut());
-                }
-                h.remove()
-            });
+            if let Some(h) = data.timer.borrow_mut().take() {
+                h.remove();
+            }
 
             #[cfg(feature = "work_steal")]
             scheduler.schedule_with_id(co, id);
             #[cfg(not(feature = "work_steal"))]
-            crate::coroutine_impl::run_coroutine(co);
+            if scheduler.is_retired(id) {
+                scheduler.schedule_global(co);
+            } else {
+                crate::coroutine_impl::run_coroutine(co);
+            }
         }
 
+        // move the fds to their new workers before running any coroutine, so
+        // that a retired worker that is blocked by a pinned coroutine doesn't
+        // hold them
+        self.place_fds(scheduler, id);
+
         // run all the local tasks
         scheduler.run_queued_tasks(id);
 
@@ -162,14 +195,42 @@ impl Selector {
 
         // deal with the timer list
         #[cfg(feature = "io_timeout")]
-        let next_expire = single_selector
-            .timer_list
-            .schedule_timer(now(), &timeout_handler);
+        let next_expire = {
+            let timer_list = &single_selector.timer_list;
+            timer_list.schedule_timer(timer_list.clock().now(), &timeout_handler)
+        };
         #[cfg(not(feature = "io_timeout"))]
         let next_expire = None;
         Ok(next_expire)
     }
 
+    // number of the pending io timers of all the selectors
+    #[inline]
+    #[cfg(feature = "io_timeout")]
+    pub fn pending_timers(&self) -> usize {
+        self.vec.iter().map(|s| s.timer_list.pending()).sum()
+    }
+
+    #[inline]
+    #[cfg(not(feature = "io_timeout"))]
+    pub fn pending_timers(&self) -> usize {
+        0
+    }
+
+    // the earliest time in ns that the selectors check the io timers again
+    #[inline]
+    #[cfg(feature = "io_timeout")]
+    pub fn next_timer_wake(&self) -> u64 {
+        let wakes = self.vec.iter().map(|s| s.timer_list.next_wake());
+        wakes.min().unwrap_or(u64::MAX)
+    }
+
+    #[inline]
+    #[cfg(not(feature = "io_timeout"))]
+    pub fn next_timer_wake(&self) -> u64 {
+        u64::MAX
+    }
+
     // this will post an os event so that we can wake up the event loop
     #[inline]
     pub fn wakeup(&self, id: usize) {
@@ -178,73 +239,153 @@ impl Selector {
         trace!("wakeup id={:?}, ret={:?}", id, ret);
     }
 
+    // return true if any fd is registered to the selector
+    #[inline]
+    pub fn has_io(&self, id: usize) -> bool {
+        !unsafe { self.vec.get_unchecked(id) }.fds.lock().is_empty()
+    }
+
+    // lock the fds of the selector that the fd is registered to, the fd may
+    // be moved to another selector before the lock is taken
+    fn lock_fd(&self, ev: &EventData) -> (&SingleSelector, MutexGuard<'_, FdMap>) {
+        loop {
+            let id = ev.worker();
+            if id == MOVING {
+                // the move only takes a few syscalls
+                thread::yield_now();
+                continue;
+            }
+            let single_selector = unsafe { self.vec.get_unchecked(id) };
+            let fds = single_selector.fds.lock();
+            if ev.worker() == id {
+                return (single_selector, fds);
+            }
+        }
+    }
+
     // register io event to the selector
     #[inline]
     pub fn add_fd(&self, io_data: IoData) -> io::Result<IoData> {
-        let mut info = EpollEvent::new(
-            EpollFlags::EPOLLIN
-                | EpollFlags::EPOLLOUT
-                | EpollFlags::EPOLLRDHUP
-                | EpollFlags::EPOLLET,
-            io_data.as_ref() as *const _ as _,
-        );
-
         let fd = io_data.fd;
-        let id = fd as usize % self.vec.len();
-        let single_selector = unsafe { self.vec.get_unchecked(id) };
-        let epfd = single_selector.epfd;
-        info!("add fd to epoll select, fd={:?}", fd);
-        epoll_ctl(epfd, EpollOp::EpollCtlAdd, fd, &mut info)
-            .map_err(from_nix_error)
-            .map(|_| io_data)
+        let sched = io_data.sched;
+        loop {
+            let workers = sched.workers();
+            let id = fd as usize % workers;
+            let single_selector = unsafe { self.vec.get_unchecked(id) };
+            let mut fds = single_selector.fds.lock();
+            // the worker may have moved out its fds for the new worker count
+            if sched.workers() != workers {
+                continue;
+            }
+            info!("add fd to epoll select, fd={:?}", fd);
+            io_data.set_worker(id);
+            let mut info = fd_event(&io_data);
+            let ret = epoll_ctl(single_selector.epfd, EpollOp::EpollCtlAdd, fd, &mut info)
+                .map_err(from_nix_error);
+            if ret.is_ok() {
+                fds.insert(fd_key(&io_data), (*io_data).clone());
+            }
+            // the io data is always deleted when dropped, even if it fails
+            // here, which locks the fds again
+            drop(fds);
+            return ret.map(|_| io_data);
+        }
     }
 
     #[inline]
     pub fn mod_fd(&self, io_data: &IoData, is_read: bool) -> io::Result<()> {
-        let mut info = if is_read {
-            EpollEvent::new(
-                EpollFlags::EPOLLIN | EpollFlags::EPOLLRDHUP | EpollFlags::EPOLLET,
-                io_data.as_ref() as *const _ as _,
-            )
-        } else {
-            EpollEvent::new(
-                EpollFlags::EPOLLOUT | EpollFlags::EPOLLHUP | EpollFlags::EPOLLET,
-                io_data.as_ref() as *const _ as _,
-            )
-        };
-
         let fd = io_data.fd;
-        let id = fd as usize % self.vec.len();
-        let single_selector = unsafe { self.vec.get_unchecked(id) };
-        let epfd = single_selector.epfd;
+        let (single_selector, _fds) = self.lock_fd(io_data);
         info!("mod fd to epoll select, fd={:?}, is_read={}", fd, is_read);
-        epoll_ctl(epfd, EpollOp::EpollCtlMod, fd, &mut info).map_err(from_nix_error)
+        io_data.set_interest(is_read);
+        let mut info = fd_event(io_data);
+        epoll_ctl(single_selector.epfd, EpollOp::EpollCtlMod, fd, &mut info).map_err(from_nix_error)
     }
 
     #[inline]
     pub fn del_fd(&self, io_data: &IoData) {
         #[cfg(feature = "io_timeout")]
         if let Some(h) = io_data.timer.borrow_mut().take() {
-            unsafe {
-                // mark the timer as removed if any, this only happened
-                // when cancel an IO. what if the timer expired at the same time?
-                // because we run this func in the user space, so the timer handler
-                // will not got the coroutine
-                h.with_mut_data(|value| value.data.event_data = std::ptr::null_mut());
-            }
+            // remove the timer if any, this only happened when cancel an IO.
+            // if the timer is fired at the same time, the timer handler
+            // would not get the coroutine
+            h.remove();
         }
 
         let fd = io_data.fd;
-        let id = fd as usize % self.vec.len();
-        let single_selector = unsafe { self.vec.get_unchecked(id) };
-        let epfd = single_selector.epfd;
+        let (single_selector, mut fds) = self.lock_fd(io_data);
         info!("del fd from epoll select, fd={:?}", fd);
-        epoll_ctl(epfd, EpollOp::EpollCtlDel, fd, None).ok();
+        fds.remove(&fd_key(io_data));
+        epoll_ctl(single_selector.epfd, EpollOp::EpollCtlDel, fd, None).ok();
+        drop(fds);
 
         // after EpollCtlDel push the unused event data
         single_selector.free_ev.push((*io_data).clone());
     }
 
+    // move the fds of the worker to the workers that they belong to after
+    // the active worker count is changed, it's called by the worker itself
+    // so that no event of the moved fds is being processed
+    fn place_fds(&self, sched: &Scheduler, id: usize) {
+        let single_selector = unsafe { self.vec.get_unchecked(id) };
+        let workers = sched.workers();
+        if single_selector.placed.swap(workers, Ordering::Relaxed) == workers {
+            return;
+        }
+        let moved: Vec<_> = {
+            let fds = single_selector.fds.lock();
+            let moved = fds.values().filter(|ev| ev.fd as usize % workers != id);
+            moved.cloned().collect()
+        };
+        if !moved.is_empty() {
+            info!("move {} fds of worker {}", moved.len(), id);
+        }
+        for ev in moved {
+            self.move_fd(sched, &ev, id, workers);
+        }
+    }
+
+    // move the fd to the worker that it belongs to in `workers`
+    fn move_fd(&self, sched: &Scheduler, ev: &Arc<EventData>, from: usize, workers: usize) {
+        let src = unsafe { self.vec.get_unchecked(from) };
+        {
+            let mut fds = src.fds.lock();
+            if fds.remove(&fd_key(ev)).is_none() {
+                // the fd is deleted
+                return;
+            }
+            // others wait until the fd is settled
+            ev.set_worker(MOVING);
+        }
+
+        let fd = ev.fd;
+        let to = fd as usize % workers;
+        let dst = unsafe { self.vec.get_unchecked(to) };
+        {
+            let mut fds = dst.fds.lock();
+            // the worker count is changed again, the worker would place its
+            // fds later so the fd is kept here for the next round
+            if sched.workers() == workers {
+                let mut info = fd_event(ev);
+                match epoll_ctl(dst.epfd, EpollOp::EpollCtlAdd, fd, &mut info) {
+                    Ok(()) => {
+                        // the fd is still open since the deleting waits for
+                        // the move, so it's safe to remove it by the number
+                        epoll_ctl(src.epfd, EpollOp::EpollCtlDel, fd, None).ok();
+                        fds.insert(fd_key(ev), ev.clone());
+                        ev.set_worker(to);
+                        return;
+                    }
+                    Err(e) => error!("failed to move fd {} to worker {}: {}", fd, to, e),
+                }
+            }
+        }
+
+        // put it back
+        src.fds.lock().insert(fd_key(ev), ev.clone());
+        ev.set_worker(from);
+    }
+
     // we can't free the event data directly in the worker thread
     // must free them before the next epoll_wait
     #[inline]
@@ -257,7 +398,11 @@ impl Selector {
     #[inline]
     #[cfg(feature = "io_timeout")]
     pub fn add_io_timer(&self, io: &IoData, timeout: Duration) {
-        let id = io.fd as usize % self.vec.len();
+        // the timer can be on any worker while the fd is moved
+        let id = match io.worker() {
+            MOVING => io.fd as usize % io.sched.workers(),
+            id => id,
+        };
         // info!("io timeout = {:?}", dur);
         let (h, b_new) = unsafe { self.vec.get_unchecked(id) }
             .timer_list
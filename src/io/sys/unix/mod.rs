@@ -22,16 +22,19 @@ pub mod wait_io;
 
 #[cfg(feature = "io_timeout")]
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::ops::Deref;
 use std::os::unix::io::{AsRawFd, RawFd};
-use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "io_timeout")]
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::{fmt, io};
 
 use crate::coroutine_impl::{run_coroutine, CoroutineImpl};
 use crate::io::thread::ASSOCIATED_IO_RET;
 use crate::likely::likely;
-use crate::scheduler::get_scheduler;
+use crate::scheduler::{get_scheduler, Scheduler};
 use crate::sync::AtomicOption;
 #[cfg(feature = "io_timeout")]
 use crate::timeout_list::{TimeOutList, TimeoutHandle};
@@ -43,18 +46,19 @@ pub use self::select::{Selector, SysEvent};
 
 #[inline]
 pub fn add_socket<T: AsRawFd + ?Sized>(t: &T) -> io::Result<IoData> {
-    get_scheduler().get_selector().add_fd(IoData::new(t))
+    let io_data = IoData::new(t);
+    io_data.selector().add_fd(io_data)
 }
 
 #[inline]
 pub fn mod_socket(io: &IoData, is_read: bool) -> io::Result<()> {
-    get_scheduler().get_selector().mod_fd(io, is_read)
+    io.selector().mod_fd(io, is_read)
 }
 
 #[inline]
 fn del_socket(io: &IoData) {
     // transfer the io to the selector
-    get_scheduler().get_selector().del_fd(io);
+    io.selector().del_fd(io);
 }
 
 // deal with the io result
@@ -81,11 +85,7 @@ fn from_nix_error(err: nix::Error) -> ::std::io::Error {
 
 #[cfg(feature = "io_timeout")]
 fn timeout_handler(data: TimerData) {
-    if data.event_data.is_null() {
-        return;
-    }
-
-    let event_data = unsafe { &mut *data.event_data };
+    let event_data = unsafe { &mut *data.event_data.as_ptr() };
     // remove the event timer
     event_data.timer.borrow_mut().take();
 
@@ -104,18 +104,44 @@ fn timeout_handler(data: TimerData) {
 // the timeout data
 #[cfg(feature = "io_timeout")]
 pub struct TimerData {
-    event_data: *mut EventData,
+    event_data: NonNull<EventData>,
 }
 
+#[cfg(feature = "io_timeout")]
+unsafe impl Send for TimerData {}
+
 #[cfg(feature = "io_timeout")]
 pub type TimerList = TimeOutList<TimerData>;
 #[cfg(feature = "io_timeout")]
 pub type TimerHandle = TimeoutHandle<TimerData>;
 
+// the worker id of the fd while it's moved to another selector
+const MOVING: usize = usize::MAX;
+
+// the events that the fd is registered for
+const INTEREST_ALL: u8 = 0;
+const INTEREST_READ: u8 = 1;
+const INTEREST_WRITE: u8 = 2;
+
+// the fds that are registered to a selector, keyed by their event data
+type FdMap = HashMap<usize, Arc<EventData>>;
+
+#[inline]
+fn fd_key(ev: &EventData) -> usize {
+    ev as *const EventData as usize
+}
+
 // event associated io data, must be construct in
 // each file handle, the epoll event.data would point to it
 pub struct EventData {
     pub fd: RawFd,
+    // the scheduler that the fd is registered to
+    sched: &'static Scheduler,
+    // the worker whose selector the fd is registered to, the fd is moved to
+    // another worker when the active worker count is changed
+    id: AtomicUsize,
+    // the events that the fd is registered for, changed by `mod_socket`
+    interest: AtomicU8,
     pub io_flag: AtomicBool,
     #[cfg(feature = "io_timeout")]
     pub timer: RefCell<Option<TimerHandle>>,
@@ -127,8 +153,12 @@ unsafe impl Sync for EventData {}
 
 impl EventData {
     pub fn new(fd: RawFd) -> EventData {
+        let sched = get_scheduler();
         EventData {
             fd,
+            sched,
+            id: AtomicUsize::new(fd as usize % sched.workers()),
+            interest: AtomicU8::new(INTEREST_ALL),
             io_flag: AtomicBool::new(false),
             #[cfg(feature = "io_timeout")]
             timer: RefCell::new(None),
@@ -136,10 +166,49 @@ impl EventData {
         }
     }
 
+    // get the selector that the fd is registered to
+    #[inline]
+    pub fn selector(&self) -> &'static Selector {
+        self.sched.get_selector()
+    }
+
+    // the worker whose selector the fd is registered to, `MOVING` while the
+    // fd is moved to another worker
+    #[inline]
+    fn worker(&self) -> usize {
+        self.id.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    fn set_worker(&self, id: usize) {
+        self.id.store(id, Ordering::Release);
+    }
+
+    // the events that the fd is registered for, none for both read and
+    // write, or whether it's read only
+    #[inline]
+    fn interest(&self) -> Option<bool> {
+        match self.interest.load(Ordering::Relaxed) {
+            INTEREST_READ => Some(true),
+            INTEREST_WRITE => Some(false),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    fn set_interest(&self, is_read: bool) {
+        let interest = if is_read {
+            INTEREST_READ
+        } else {
+            INTEREST_WRITE
+        };
+        self.interest.store(interest, Ordering::Relaxed);
+    }
+
     #[cfg(feature = "io_timeout")]
     pub fn timer_data(&self) -> TimerData {
         TimerData {
-            event_data: self as *const _ as *mut _,
+            event_data: NonNull::from(self),
         }
     }
 
@@ -151,19 +220,14 @@ impl EventData {
             Some(co) => co,
         };
 
-        // it's safe to remove the timer since we are running the timer_list in the same thread
+        // cancel the timer, the timer function would not get the event data
         #[cfg(feature = "io_timeout")]
-        self.timer.borrow_mut().take().map(|h| {
-            unsafe {
-                // tell the timer function not to cancel the io
-                // it's not always true that you can really remove the timer entry
-                h.with_mut_data(|value| value.data.event_data = std::ptr::null_mut());
-            }
-            h.remove()
-        });
+        if let Some(h) = self.timer.borrow_mut().take() {
+            h.remove();
+        }
 
         // schedule the coroutine
-        get_scheduler().schedule(co);
+        self.sched.schedule(co);
     }
 
     /// used by local re-schedule that in `subscribe`
@@ -176,16 +240,11 @@ impl EventData {
             Some(co) => co,
         };
 
-        // it's safe to remove the timer since we are running the timer_list in the same thread
+        // cancel the timer, the timer function would not get the event data
         #[cfg(feature = "io_timeout")]
-        self.timer.borrow_mut().take().map(|h| {
-            unsafe {
-                // tell the timer function not to cancel the io
-                // it's not always true that you can really remove the timer entry
-                h.with_mut_data(|value| value.data.event_data = std::ptr::null_mut());
-            }
-            h.remove()
-        });
+        if let Some(h) = self.timer.borrow_mut().take() {
+            h.remove();
+        }
 
         // run the coroutine
         run_coroutine(co);
@@ -4,7 +4,11 @@ use std::sync::atomic::Ordering;
 use std::time::Duration;
 
 use super::super::{co_io_result, IoData};
+#[cfg(feature = "io_timeout")]
+use crate::coroutine_impl::co_timeout;
 use crate::coroutine_impl::{is_coroutine, CoroutineImpl, EventSource};
+#[cfg(feature = "task_dump")]
+use crate::dump::BlockState;
 use crate::io::AsIoData;
 use crate::yield_now::yield_with_io;
 
@@ -66,14 +70,17 @@ impl<'a> SocketWriteVectored<'a> {
 }
 
 impl<'a> EventSource for SocketWriteVectored<'a> {
+    #[cfg(feature = "task_dump")]
+    fn block_state(&self) -> BlockState {
+        BlockState::Io(Some(self.io_data.fd as u64))
+    }
+
     fn subscribe(&mut self, co: CoroutineImpl) {
         let io_data = self.io_data;
 
         #[cfg(feature = "io_timeout")]
-        if let Some(dur) = self.timeout {
-            crate::scheduler::get_scheduler()
-                .get_selector()
-                .add_io_timer(self.io_data, dur);
+        if let Some(dur) = co_timeout(&co, self.timeout) {
+            self.io_data.selector().add_io_timer(self.io_data, dur);
         }
         unsafe { io_data.co.unsync_store(co) };
 
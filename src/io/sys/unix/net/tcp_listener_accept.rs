@@ -6,6 +6,8 @@ use super::super::{add_socket, co_io_result, IoData};
 #[cfg(feature = "io_cancel")]
 use crate::coroutine_impl::co_cancel_data;
 use crate::coroutine_impl::{is_coroutine, CoroutineImpl, EventSource};
+#[cfg(feature = "task_dump")]
+use crate::dump::BlockState;
 use crate::io::AsIoData;
 use crate::net::{TcpListener, TcpStream};
 use crate::yield_now::yield_with_io;
@@ -59,6 +61,11 @@ impl<'a> TcpListenerAccept<'a> {
 }
 
 impl<'a> EventSource for TcpListenerAccept<'a> {
+    #[cfg(feature = "task_dump")]
+    fn block_state(&self) -> BlockState {
+        BlockState::Io(Some(self.io_data.fd as u64))
+    }
+
     fn subscribe(&mut self, co: CoroutineImpl) {
         #[cfg(feature = "io_cancel")]
         let cancel = co_cancel_data(&co);
@@ -5,6 +5,8 @@ use std::sync::atomic::Ordering;
 #[cfg(feature = "io_cancel")]
 use crate::coroutine_impl::co_cancel_data;
 use crate::coroutine_impl::{is_coroutine, CoroutineImpl, EventSource};
+#[cfg(feature = "task_dump")]
+use crate::dump::BlockState;
 use crate::io::sys::{co_io_result, IoData};
 use crate::io::{AsIoData, CoIo};
 use crate::os::unix::net::{UnixListener, UnixStream};
@@ -59,6 +61,11 @@ impl<'a> UnixListenerAccept<'a> {
 }
 
 impl<'a> EventSource for UnixListenerAccept<'a> {
+    #[cfg(feature = "task_dump")]
+    fn block_state(&self) -> BlockState {
+        BlockState::Io(Some(self.io_data.fd as u64))
+    }
+
     fn subscribe(&mut self, co: CoroutineImpl) {
         #[cfg(feature = "io_cancel")]
         let cancel = co_cancel_data(&co);
@@ -8,6 +8,8 @@ use super::super::{add_socket, co_io_result, IoData};
 #[cfg(feature = "io_cancel")]
 use crate::coroutine_impl::co_cancel_data;
 use crate::coroutine_impl::{is_coroutine, CoroutineImpl, EventSource};
+#[cfg(feature = "task_dump")]
+use crate::dump::BlockState;
 use crate::io::{CoIo, OptionCell};
 use crate::os::unix::net::UnixStream;
 use crate::yield_now::yield_with_io;
@@ -89,14 +91,19 @@ impl UnixStreamConnect {
 }
 
 impl EventSource for UnixStreamConnect {
+    #[cfg(feature = "task_dump")]
+    fn block_state(&self) -> BlockState {
+        BlockState::Io(Some(self.io_data.fd as u64))
+    }
+
     fn subscribe(&mut self, co: CoroutineImpl) {
         #[cfg(feature = "io_cancel")]
         let cancel = co_cancel_data(&co);
         let io_data = &self.io_data;
 
         #[cfg(feature = "io_timeout")]
-        crate::scheduler::get_scheduler()
-            .get_selector()
+        self.io_data
+            .selector()
             .add_io_timer(&self.io_data, Duration::from_secs(2));
         unsafe { io_data.co.unsync_store(co) };
 
@@ -1,12 +1,14 @@
 use std::cell::UnsafeCell;
 use std::os::windows::io::AsRawSocket;
+use std::ptr::NonNull;
+use std::sync::Arc;
 #[cfg(feature = "io_timeout")]
 use std::time::Duration;
 use std::{io, ptr};
 
-use crate::coroutine_impl::CoroutineImpl;
+use crate::coroutine_impl::{co_id, CoroutineImpl};
 use crate::scheduler::Scheduler;
-use crate::timeout_list::{now, ns_to_dur, TimeOutList, TimeoutHandle};
+use crate::timeout_list::{ns_to_dur, Clock, TimeOutList, TimeoutHandle};
 use crate::yield_now::set_co_para;
 use miow::iocp::{CompletionPort, CompletionStatus};
 use smallvec::SmallVec;
@@ -15,9 +17,11 @@ use windows_sys::Win32::System::IO::{CancelIoEx, GetOverlappedResult, OVERLAPPED
 
 // the timeout data
 pub struct TimerData {
-    event_data: *mut EventData,
+    event_data: NonNull<EventData>,
 }
 
+unsafe impl Send for TimerData {}
+
 type TimerList = TimeOutList<TimerData>;
 pub type TimerHandle = TimeoutHandle<TimerData>;
 
@@ -51,7 +55,7 @@ impl EventData {
     #[cfg(feature = "io_timeout")]
     pub fn timer_data(&self) -> TimerData {
         TimerData {
-            event_data: self as *const _ as *mut _,
+            event_data: NonNull::from(self),
         }
     }
 
@@ -71,11 +75,11 @@ struct SingleSelector {
 }
 
 impl SingleSelector {
-    pub fn new() -> io::Result<SingleSelector> {
+    pub fn new(clock: &Arc<Clock>) -> io::Result<SingleSelector> {
         // only let one thread working, other threads blocking, this is more efficient
         CompletionPort::new(1).map(|cp| SingleSelector {
             port: cp,
-            timer_list: TimerList::new(),
+            timer_list: TimerList::with_clock(clock.clone()),
         })
     }
 }
@@ -86,13 +90,13 @@ pub struct Selector {
 }
 
 impl Selector {
-    pub fn new(io_workers: usize) -> io::Result<Self> {
+    pub fn new(io_workers: usize, clock: &Arc<Clock>) -> io::Result<Self> {
         let mut s = Selector {
             vec: SmallVec::new(),
         };
 
         for _ in 0..io_workers {
-            let ss = SingleSelector::new()?;
+            let ss = SingleSelector::new(clock)?;
             s.vec.push(ss);
         }
 
@@ -116,12 +120,16 @@ impl Selector {
             Err(ref e) if e.raw_os_error() == Some(WAIT_TIMEOUT as i32) => 0,
             Err(e) => return Err(e),
         };
+        let stats = scheduler.worker_stats(id);
+        stats.selects.add(1);
+        stats.events.add(n);
 
         for status in unsafe { events.get_unchecked(..n) } {
             // need to check the status for each io
             let overlapped = status.overlapped();
             if overlapped.is_null() {
                 // this is just a wakeup event, ignore it
+                stats.notified.add(1);
                 scheduler.collect_global(id);
                 continue;
             }
@@ -133,19 +141,10 @@ impl Selector {
             // the coroutine will never come back because there is no event
             let mut co = data.co.take().expect("can't get co in selector");
 
-            // it's safe to remove the timer since we are
-            // running the timer_list in the same thread
-            // this is not true when running in multi-thread environment
-            data.timer.take().map(|h| {
-                unsafe {
-                    // tell the timer function not to cancel the io
-                    // it's not always true that you can really remove the timer entry
-                    // it's safe in multi-thread env because it only access its own data
-                    h.with_mut_data(|value| value.data.event_data = ptr::null_mut());
-                }
-                // NOT SAFE for multi-thread!!
-                h.remove()
-            });
+            // cancel the timer, the timer function would not get the event data
+            if let Some(h) = data.timer.take() {
+                h.remove();
+            }
 
             let overlapped = unsafe { &*overlapped };
             // info!("select got overlapped, status = {}", overlapped.Internal);
@@ -154,7 +153,11 @@ impl Selector {
             // check the status
             match overlapped.Internal as u32 {
                 ERROR_OPERATION_ABORTED | STATUS_CANCELLED_U32 => {
-                    warn!("coroutine timeout, stat=0x{:x}", overlapped.Internal);
+                    warn!(
+                        "coroutine {} timeout, stat=0x{:x}",
+                        co_id(&co),
+                        overlapped.Internal
+                    );
                     set_co_para(&mut co, io::Error::new(io::ErrorKind::TimedOut, "timeout"));
                     // timer data is popped already
                 }
@@ -186,12 +189,26 @@ impl Selector {
         scheduler.run_queued_tasks(id);
 
         // deal with the timer list
-        let next_expire = single_selector
-            .timer_list
-            .schedule_timer(now(), &timeout_handler);
+        let next_expire = {
+            let timer_list = &single_selector.timer_list;
+            timer_list.schedule_timer(timer_list.clock().now(), &timeout_handler)
+        };
         Ok(next_expire)
     }
 
+    // number of the pending io timers of all the selectors
+    #[inline]
+    pub fn pending_timers(&self) -> usize {
+        self.vec.iter().map(|s| s.timer_list.pending()).sum()
+    }
+
+    // the earliest time in ns that the selectors check the io timers again
+    #[inline]
+    pub fn next_timer_wake(&self) -> u64 {
+        let wakes = self.vec.iter().map(|s| s.timer_list.next_wake());
+        wakes.min().unwrap_or(u64::MAX)
+    }
+
     // this will post an os event so that we can wakeup the event loop
     #[inline]
     pub fn wakeup(&self, id: usize) {
@@ -202,12 +219,18 @@ impl Selector {
             .unwrap();
     }
 
-    // register file handle to the iocp
+    // the registered handles are not tracked, so always assume there is some
+    #[inline]
+    pub fn has_io(&self, _id: usize) -> bool {
+        true
+    }
+
+    // register file handle to the iocp of one of the active workers
     #[inline]
-    pub fn add_socket<T: AsRawSocket + ?Sized>(&self, t: &T) -> io::Result<()> {
+    pub fn add_socket<T: AsRawSocket + ?Sized>(&self, t: &T, workers: usize) -> io::Result<()> {
         // the token para is not used, just pass the handle
         let fd = (t.as_raw_socket() as usize) >> 2;
-        let id = fd % self.vec.len();
+        let id = fd % workers;
         unsafe { self.vec.get_unchecked(id) }.port.add_socket(fd, t)
     }
 
@@ -240,12 +263,8 @@ unsafe fn cancel_io(handle: HANDLE, overlapped: *mut OVERLAPPED) -> io::Result<(
 // when timeout happened we need to cancel the io operation
 // this will trigger an event on the IOCP and processed in the selector
 pub fn timeout_handler(data: TimerData) {
-    if data.event_data.is_null() {
-        return;
-    }
-
     unsafe {
-        let event_data = &mut *data.event_data;
+        let event_data = &mut *data.event_data.as_ptr();
         // remove the event timer
         event_data.timer.take();
         // ignore the error, the select may grab the data first!
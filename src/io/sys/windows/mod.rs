@@ -54,7 +54,11 @@ unsafe impl Send for IoData {}
 // register the socket to the system selector
 #[inline]
 pub fn add_socket<T: AsRawSocket + ?Sized>(t: &T) -> io::Result<IoData> {
-    get_scheduler().get_selector().add_socket(t).map(|_| IoData)
+    let sched = get_scheduler();
+    sched
+        .get_selector()
+        .add_socket(t, sched.workers())
+        .map(|_| IoData)
 }
 
 // deal with the io result
@@ -6,6 +6,8 @@ use std::time::Duration;
 use super::super::{co_io_result, EventData};
 #[cfg(feature = "io_cancel")]
 use crate::coroutine_impl::co_cancel_data;
+#[cfg(feature = "io_timeout")]
+use crate::coroutine_impl::co_timeout;
 use crate::coroutine_impl::{is_coroutine, CoroutineImpl, EventSource};
 #[cfg(feature = "io_cancel")]
 use crate::io::cancel::CancelIoData;
@@ -57,7 +59,7 @@ impl<'a> EventSource for SocketRead<'a> {
         // that the timer handle can't be removed in time
         // we must prepare the timer before call the API
         #[cfg(feature = "io_timeout")]
-        if let Some(dur) = self.timeout {
+        if let Some(dur) = co_timeout(&co, self.timeout) {
             s.get_selector().add_io_timer(&mut self.io_data, dur);
         }
 
@@ -7,6 +7,8 @@ use std::time::Duration;
 use super::super::{add_socket, co_io_result, EventData, IoData};
 #[cfg(feature = "io_cancel")]
 use crate::coroutine_impl::co_cancel_data;
+#[cfg(feature = "io_timeout")]
+use crate::coroutine_impl::co_timeout;
 use crate::coroutine_impl::{is_coroutine, CoroutineImpl, EventSource};
 #[cfg(feature = "io_cancel")]
 use crate::io::cancel::CancelIoData;
@@ -96,7 +98,7 @@ impl EventSource for TcpStreamConnect {
         #[cfg(feature = "io_cancel")]
         let cancel = co_cancel_data(&co);
         #[cfg(feature = "io_timeout")]
-        if let Some(dur) = self.timeout {
+        if let Some(dur) = co_timeout(&co, self.timeout) {
             s.get_selector().add_io_timer(&mut self.io_data, dur);
         }
         self.io_data.co = Some(co);
@@ -5,6 +5,8 @@ use std::os::windows::io::AsRawSocket;
 use std::time::Duration;
 
 use super::super::{co_io_result, EventData};
+#[cfg(feature = "io_timeout")]
+use crate::coroutine_impl::co_timeout;
 use crate::coroutine_impl::{is_coroutine, CoroutineImpl, EventSource};
 use crate::net::UdpSocket;
 use crate::scheduler::get_scheduler;
@@ -51,7 +53,7 @@ impl<'a> EventSource for UdpSendTo<'a> {
     fn subscribe(&mut self, co: CoroutineImpl) {
         let s = get_scheduler();
         #[cfg(feature = "io_timeout")]
-        if let Some(dur) = self.timeout {
+        if let Some(dur) = co_timeout(&co, self.timeout) {
             s.get_selector().add_io_timer(&mut self.io_data, dur);
         }
         // prepare the co first
@@ -3,8 +3,13 @@ use std::fmt;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::thread::Result;
+use std::time::Duration;
 
 use crate::coroutine_impl::Coroutine;
+#[cfg(feature = "task_dump")]
+use crate::coroutine_impl::{current, is_coroutine};
+#[cfg(feature = "task_dump")]
+use crate::dump::BlockState;
 use crate::sync::{AtomicOption, Blocker};
 use generator::Error;
 
@@ -45,6 +50,11 @@ impl Join {
     }
 
     fn wait(&self) {
+        self.wait_timeout(None);
+    }
+
+    // return true if the coroutine is done within the timeout
+    fn wait_timeout(&self, dur: Option<Duration>) -> bool {
         if self.state.load(Ordering::Acquire) {
             let cur = Blocker::current();
             // register the blocker first
@@ -52,11 +62,28 @@ impl Join {
             // re-check the state
             if self.state.load(Ordering::Acquire) {
                 // successfully register the blocker
-                cur.park(None).ok();
+                #[cfg(feature = "task_dump")]
+                let co = is_coroutine().then(current);
+                #[cfg(feature = "task_dump")]
+                if let Some(co) = co.as_ref() {
+                    co.set_block_reason(Some(BlockState::Joining));
+                }
+                cur.park(dur).ok();
+                #[cfg(feature = "task_dump")]
+                if let Some(co) = co.as_ref() {
+                    co.set_block_reason(None);
+                }
+                // timeout, unregister the blocker and re-check the state since
+                // the coroutine may finish in between. a lost take means the
+                // trigger already owns the wakeup, which is the completion
+                if self.to_wake.take().is_some() && self.state.load(Ordering::Acquire) {
+                    return false;
+                }
             } else {
                 self.to_wake.take();
             }
         }
+        true
     }
 }
 
@@ -102,11 +129,44 @@ impl<T> JoinHandle<T> {
         self.join.wait();
     }
 
+    // block until the coroutine is done or timeout, return true if done
+    pub(crate) fn wait_timeout(&self, dur: Duration) -> bool {
+        self.join.wait_timeout(Some(dur))
+    }
+
     /// Join the coroutine, returning the result it produced.
     pub fn join(self) -> Result<T> {
         self.join.wait();
+        self.take_result()
+    }
+
+    /// Join the coroutine with a timeout
+    ///
+    /// return the handle back if the coroutine is not done within the
+    /// timeout, so that it can be joined or aborted later
+    pub fn join_timeout(self, dur: Duration) -> std::result::Result<Result<T>, Self> {
+        if self.wait_timeout(dur) {
+            Ok(self.take_result())
+        } else {
+            Err(self)
+        }
+    }
+
+    /// Cancel the coroutine and wait until it's unwound
+    ///
+    /// The coroutine panics at its next blocking point and drops everything
+    /// it holds before this returns, a coroutine that already finished
+    /// returns its result as usual.
+    pub fn abort(self) -> Result<T> {
+        if !self.is_done() {
+            // safe since the coroutine owns all its data and we wait for it
+            unsafe { self.co.cancel() };
+        }
+        self.join()
+    }
 
-        // take the result
+    // take the result after the coroutine is done
+    fn take_result(&self) -> Result<T> {
         self.packet
             .take()
             .ok_or_else(|| self.panic.take().unwrap_or_else(|| Box::new(Error::Cancel)))
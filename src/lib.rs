@@ -27,20 +27,31 @@
 #[macro_use]
 extern crate log;
 
+mod affinity;
+mod blocking_pool;
 mod cancel;
 mod config;
+#[cfg(feature = "task_dump")]
+mod dump;
 mod join;
 mod likely;
+mod live;
 mod local;
+mod metrics;
 mod park;
 mod pool;
+#[cfg(feature = "task_dump")]
+mod registry;
+mod runtime;
 mod sleep;
+mod timeout;
 #[macro_use]
 mod macros;
 mod coroutine_impl;
 mod scheduler;
 mod scoped;
 mod timeout_list;
+mod watchdog;
 mod yield_now;
 
 pub mod coroutine;
@@ -48,8 +59,18 @@ pub mod cqueue;
 pub mod io;
 pub mod net;
 pub mod os;
+#[cfg(feature = "sim")]
+pub mod sim;
 pub mod sync;
+pub mod time;
+pub use crate::affinity::CpuAffinity;
 pub use crate::config::{config, Config};
+pub use crate::io::IdlePolicy;
 pub use crate::local::LocalKey;
+pub use crate::metrics::{metrics, Metrics, PoolMetrics, WorkerMetrics};
+pub use crate::runtime::{
+    set_active_workers, shutdown, CurrentThreadRuntime, Runtime, RuntimeBuilder, ShutdownReport,
+};
+pub use crate::watchdog::WatchdogReport;
 // re-export may_queue
 pub use may_queue as queue;
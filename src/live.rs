@@ -0,0 +1,130 @@
+//! the handles of the live coroutines of a scheduler
+//!
+//! it's always kept so that the shutdown can cancel the live coroutines and
+//! the watchdog can tell which one is blocking a worker. Each coroutine takes
+//! a slot when spawned and gives it back when done, the slot index is kept in
+//! the coroutine, so no lock is shared by the spawns and exits, only the lock
+//! of the slot itself that is taken by a snapshot now and then.
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+use crate::coroutine_impl::Coroutine;
+use crossbeam::queue::SegQueue;
+use parking_lot::Mutex;
+
+// the first bucket has `1 << FIRST_BITS` slots, each next one doubles it
+const FIRST_BITS: u32 = 6;
+const BUCKETS: usize = (usize::BITS - FIRST_BITS) as usize;
+
+type Slot = Mutex<Option<Coroutine>>;
+
+pub struct LiveSet {
+    // the buckets are allocated on demand and never moved
+    buckets: Box<[AtomicPtr<Slot>]>,
+    // the number of the slots that are ever taken
+    next: AtomicUsize,
+    // the slots that are given back
+    free: SegQueue<usize>,
+}
+
+// the bucket and the offset in it of the slot
+#[inline]
+fn locate(slot: usize) -> (usize, usize) {
+    let pos = slot + (1 << FIRST_BITS);
+    let bucket = (usize::BITS - 1 - pos.leading_zeros() - FIRST_BITS) as usize;
+    (bucket, pos - bucket_len(bucket))
+}
+
+#[inline]
+fn bucket_len(bucket: usize) -> usize {
+    1 << (bucket as u32 + FIRST_BITS)
+}
+
+impl LiveSet {
+    pub fn new() -> Self {
+        LiveSet {
+            buckets: (0..BUCKETS)
+                .map(|_| AtomicPtr::new(ptr::null_mut()))
+                .collect(),
+            next: AtomicUsize::new(0),
+            free: SegQueue::new(),
+        }
+    }
+
+    // get the slot, `None` if its bucket is not allocated yet
+    #[inline]
+    fn get_slot(&self, slot: usize) -> Option<&Slot> {
+        let (bucket, offset) = locate(slot);
+        let p = unsafe { self.buckets.get_unchecked(bucket) }.load(Ordering::Acquire);
+        if p.is_null() {
+            return None;
+        }
+        Some(unsafe { &*p.add(offset) })
+    }
+
+    // get the slot, allocate its bucket if needed
+    fn slot(&self, slot: usize) -> &Slot {
+        if let Some(s) = self.get_slot(slot) {
+            return s;
+        }
+        let (bucket, offset) = locate(slot);
+        let new: Box<[Slot]> = (0..bucket_len(bucket)).map(|_| Mutex::new(None)).collect();
+        let new = Box::into_raw(new) as *mut Slot;
+        let p = match unsafe { self.buckets.get_unchecked(bucket) }.compare_exchange(
+            ptr::null_mut(),
+            new,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => new,
+            Err(p) => {
+                // another spawn allocated it first
+                let len = bucket_len(bucket);
+                drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(new, len)) });
+                p
+            }
+        };
+        unsafe { &*p.add(offset) }
+    }
+
+    /// put the newly spawned coroutine in a slot and return the slot index
+    #[inline]
+    pub fn insert(&self, co: &Coroutine) -> usize {
+        let slot = match self.free.pop() {
+            Some(slot) => slot,
+            None => self.next.fetch_add(1, Ordering::Relaxed),
+        };
+        *self.slot(slot).lock() = Some(co.clone());
+        slot
+    }
+
+    /// give back the slot of the finished coroutine
+    #[inline]
+    pub fn remove(&self, slot: usize) {
+        self.slot(slot).lock().take();
+        self.free.push(slot);
+    }
+
+    /// get the live coroutine in the slot
+    pub fn get(&self, slot: usize) -> Option<Coroutine> {
+        self.get_slot(slot)?.lock().clone()
+    }
+
+    /// get the handles of all the live coroutines
+    pub fn snapshot(&self) -> Vec<Coroutine> {
+        let n = self.next.load(Ordering::Relaxed);
+        (0..n).filter_map(|slot| self.get(slot)).collect()
+    }
+}
+
+impl Drop for LiveSet {
+    fn drop(&mut self) {
+        for (bucket, p) in self.buckets.iter().enumerate() {
+            let p = p.load(Ordering::Relaxed);
+            if !p.is_null() {
+                let len = bucket_len(bucket);
+                drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(p, len)) });
+            }
+        }
+    }
+}
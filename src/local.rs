@@ -1,12 +1,15 @@
 use std::any::TypeId;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::hash::{BuildHasherDefault, Hasher};
 use std::ptr::NonNull;
 use std::sync::Arc;
+use std::time::Instant;
 
 use crate::coroutine_impl::Coroutine;
 use crate::join::Join;
+use crate::scheduler::Scheduler;
+use crate::sync::CancellationToken;
 use generator::get_local_data;
 
 // thread local map storage
@@ -18,17 +21,26 @@ pub struct CoroutineLocal {
     co: Coroutine,
     // when panic happens, we need to trigger the join here
     join: Arc<Join>,
+    // the scheduler that the coroutine belongs to
+    sched: &'static Scheduler,
     // real local data hash map
     local_data: LocalMap,
+    // the cancellation token of the scope that spawned the coroutine
+    token: RefCell<Option<CancellationToken>>,
+    // the deadline that bounds the blocking calls of the coroutine
+    deadline: Cell<Option<Instant>>,
 }
 
 impl CoroutineLocal {
     /// create coroutine local storage
-    pub fn new(co: Coroutine, join: Arc<Join>) -> Box<Self> {
+    pub fn new(co: Coroutine, join: Arc<Join>, sched: &'static Scheduler) -> Box<Self> {
         Box::new(CoroutineLocal {
             co,
             join,
+            sched,
             local_data: RefCell::new(HashMap::default()),
+            token: RefCell::new(None),
+            deadline: Cell::new(None),
         })
     }
 
@@ -41,6 +53,31 @@ impl CoroutineLocal {
     pub fn get_join(&self) -> Arc<Join> {
         self.join.clone()
     }
+
+    // get the scheduler that the coroutine belongs to
+    pub fn get_sched(&self) -> &'static Scheduler {
+        self.sched
+    }
+
+    // get the cancellation token of the coroutine
+    pub fn get_token(&self) -> Option<CancellationToken> {
+        self.token.borrow().clone()
+    }
+
+    // set the cancellation token of the coroutine
+    pub fn set_token(&self, token: CancellationToken) {
+        *self.token.borrow_mut() = Some(token);
+    }
+
+    // get the deadline of the coroutine
+    pub fn get_deadline(&self) -> Option<Instant> {
+        self.deadline.get()
+    }
+
+    // set the deadline of the coroutine
+    pub fn set_deadline(&self, deadline: Option<Instant>) {
+        self.deadline.set(deadline);
+    }
 }
 
 #[inline]
@@ -0,0 +1,167 @@
+//! `May` runtime metrics
+//!
+//! The counters are updated with relaxed atomics, most of them are only
+//! written by the owning worker thread, so they are cheap enough to be
+//! always enabled. A snapshot is not an atomic view of the whole runtime.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::scheduler::get_scheduler;
+
+/// single writer counter, only the owning thread can call `add`
+#[derive(Default)]
+pub(crate) struct LocalCounter(AtomicUsize);
+
+impl LocalCounter {
+    #[inline]
+    pub fn add(&self, n: usize) {
+        // no need a locked instruction for the single writer
+        let v = self.0.load(Ordering::Relaxed);
+        self.0.store(v.wrapping_add(n), Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn get(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// multi writer counter
+#[derive(Default)]
+pub(crate) struct SharedCounter(AtomicUsize);
+
+impl SharedCounter {
+    #[inline]
+    pub fn add(&self, n: usize) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn get(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// the internal counters of a worker thread
+#[derive(Default)]
+pub(crate) struct WorkerStats {
+    // coroutines pushed to the local queue
+    pub pushed: LocalCounter,
+    // coroutines popped from the local queue by the owner
+    pub popped: LocalCounter,
+    // coroutines popped from the local queue by other workers
+    pub taken: SharedCounter,
+    // successful steals by this worker
+    pub steals: LocalCounter,
+    // coroutines stolen by this worker
+    pub stolen: LocalCounter,
+    // number of select calls
+    pub selects: LocalCounter,
+    // number of io events returned by select
+    pub events: LocalCounter,
+    // number of blocking select calls
+    pub parks: LocalCounter,
+    // number of wakeup notifications received by select
+    pub notified: LocalCounter,
+    // number of non-blocking polls that found work when spinning
+    pub spin_hits: LocalCounter,
+    // number of blocking select calls that returned without any work
+    pub idle_wakeups: LocalCounter,
+}
+
+impl WorkerStats {
+    // the local queue length calculated from the counters
+    pub fn local_queue_len(&self) -> usize {
+        // read the consumers first so that the result would not underflow
+        let taken = self.taken.get();
+        let popped = self.popped.get();
+        let pushed = self.pushed.get();
+        pushed.saturating_sub(popped.wrapping_add(taken))
+    }
+
+    // a number that changes whenever the worker gets some work done
+    #[inline]
+    pub fn activity(&self) -> usize {
+        self.events
+            .get()
+            .wrapping_add(self.popped.get())
+            .wrapping_add(self.steals.get())
+    }
+}
+
+/// Metrics of a worker thread
+#[derive(Debug, Clone, Default)]
+pub struct WorkerMetrics {
+    /// number of coroutines in the local queue
+    pub local_queue_len: usize,
+    /// number of coroutines in the global queue
+    pub global_queue_len: usize,
+    /// number of successful steals from other workers
+    pub steal_count: usize,
+    /// number of coroutines stolen from other workers
+    pub stolen_coroutines: usize,
+    /// number of `select` calls on the worker's io selector
+    pub select_count: usize,
+    /// number of io events returned by the worker's io selector
+    pub event_count: usize,
+    /// number of times the worker blocked in the io selector when idle
+    pub park_count: usize,
+    /// number of wakeup notifications that the worker received, e.g. for the
+    /// coroutines scheduled from other threads
+    pub notify_count: usize,
+    /// number of times the worker found work by polling when spinning, see
+    /// [`IdlePolicy::Spin`](enum.IdlePolicy.html#variant.Spin)
+    pub spin_hit_count: usize,
+    /// number of times the worker woke up from blocking without any work,
+    /// e.g. for the periodic wakeup of [`IdlePolicy::Default`]
+    ///
+    /// [`IdlePolicy::Default`]: enum.IdlePolicy.html#variant.Default
+    pub idle_wakeup_count: usize,
+}
+
+impl WorkerMetrics {
+    /// average io events returned per `select` call
+    pub fn events_per_select(&self) -> f64 {
+        if self.select_count == 0 {
+            0.0
+        } else {
+            self.event_count as f64 / self.select_count as f64
+        }
+    }
+}
+
+/// Metrics of the coroutine pool
+#[derive(Debug, Clone, Default)]
+pub struct PoolMetrics {
+    /// number of spawns that reused a cached coroutine
+    pub hits: usize,
+    /// number of spawns that had to create a new coroutine
+    pub misses: usize,
+    /// number of cached coroutines
+    pub size: usize,
+    /// max number of cached coroutines
+    pub capacity: usize,
+}
+
+/// A snapshot of the runtime metrics
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    /// per worker metrics, indexed by the worker id, including the retired ones
+    pub workers: Vec<WorkerMetrics>,
+    /// number of the active workers
+    pub active_workers: usize,
+    /// number of live coroutines
+    pub live_coroutines: usize,
+    /// number of pending timers, e.g. `sleep`, `park_timeout` and the io
+    /// timeouts
+    pub pending_timers: usize,
+    /// coroutine pool metrics
+    pub pool: PoolMetrics,
+}
+
+/// get the metrics of the current runtime
+///
+/// in thread context this is the default runtime
+pub fn metrics() -> Metrics {
+    get_scheduler().metrics()
+}
@@ -5,8 +5,12 @@ use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
-use crate::cancel::Cancel;
-use crate::coroutine_impl::{co_cancel_data, run_coroutine, CoroutineImpl, EventSource};
+use crate::cancel::{trigger_cancel_panic, Cancel};
+use crate::coroutine_impl::{
+    co_cancel_data, current_timeout, run_coroutine, CoroutineImpl, EventSource,
+};
+#[cfg(feature = "task_dump")]
+use crate::dump::BlockState;
 use crate::scheduler::get_scheduler;
 use crate::sync::atomic_dur::AtomicDuration;
 use crate::sync::AtomicOption;
@@ -108,11 +112,8 @@ impl Park {
     #[inline]
     fn remove_timeout_handle(&self) {
         if let Some(h) = self.set_timeout_handle(None) {
-            if h.is_link() {
-                get_scheduler().del_timer(h);
-            }
-            // when timeout the node is unlinked
-            // just drop it to release memory
+            // it's a no-op if the timer is already fired
+            get_scheduler().del_timer(h);
         }
     }
 
@@ -148,7 +149,13 @@ impl Park {
             yield_now();
         }
 
-        self.timeout.store(dur);
+        // the deadline of the coroutine bounds the timeout
+        let timeout = current_timeout(dur);
+        // the stored timeout can't be zero, return at once instead
+        if timeout == Some(Duration::ZERO) {
+            return self.timed_out(dur);
+        }
+        self.timeout.store(timeout);
 
         // what if the state is set before yield?
         // the subscribe would re-check it
@@ -163,7 +170,7 @@ impl Park {
 
         if let Some(err) = get_co_para() {
             match err.kind() {
-                ErrorKind::TimedOut => return Err(ParkError::Timeout),
+                ErrorKind::TimedOut => return self.timed_out(dur),
                 ErrorKind::Other => return Err(ParkError::Canceled),
                 _ => unreachable!("unexpected return error kind"),
             }
@@ -172,6 +179,18 @@ impl Park {
         Ok(())
     }
 
+    // the park is timed out, either by its own timeout or the deadline
+    fn timed_out(&self, dur: Option<Duration>) -> Result<(), ParkError> {
+        if dur.is_some() {
+            return Err(ParkError::Timeout);
+        }
+        // the deadline is passed for a park without timeout
+        if self.check_cancel.load(Ordering::Relaxed) {
+            trigger_cancel_panic();
+        }
+        Err(ParkError::Canceled)
+    }
+
     fn delay_drop(&self) -> DropGuard {
         self.wait_kernel.store(true, Ordering::Release);
         DropGuard(self)
@@ -196,6 +215,11 @@ impl Drop for Park {
 }
 
 impl EventSource for Park {
+    #[cfg(feature = "task_dump")]
+    fn block_state(&self) -> BlockState {
+        BlockState::Parked
+    }
+
     // register the coroutine to the park
     fn subscribe(&mut self, co: CoroutineImpl) {
         let cancel = co_cancel_data(&co);
@@ -1,7 +1,7 @@
 use std::sync::atomic::{AtomicUsize, Ordering};
 
-use crate::config::config;
 use crate::coroutine_impl::CoroutineImpl;
+use crate::metrics::{PoolMetrics, SharedCounter};
 use crossbeam::queue::SegQueue;
 use generator::Gn;
 
@@ -11,25 +11,33 @@ pub struct CoroutinePool {
     // the pool must support mpmc operation!
     pool: SegQueue<CoroutineImpl>,
     size: AtomicUsize,
+    capacity: usize,
+    stack_size: usize,
+    hits: SharedCounter,
+    misses: SharedCounter,
 }
 
 impl CoroutinePool {
-    fn create_dummy_coroutine() -> CoroutineImpl {
-        Gn::new_opt(config().get_stack_size(), move || {
+    fn create_dummy_coroutine(&self) -> CoroutineImpl {
+        Gn::new_opt(self.stack_size, move || {
             unreachable!("dummy coroutine should never be called");
         })
     }
 
-    pub fn new() -> Self {
-        let capacity = config().get_pool_capacity();
-        let pool = SegQueue::new();
+    pub fn new(capacity: usize, stack_size: usize) -> Self {
+        let pool = CoroutinePool {
+            pool: SegQueue::new(),
+            size: AtomicUsize::new(capacity),
+            capacity,
+            stack_size,
+            hits: SharedCounter::default(),
+            misses: SharedCounter::default(),
+        };
         for _ in 0..capacity {
-            let co = Self::create_dummy_coroutine();
-            pool.push(co);
+            let co = pool.create_dummy_coroutine();
+            pool.pool.push(co);
         }
-        let size = AtomicUsize::new(capacity);
-
-        CoroutinePool { pool, size }
+        pool
     }
 
     /// get a raw coroutine from the pool
@@ -37,10 +45,14 @@ impl CoroutinePool {
     pub fn get(&self) -> CoroutineImpl {
         self.size.fetch_sub(1, Ordering::AcqRel);
         match self.pool.pop() {
-            Some(co) => co,
+            Some(co) => {
+                self.hits.add(1);
+                co
+            }
             None => {
+                self.misses.add(1);
                 self.size.fetch_add(1, Ordering::AcqRel);
-                Self::create_dummy_coroutine()
+                self.create_dummy_coroutine()
             }
         }
     }
@@ -50,10 +62,27 @@ impl CoroutinePool {
     pub fn put(&self, co: CoroutineImpl) {
         // discard the co if push failed
         let m = self.size.fetch_add(1, Ordering::AcqRel);
-        if m >= config().get_pool_capacity() {
+        if m >= self.capacity {
             self.size.fetch_sub(1, Ordering::AcqRel);
             return;
         }
         self.pool.push(co);
     }
+
+    /// get the pool metrics
+    pub fn metrics(&self) -> PoolMetrics {
+        PoolMetrics {
+            hits: self.hits.get(),
+            misses: self.misses.get(),
+            size: self.pool.len(),
+            capacity: self.capacity,
+        }
+    }
+
+    /// drop all the cached coroutines
+    pub fn clear(&self) {
+        while self.pool.pop().is_some() {
+            self.size.fetch_sub(1, Ordering::AcqRel);
+        }
+    }
 }
@@ -0,0 +1,54 @@
+//! registry of all the live coroutines of a scheduler
+//!
+//! it's only kept with the `task_dump` feature since it takes a shard lock on
+//! each spawn and exit, the shutdown and the watchdog use the live set instead
+use std::collections::HashMap;
+
+use crate::coroutine_impl::Coroutine;
+use parking_lot::Mutex;
+
+// number of the lock shards, must be power of 2
+const SHARDS: usize = 64;
+
+type Shard = Mutex<HashMap<usize, Coroutine>>;
+
+pub struct Registry {
+    shards: Vec<Shard>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Registry {
+            shards: (0..SHARDS).map(|_| Mutex::new(HashMap::new())).collect(),
+        }
+    }
+
+    #[inline]
+    fn shard(&self, key: usize) -> &Shard {
+        // the key is a heap address, ignore the low bits
+        unsafe { self.shards.get_unchecked((key >> 6) & (SHARDS - 1)) }
+    }
+
+    /// register a newly spawned coroutine
+    #[inline]
+    pub fn insert(&self, co: &Coroutine) {
+        let key = co.as_ptr();
+        self.shard(key).lock().insert(key, co.clone());
+    }
+
+    /// remove a finished coroutine
+    #[inline]
+    pub fn remove(&self, co: &Coroutine) {
+        let key = co.as_ptr();
+        self.shard(key).lock().remove(&key);
+    }
+
+    /// get the handles of all the live coroutines
+    pub fn snapshot(&self) -> Vec<Coroutine> {
+        let mut v = Vec::new();
+        for shard in self.shards.iter() {
+            v.extend(shard.lock().values().cloned());
+        }
+        v
+    }
+}
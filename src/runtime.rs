@@ -0,0 +1,767 @@
+//! `May` Runtime interface
+//!
+//! A runtime owns its own scheduler, event loop, timers and coroutine pool,
+//! so that coroutines of different runtimes never share worker threads.
+//! The free functions like `coroutine::spawn` work on the default runtime which
+//! is created from the global [`Config`](struct.Config.html) on first use.
+
+use std::fmt;
+use std::io;
+use std::marker::PhantomData;
+use std::panic;
+use std::time::Duration;
+
+use crate::affinity::CpuAffinity;
+use crate::config::{config, resolve_max_workers, ThreadHook};
+use crate::coroutine_impl::{Builder, Coroutine};
+#[cfg(feature = "task_dump")]
+use crate::dump::Dump;
+use crate::io::IdlePolicy;
+use crate::join::JoinHandle;
+use crate::metrics::Metrics;
+use crate::scheduler::{default_scheduler, is_default_started, Scheduler};
+use crate::time::Clock;
+use crate::watchdog::{WatchdogCallback, WatchdogReport};
+
+/// Runtime factory, which can be used in order to configure the properties of
+/// a new runtime.
+///
+/// The initial settings are copied from the global [`Config`], methods can be
+/// chained on it in order to override them.
+///
+/// # Examples
+///
+/// ```
+/// use may::RuntimeBuilder;
+///
+/// let rt = RuntimeBuilder::new().workers(2).build().unwrap();
+/// let ret = unsafe { rt.block_on(|| 42) };
+/// assert_eq!(ret, 42);
+/// ```
+///
+/// [`Config`]: struct.Config.html
+#[derive(Debug, Clone)]
+pub struct RuntimeBuilder {
+    workers: usize,
+    max_workers: usize,
+    stack_size: usize,
+    pool_capacity: usize,
+    blocking_threads: usize,
+    blocking_idle_timeout: Duration,
+    cpu_affinity: CpuAffinity,
+    thread_stack_size: usize,
+    steal_batch: usize,
+    lifo_slot: bool,
+    idle_policy: IdlePolicy,
+    on_thread_start: Option<ThreadHook>,
+    on_thread_stop: Option<ThreadHook>,
+    watchdog: Option<Duration>,
+    watchdog_callback: Option<WatchdogCallback>,
+    current_thread: bool,
+    #[cfg(feature = "sim")]
+    sim_seed: Option<u64>,
+}
+
+impl Default for RuntimeBuilder {
+    fn default() -> Self {
+        RuntimeBuilder::new()
+    }
+}
+
+impl RuntimeBuilder {
+    /// create a runtime builder with the settings of the global config
+    pub fn new() -> Self {
+        let config = config();
+        let (on_thread_start, on_thread_stop) = config.get_thread_hooks();
+        RuntimeBuilder {
+            workers: config.get_workers(),
+            max_workers: config.max_workers_setting(),
+            stack_size: config.get_stack_size(),
+            pool_capacity: config.get_pool_capacity(),
+            blocking_threads: config.get_blocking_threads(),
+            blocking_idle_timeout: config.get_blocking_idle_timeout(),
+            cpu_affinity: config.get_cpu_affinity(),
+            thread_stack_size: config.get_thread_stack_size(),
+            steal_batch: config.get_steal_batch(),
+            lifo_slot: config.get_lifo_slot(),
+            idle_policy: config.get_idle_policy(),
+            on_thread_start,
+            on_thread_stop,
+            watchdog: config.get_watchdog(),
+            watchdog_callback: config.get_watchdog_callback(),
+            current_thread: false,
+            #[cfg(feature = "sim")]
+            sim_seed: None,
+        }
+    }
+
+    /// set the worker thread number
+    ///
+    /// the minimum worker thread is 1, if you pass 0 to it, will use internal default
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = if workers != 0 {
+            workers
+        } else {
+            num_cpus::get()
+        };
+        self
+    }
+
+    /// set the max worker thread number
+    ///
+    /// all the worker threads up to this limit are started when the runtime is
+    /// built, the workers beyond [`workers`](#method.workers) start retired
+    /// and can be reactivated by
+    /// [`Runtime::set_active_workers`](struct.Runtime.html#method.set_active_workers),
+    /// which can't go beyond this limit. The default is twice the workers. If
+    /// you pass 0 to it, the default is used, a value less than the workers is
+    /// the same as the workers, so that no worker can be added later
+    pub fn max_workers(mut self, workers: usize) -> Self {
+        self.max_workers = workers;
+        self
+    }
+
+    /// set default coroutine stack size in usize
+    ///
+    /// if you pass 0 to it, will use internal default
+    pub fn stack_size(mut self, size: usize) -> Self {
+        self.stack_size = if size != 0 {
+            size
+        } else {
+            config().get_stack_size()
+        };
+        self
+    }
+
+    /// set cached coroutine pool number
+    ///
+    /// if you pass 0 to it, will use internal default
+    pub fn pool_capacity(mut self, capacity: usize) -> Self {
+        self.pool_capacity = if capacity != 0 {
+            capacity
+        } else {
+            config().get_pool_capacity()
+        };
+        self
+    }
+
+    /// set the max thread number of the `spawn_blocking` pool
+    ///
+    /// if you pass 0 to it, will use internal default
+    pub fn blocking_threads(mut self, threads: usize) -> Self {
+        self.blocking_threads = if threads != 0 {
+            threads
+        } else {
+            config().get_blocking_threads()
+        };
+        self
+    }
+
+    /// set how long an idle thread of the `spawn_blocking` pool is kept alive
+    pub fn blocking_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.blocking_idle_timeout = timeout;
+        self
+    }
+
+    /// set how the worker threads are pinned to the cpu cores
+    pub fn cpu_affinity(mut self, affinity: CpuAffinity) -> Self {
+        self.cpu_affinity = affinity;
+        self
+    }
+
+    /// set the os stack size of the runtime threads
+    ///
+    /// if you pass 0 to it, will use the std default
+    pub fn thread_stack_size(mut self, size: usize) -> Self {
+        self.thread_stack_size = size;
+        self
+    }
+
+    /// set the max number of coroutines that an idle worker steals at a time
+    ///
+    /// if you pass 0 to it, will use internal default
+    pub fn steal_batch(mut self, batch: usize) -> Self {
+        self.steal_batch = if batch != 0 {
+            batch
+        } else {
+            config().get_steal_batch()
+        };
+        self
+    }
+
+    /// enable or disable the worker LIFO slot
+    ///
+    /// see [`Config::set_lifo_slot`](struct.Config.html#method.set_lifo_slot)
+    pub fn lifo_slot(mut self, enable: bool) -> Self {
+        self.lifo_slot = enable;
+        self
+    }
+
+    /// set what the idle workers do when there are no ready coroutines
+    ///
+    /// see [`IdlePolicy`](enum.IdlePolicy.html)
+    pub fn idle_policy(mut self, policy: IdlePolicy) -> Self {
+        self.idle_policy = policy;
+        self
+    }
+
+    /// set a callback that runs on each runtime thread when it starts
+    ///
+    /// see [`Config::on_thread_start`](struct.Config.html#method.on_thread_start)
+    pub fn on_thread_start<F>(mut self, f: F) -> Self
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.on_thread_start = Some(ThreadHook::new(f));
+        self
+    }
+
+    /// set a callback that runs on each runtime thread before it exits
+    ///
+    /// see [`Config::on_thread_stop`](struct.Config.html#method.on_thread_stop)
+    pub fn on_thread_stop<F>(mut self, f: F) -> Self
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.on_thread_stop = Some(ThreadHook::new(f));
+        self
+    }
+
+    /// enable the watchdog with the threshold, pass `None` to disable it
+    ///
+    /// see [`Config::set_watchdog`](struct.Config.html#method.set_watchdog)
+    pub fn watchdog(mut self, threshold: Option<Duration>) -> Self {
+        self.watchdog = threshold;
+        self
+    }
+
+    /// set the callback that is called by the watchdog thread for each report
+    pub fn watchdog_callback<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&WatchdogReport) + Send + Sync + 'static,
+    {
+        self.watchdog_callback = Some(WatchdogCallback::new(f));
+        self
+    }
+
+    /// get the worker thread number
+    pub fn get_workers(&self) -> usize {
+        self.workers
+    }
+
+    /// get the max worker thread number
+    pub fn get_max_workers(&self) -> usize {
+        resolve_max_workers(self.max_workers, self.workers)
+    }
+
+    /// get the default coroutine stack size
+    pub fn get_stack_size(&self) -> usize {
+        self.stack_size
+    }
+
+    /// get the coroutine pool capacity
+    pub fn get_pool_capacity(&self) -> usize {
+        self.pool_capacity
+    }
+
+    /// get the max thread number of the `spawn_blocking` pool
+    pub fn get_blocking_threads(&self) -> usize {
+        self.blocking_threads
+    }
+
+    /// get the idle timeout of the `spawn_blocking` pool threads
+    pub fn get_blocking_idle_timeout(&self) -> Duration {
+        self.blocking_idle_timeout
+    }
+
+    /// get the worker threads cpu affinity setting
+    pub fn get_cpu_affinity(&self) -> &CpuAffinity {
+        &self.cpu_affinity
+    }
+
+    /// get the os stack size of the runtime threads, 0 means the std default
+    pub fn get_thread_stack_size(&self) -> usize {
+        self.thread_stack_size
+    }
+
+    /// get the max number of coroutines stolen at a time
+    pub fn get_steal_batch(&self) -> usize {
+        self.steal_batch
+    }
+
+    /// get if the worker LIFO slot is enabled
+    pub fn get_lifo_slot(&self) -> bool {
+        self.lifo_slot
+    }
+
+    /// get the idle worker policy
+    pub fn get_idle_policy(&self) -> IdlePolicy {
+        self.idle_policy
+    }
+
+    pub(crate) fn get_thread_hooks(&self) -> (Option<ThreadHook>, Option<ThreadHook>) {
+        (self.on_thread_start.clone(), self.on_thread_stop.clone())
+    }
+
+    /// get the watchdog threshold
+    pub fn get_watchdog(&self) -> Option<Duration> {
+        self.watchdog
+    }
+
+    pub(crate) fn get_watchdog_callback(&self) -> Option<WatchdogCallback> {
+        self.watchdog_callback.clone()
+    }
+
+    pub(crate) fn is_current_thread(&self) -> bool {
+        self.current_thread
+    }
+
+    #[cfg(feature = "sim")]
+    pub(crate) fn get_sim_seed(&self) -> Option<u64> {
+        self.sim_seed
+    }
+
+    /// create the runtime and start all its threads
+    pub fn build(self) -> io::Result<Runtime> {
+        let sched = Scheduler::new(&self)?;
+        if let Err(e) = sched.start() {
+            // join the threads that already started
+            sched.shutdown(Duration::from_millis(0)).ok();
+            return Err(e);
+        }
+        Ok(Runtime { sched })
+    }
+
+    /// create a runtime that is driven by the calling thread
+    ///
+    /// There is no worker thread, the worker settings are ignored.
+    /// See [`CurrentThreadRuntime`](struct.CurrentThreadRuntime.html).
+    pub fn build_current_thread(mut self) -> io::Result<CurrentThreadRuntime> {
+        self.workers = 1;
+        self.max_workers = 1;
+        self.current_thread = true;
+        let sched = Scheduler::new(&self)?;
+        if let Err(e) = sched.start() {
+            sched.shutdown(Duration::from_millis(0)).ok();
+            return Err(e);
+        }
+        Ok(CurrentThreadRuntime {
+            sched,
+            _not_send: PhantomData,
+        })
+    }
+
+    /// create a current thread runtime that runs the deterministic simulation
+    #[cfg(feature = "sim")]
+    pub(crate) fn build_simulation(mut self, seed: u64) -> io::Result<CurrentThreadRuntime> {
+        self.sim_seed = Some(seed);
+        self.build_current_thread()
+    }
+}
+
+/// An isolated coroutine runtime
+///
+/// Coroutines spawned by a runtime, and all the coroutines spawned by them,
+/// are scheduled only on the worker threads of that runtime. Their timers and
+/// the io objects created inside them are also handled by that runtime.
+///
+/// Dropping a runtime shuts it down with a short timeout of 100ms, use
+/// [`shutdown`] to give the live coroutines more time to finish. All the
+/// runtime resources are then freed, unless something still refers to them,
+/// e.g. a live coroutine, a detached thread or an io object created inside
+/// the runtime, in which case they are leaked.
+///
+/// [`shutdown`]: struct.Runtime.html#method.shutdown
+pub struct Runtime {
+    sched: &'static Scheduler,
+}
+
+// the scheduler is designed to be shared by all threads
+unsafe impl Send for Runtime {}
+unsafe impl Sync for Runtime {}
+
+impl Runtime {
+    /// create a runtime with the settings of the global config
+    pub fn new() -> io::Result<Runtime> {
+        RuntimeBuilder::new().build()
+    }
+
+    /// Spawns a new coroutine on the runtime, returning a [`JoinHandle`] for it.
+    ///
+    /// # Safety
+    ///
+    /// see [`coroutine::spawn`] for the safety requirements
+    ///
+    /// [`JoinHandle`]: coroutine/struct.JoinHandle.html
+    /// [`coroutine::spawn`]: coroutine/fn.spawn.html
+    #[track_caller]
+    pub unsafe fn spawn<F, T>(&self, f: F) -> JoinHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        self.spawn_with(Builder::new(), f).unwrap()
+    }
+
+    /// Spawns a new coroutine configured by the `Builder` on the runtime
+    ///
+    /// # Safety
+    ///
+    /// see [`coroutine::spawn`] for the safety requirements
+    ///
+    /// [`coroutine::spawn`]: coroutine/fn.spawn.html
+    #[track_caller]
+    pub unsafe fn spawn_with<F, T>(&self, builder: Builder, f: F) -> io::Result<JoinHandle<T>>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        builder.spawn_on(self.sched, f)
+    }
+
+    /// Runs the closure as a coroutine on the runtime and blocks the current
+    /// thread or coroutine until it's done, returning the result.
+    ///
+    /// If the coroutine panics, the panic is resumed in the caller.
+    ///
+    /// # Safety
+    ///
+    /// see [`coroutine::spawn`] for the safety requirements
+    ///
+    /// [`coroutine::spawn`]: coroutine/fn.spawn.html
+    #[track_caller]
+    pub unsafe fn block_on<F, T>(&self, f: F) -> T
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        match self.spawn(f).join() {
+            Ok(ret) => ret,
+            Err(e) => panic::resume_unwind(e),
+        }
+    }
+
+    /// get the active worker thread number of the runtime
+    pub fn workers(&self) -> usize {
+        self.sched.workers()
+    }
+
+    /// Retire or reactivate the worker threads of the runtime
+    ///
+    /// No thread is created or stopped here, all the `max_workers` threads
+    /// are started when the runtime is built, and the first `workers` of them
+    /// are kept active. `workers` must be within `1..=max_workers`, so by
+    /// default the active workers can grow to twice the initial number, see
+    /// [`RuntimeBuilder::max_workers`].
+    ///
+    /// [`RuntimeBuilder::max_workers`]: struct.RuntimeBuilder.html#method.max_workers
+    ///
+    /// A retired worker moves its queued coroutines to the active ones and
+    /// gets no new coroutines, only the ones pinned to it. On unix the io
+    /// objects are spread over the active workers by their fds, so after the
+    /// change each worker moves the io objects that now belong to another
+    /// worker to it, and a retired worker polls none of them. On windows the
+    /// io handles can't leave the completion port they are associated with,
+    /// so the ones on a retired worker are still polled by it.
+    pub fn set_active_workers(&self, workers: usize) -> io::Result<()> {
+        self.sched.set_active_workers(workers)
+    }
+
+    /// get a snapshot of the runtime metrics
+    pub fn metrics(&self) -> Metrics {
+        self.sched.metrics()
+    }
+
+    /// dump all the live coroutines of the runtime
+    #[cfg(feature = "task_dump")]
+    pub fn dump(&self) -> Dump {
+        Dump::new(self.sched.registry.snapshot())
+    }
+
+    /// get the clock of the runtime timers
+    pub fn clock(&self) -> Clock<'_> {
+        Clock::new(self.sched)
+    }
+
+    /// Gracefully shutdown the runtime and join all its threads.
+    ///
+    /// New coroutines can't be spawned on the runtime any more. The live
+    /// coroutines are given `timeout` to finish, the remaining ones are then
+    /// canceled and given `timeout` again to unwind. At last the threads are
+    /// given `timeout` to exit, a thread that is still blocked, e.g. by a
+    /// coroutine in a thread blocking call, is detached instead of joined.
+    ///
+    /// It's an error to call this function from the runtime's own coroutines.
+    pub fn shutdown(self, timeout: Duration) -> io::Result<ShutdownReport> {
+        self.sched.shutdown(timeout)
+    }
+}
+
+impl Drop for Runtime {
+    fn drop(&mut self) {
+        if !self.sched.is_closed() {
+            if let Err(e) = self.sched.shutdown(Duration::from_millis(100)) {
+                error!("failed to shutdown runtime, err = {:?}", e);
+                return;
+            }
+        }
+        // the scheduler is not used by the runtime any more
+        unsafe { self.sched.release() };
+    }
+}
+
+impl fmt::Debug for Runtime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Runtime")
+            .field("workers", &self.workers())
+            .finish()
+    }
+}
+
+/// A coroutine runtime that is driven by the calling thread
+///
+/// The thread that owns the runtime runs the io event loop, the timers and
+/// all the coroutines by itself when it calls [`block_on`] or [`turn`], so the
+/// coroutines can safely use the `!Send` data. Other threads can still wake
+/// up its coroutines, e.g. by sending to a channel, the coroutines are run by
+/// the owner thread on its next turn. The `spawn_blocking` functions still
+/// run on the blocking thread pool.
+///
+/// It can be embedded into an existing main loop by calling [`turn`]
+/// periodically.
+///
+/// # Examples
+///
+/// ```
+/// use std::rc::Rc;
+/// use may::RuntimeBuilder;
+///
+/// let rt = RuntimeBuilder::new().build_current_thread().unwrap();
+/// let data = Rc::new(1);
+/// let ret = unsafe { rt.block_on(move || *data + 1) };
+/// assert_eq!(ret, 2);
+/// ```
+///
+/// [`block_on`]: struct.CurrentThreadRuntime.html#method.block_on
+/// [`turn`]: struct.CurrentThreadRuntime.html#method.turn
+pub struct CurrentThreadRuntime {
+    sched: &'static Scheduler,
+    // the runtime must be driven by the thread that owns it
+    _not_send: PhantomData<*const ()>,
+}
+
+impl CurrentThreadRuntime {
+    /// create a current thread runtime with the settings of the global config
+    pub fn new() -> io::Result<CurrentThreadRuntime> {
+        RuntimeBuilder::new().build_current_thread()
+    }
+
+    /// Spawns a new coroutine on the runtime, returning a [`JoinHandle`] for it.
+    ///
+    /// The coroutine doesn't run until the runtime is driven.
+    ///
+    /// # Safety
+    ///
+    /// see [`coroutine::spawn`] for the safety requirements
+    ///
+    /// [`JoinHandle`]: coroutine/struct.JoinHandle.html
+    /// [`coroutine::spawn`]: coroutine/fn.spawn.html
+    #[track_caller]
+    pub unsafe fn spawn<F, T>(&self, f: F) -> JoinHandle<T>
+    where
+        F: FnOnce() -> T + 'static,
+        T: 'static,
+    {
+        self.spawn_with(Builder::new(), f).unwrap()
+    }
+
+    /// Spawns a new coroutine configured by the `Builder` on the runtime
+    ///
+    /// # Safety
+    ///
+    /// see [`coroutine::spawn`] for the safety requirements
+    ///
+    /// [`coroutine::spawn`]: coroutine/fn.spawn.html
+    #[track_caller]
+    pub unsafe fn spawn_with<F, T>(&self, builder: Builder, f: F) -> io::Result<JoinHandle<T>>
+    where
+        F: FnOnce() -> T + 'static,
+        T: 'static,
+    {
+        builder.spawn_on_current_thread(self.sched, f)
+    }
+
+    /// Runs the closure as a coroutine and drives the runtime on the current
+    /// thread until it's done, returning the result.
+    ///
+    /// The other coroutines of the runtime also run meanwhile. If the
+    /// coroutine panics, the panic is resumed in the caller.
+    ///
+    /// # Panics
+    ///
+    /// Panics if it's called inside a coroutine or a runtime thread.
+    ///
+    /// # Safety
+    ///
+    /// see [`coroutine::spawn`] for the safety requirements
+    ///
+    /// [`coroutine::spawn`]: coroutine/fn.spawn.html
+    #[track_caller]
+    pub unsafe fn block_on<F, T>(&self, f: F) -> T
+    where
+        F: FnOnce() -> T + 'static,
+        T: 'static,
+    {
+        let h = self.spawn(f);
+        while !h.is_done() {
+            self.sched.turn(None);
+        }
+        match h.join() {
+            Ok(ret) => ret,
+            Err(e) => panic::resume_unwind(e),
+        }
+    }
+
+    /// Drives the runtime on the current thread for one round
+    ///
+    /// The expired timers and the ready coroutines are run. If there is none,
+    /// it waits at most `timeout` for the io events, the timers or the wakeups
+    /// from other threads, and runs the coroutines that become ready. Pass a
+    /// zero `timeout` to poll the runtime without blocking.
+    ///
+    /// # Panics
+    ///
+    /// Panics if it's called inside a coroutine or a runtime thread.
+    pub fn turn(&self, timeout: Duration) {
+        self.sched.turn(Some(timeout));
+    }
+
+    /// get a snapshot of the runtime metrics
+    pub fn metrics(&self) -> Metrics {
+        self.sched.metrics()
+    }
+
+    /// dump all the live coroutines of the runtime
+    #[cfg(feature = "task_dump")]
+    pub fn dump(&self) -> Dump {
+        Dump::new(self.sched.registry.snapshot())
+    }
+
+    /// get the clock of the runtime timers
+    pub fn clock(&self) -> Clock<'_> {
+        Clock::new(self.sched)
+    }
+
+    #[cfg(feature = "sim")]
+    pub(crate) fn scheduler(&self) -> &'static Scheduler {
+        self.sched
+    }
+
+    /// Gracefully shutdown the runtime
+    ///
+    /// New coroutines can't be spawned on the runtime any more. The runtime
+    /// is driven for at most `timeout` to let the live coroutines finish, the
+    /// remaining ones are then canceled and given `timeout` again to unwind.
+    pub fn shutdown(self, timeout: Duration) -> io::Result<ShutdownReport> {
+        self.sched.shutdown(timeout)
+    }
+}
+
+impl Drop for CurrentThreadRuntime {
+    fn drop(&mut self) {
+        if !self.sched.is_closed() {
+            if let Err(e) = self.sched.shutdown(Duration::from_millis(100)) {
+                error!("failed to shutdown runtime, err = {:?}", e);
+                return;
+            }
+        }
+        // the scheduler is not used by the runtime any more
+        unsafe { self.sched.release() };
+    }
+}
+
+impl fmt::Debug for CurrentThreadRuntime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("CurrentThreadRuntime { .. }")
+    }
+}
+
+/// The result of a runtime shutdown
+#[derive(Debug)]
+pub struct ShutdownReport {
+    alive: Vec<Coroutine>,
+    remaining: Vec<Coroutine>,
+    detached: usize,
+}
+
+impl ShutdownReport {
+    pub(crate) fn new(alive: Vec<Coroutine>, remaining: Vec<Coroutine>, detached: usize) -> Self {
+        ShutdownReport {
+            alive,
+            remaining,
+            detached,
+        }
+    }
+
+    /// the number of the coroutines that were still alive when the shutdown
+    /// timeout expired
+    pub fn alive_count(&self) -> usize {
+        self.alive.len()
+    }
+
+    /// the number of the canceled coroutines that were still alive at last,
+    /// e.g. blocked by a thread blocking call
+    pub fn remaining_count(&self) -> usize {
+        self.remaining.len()
+    }
+
+    /// the coroutines that were still alive when the shutdown timeout expired,
+    /// they are all canceled.
+    pub fn alive(&self) -> &[Coroutine] {
+        &self.alive
+    }
+
+    /// the canceled coroutines that were still alive after they were given
+    /// the timeout again to unwind, e.g. blocked by a thread blocking call
+    pub fn remaining(&self) -> &[Coroutine] {
+        &self.remaining
+    }
+
+    /// the number of runtime threads that didn't exit within the timeout,
+    /// they are detached instead of joined
+    pub fn detached_threads(&self) -> usize {
+        self.detached
+    }
+
+    /// return true if all the coroutines finished before the timeout and all
+    /// the runtime threads are joined
+    pub fn is_clean(&self) -> bool {
+        self.alive.is_empty() && self.detached == 0
+    }
+}
+
+/// Gracefully shutdown the default runtime and join all its threads.
+///
+/// After this call, spawning coroutines with the free functions would fail.
+/// See [`Runtime::shutdown`] for the details. If the default runtime is not
+/// started yet, nothing is started or shut down and an empty report is
+/// returned.
+///
+/// [`Runtime::shutdown`]: struct.Runtime.html#method.shutdown
+pub fn shutdown(timeout: Duration) -> io::Result<ShutdownReport> {
+    if !is_default_started() {
+        return Ok(ShutdownReport::new(Vec::new(), Vec::new(), 0));
+    }
+    default_scheduler().shutdown(timeout)
+}
+
+/// Retire or reactivate the worker threads of the default runtime
+///
+/// See [`Runtime::set_active_workers`] for the details, the max worker number
+/// is set by [`Config::set_max_workers`].
+///
+/// [`Runtime::set_active_workers`]: struct.Runtime.html#method.set_active_workers
+/// [`Config::set_max_workers`]: struct.Config.html#method.set_max_workers
+pub fn set_active_workers(workers: usize) -> io::Result<()> {
+    default_scheduler().set_active_workers(workers)
+}
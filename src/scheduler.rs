@@ -1,26 +1,52 @@
-use std::cell::Cell;
-#[cfg(feature = "work_steal")]
-use std::cell::UnsafeCell;
+#[cfg(feature = "sim")]
+use std::cell::RefCell;
+use std::cell::{Cell, UnsafeCell};
 use std::io;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Once};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use crate::config::config;
-use crate::coroutine_impl::{run_coroutine, CoroutineImpl};
-use crate::io::{EventLoop, Selector};
-use crate::likely::likely;
+use crate::affinity::CpuAffinity;
+use crate::blocking_pool::BlockingPool;
+use crate::config::ThreadHook;
+#[cfg(feature = "task_dump")]
+use crate::coroutine_impl::co_set_state;
+use crate::coroutine_impl::{
+    co_pinned, co_priority, co_scheduler, run_coroutine, Coroutine, CoroutineImpl, Priority,
+};
+#[cfg(feature = "task_dump")]
+use crate::dump::BlockState;
+use crate::io::{EventLoop, IdlePolicy, Selector};
+use crate::likely::{likely, unlikely};
+use crate::live::LiveSet;
+use crate::metrics::{Metrics, WorkerMetrics, WorkerStats};
 use crate::pool::CoroutinePool;
+#[cfg(feature = "task_dump")]
+use crate::registry::Registry;
+use crate::runtime::{RuntimeBuilder, ShutdownReport};
 use crate::sync::AtomicOption;
-use crate::timeout_list;
+use crate::timeout_list::{self, Clock};
+use crate::watchdog::{Watchdog, WorkerClock};
 use crate::yield_now::set_co_para;
 
+use crossbeam::queue::SegQueue;
+use crossbeam::utils::CachePadded;
 use may_queue::mpsc::Queue;
 #[cfg(feature = "work_steal")]
 use may_queue::spmc::{self, Local, Steal};
 #[cfg(not(feature = "work_steal"))]
 use may_queue::spsc::Queue as Local;
+use parking_lot::Mutex;
+
+// every N picks the normal priority queue goes before the high one, must be power of 2
+const NORMAL_PRIORITY_INTERVAL: usize = 8;
+// every N picks the low priority queue goes first, must be power of 2
+const LOW_PRIORITY_INTERVAL: usize = 32;
+// max successive picks from the lifo slot, so that the coroutines that keep
+// waking each other can't starve the local queue
+const MAX_LIFO_PICKS: usize = 3;
 
 // thread id, only workers are normal ones
 #[cfg(nightly)]
@@ -29,51 +55,80 @@ use may_queue::spsc::Queue as Local;
 pub static WORKER_ID: Cell<usize> = Cell::new(!1);
 
 #[cfg(not(nightly))]
-thread_local! { pub static WORKER_ID: Cell<usize> = Cell::new(!1); }
+thread_local! { pub static WORKER_ID: Cell<usize> = const { Cell::new(!1) }; }
+
+// the scheduler that the current thread is working for, null means default
+#[cfg(nightly)]
+#[thread_local]
+static CURRENT_SCHED: Cell<*const Scheduler> = Cell::new(std::ptr::null());
+
+#[cfg(not(nightly))]
+thread_local! { static CURRENT_SCHED: Cell<*const Scheduler> = const { Cell::new(std::ptr::null()) }; }
+
+// set the worker id of the current thread, return the previous one
+#[inline]
+fn set_worker_id(id: usize) -> usize {
+    #[cfg(nightly)]
+    return WORKER_ID.replace(id);
+    #[cfg(not(nightly))]
+    WORKER_ID.with(|worker_id| worker_id.replace(id))
+}
 
 // here we use Arc<AtomicOption<>> for that in the select implementation
-// other event may try to consume the coroutine while timer thread consume it
+// other event may try to consume the coroutine while the worker fires the timer
 type TimerData = Arc<AtomicOption<CoroutineImpl>>;
-type TimerThread = timeout_list::TimerThread<TimerData>;
+type TimerList = timeout_list::TimeOutList<TimerData>;
+
+// take the coroutine of the expired timer and set the timeout result for it
+fn take_expired(c: TimerData) -> Option<CoroutineImpl> {
+    let mut co = c.take()?;
+    set_co_para(&mut co, io::Error::new(io::ErrorKind::TimedOut, "timeout"));
+    Some(co)
+}
+
+// resume the coroutine of the expired timer
+fn on_timer_expired(c: TimerData) {
+    // just re-push the co to the visit list
+    if let Some(co) = take_expired(c) {
+        // s.schedule_global(c);
+        run_coroutine(co);
+    }
+}
+
+// how long a simulation waits for the other threads before it's reported as
+// deadlocked
+#[cfg(feature = "sim")]
+const SIM_DEADLOCK_GRACE: Duration = Duration::from_millis(100);
+
+// get a random number by splitmix64, any state including zero is valid
+#[cfg(feature = "sim")]
+fn splitmix64(state: &Cell<u64>) -> u64 {
+    let s = state.get().wrapping_add(0x9E37_79B9_7F4A_7C15);
+    state.set(s);
+    let mut z = s;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
 
 static mut SCHED: *const Scheduler = std::ptr::null();
 
 #[inline(never)]
 fn init_scheduler() {
-    let workers = config().get_workers();
-    let b: Box<Scheduler> = Scheduler::new(workers);
-    unsafe { SCHED = Box::into_raw(b) };
-
-    // timer thread
-    thread::spawn(move || {
-        // timer function
-        let timer_event_handler = |c: Arc<AtomicOption<CoroutineImpl>>| {
-            // just re-push the co to the visit list
-            if let Some(mut co) = c.take() {
-                // set the timeout result for the coroutine
-                set_co_para(&mut co, io::Error::new(io::ErrorKind::TimedOut, "timeout"));
-                // s.schedule_global(c);
-                run_coroutine(co);
-            }
-        };
+    let sched = Scheduler::new(&RuntimeBuilder::new()).expect("can't create scheduler");
+    unsafe { SCHED = sched };
+    sched.start().expect("can't start scheduler");
+}
 
-        let s = unsafe { &*SCHED };
-        s.timer_thread.run(&timer_event_handler);
-    });
-
-    let core_ids = core_affinity::get_core_ids().unwrap();
-    // io event loop thread
-    for (id, core) in (0..workers).zip(core_ids.into_iter().cycle()) {
-        thread::spawn(move || {
-            core_affinity::set_for_current(core);
-            let s = unsafe { &*SCHED };
-            s.event_loop.run(id);
-        });
-    }
+/// return true if the default scheduler is created
+#[inline]
+pub(crate) fn is_default_started() -> bool {
+    unsafe { !SCHED.is_null() }
 }
 
+/// get the default scheduler that the free functions are working on
 #[inline]
-pub fn get_scheduler() -> &'static Scheduler {
+pub(crate) fn default_scheduler() -> &'static Scheduler {
     unsafe {
         if likely(!SCHED.is_null()) {
             return &*SCHED;
@@ -84,6 +139,82 @@ pub fn get_scheduler() -> &'static Scheduler {
     unsafe { &*SCHED }
 }
 
+#[inline]
+fn current_sched() -> *const Scheduler {
+    #[cfg(nightly)]
+    let s = CURRENT_SCHED.get();
+    #[cfg(not(nightly))]
+    let s = CURRENT_SCHED.with(|s| s.get());
+    s
+}
+
+/// set the scheduler for the current thread, return the previous one
+#[inline]
+pub(crate) fn set_current_sched(sched: *const Scheduler) -> *const Scheduler {
+    #[cfg(nightly)]
+    return CURRENT_SCHED.replace(sched);
+    #[cfg(not(nightly))]
+    CURRENT_SCHED.with(|s| s.replace(sched))
+}
+
+/// get the scheduler of the current context without creating the default one
+#[inline]
+pub(crate) fn try_get_scheduler() -> Option<&'static Scheduler> {
+    let s = current_sched();
+    if !s.is_null() {
+        return Some(unsafe { &*s });
+    }
+    is_default_started().then(default_scheduler)
+}
+
+/// get the scheduler of the current context
+///
+/// this is the scheduler of the running coroutine or the runtime thread,
+/// falls back to the default scheduler for normal threads
+#[inline]
+pub fn get_scheduler() -> &'static Scheduler {
+    let s = current_sched();
+    if likely(!s.is_null()) {
+        return unsafe { &*s };
+    }
+    default_scheduler()
+}
+
+// the states that only the owner worker can access
+struct WorkerLocal {
+    // the coroutine that runs next on the worker
+    lifo_slot: UnsafeCell<Option<CoroutineImpl>>,
+    // the next io timer expiration of the current thread scheduler
+    io_expire: Cell<Option<u64>>,
+    // xorshift state for picking the steal victims
+    #[cfg(feature = "work_steal")]
+    rng: Cell<u64>,
+}
+
+impl WorkerLocal {
+    fn new(_id: usize) -> Self {
+        WorkerLocal {
+            lifo_slot: UnsafeCell::new(None),
+            io_expire: Cell::new(None),
+            // the seed must not be zero
+            #[cfg(feature = "work_steal")]
+            rng: Cell::new((_id as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15)),
+        }
+    }
+
+    // get a random number by xorshift64
+    #[inline]
+    #[cfg(feature = "work_steal")]
+    fn next_rand(&self) -> u64 {
+        let mut x = self.rng.get();
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.rng.set(x);
+        x
+    }
+}
+
 #[repr(align(128))]
 pub struct Scheduler {
     #[cfg(not(feature = "work_steal"))]
@@ -93,13 +224,63 @@ pub struct Scheduler {
     #[cfg(feature = "work_steal")]
     stealers: Vec<Steal<CoroutineImpl>>,
     global_queues: Vec<Queue<CoroutineImpl>>,
+    // the coroutines that are pinned to the workers, never stolen
+    pinned_queues: Vec<Queue<CoroutineImpl>>,
+    next_global: AtomicUsize,
+    // number of the active workers, the others are retired
+    active: AtomicUsize,
+    // shared ready queues of the high and low priority coroutines
+    high_queue: SegQueue<CoroutineImpl>,
+    low_queue: SegQueue<CoroutineImpl>,
     event_loop: EventLoop,
-    timer_thread: TimerThread,
+    // the timers of each worker
+    timers: Vec<CachePadded<TimerList>>,
+    stack_size: usize,
+    // per worker counters
+    stats: Vec<CachePadded<WorkerStats>>,
+    locals: Vec<CachePadded<WorkerLocal>>,
+    #[cfg(feature = "work_steal")]
+    steal_batch: usize,
+    lifo_slot: bool,
+    idle_policy: IdlePolicy,
+    // the calling thread drives the scheduler, there is no runtime threads
+    current_thread: bool,
+    // the PRNG state that picks the next coroutine of the simulation, none
+    // for the normal scheduler
+    #[cfg(feature = "sim")]
+    sim_rng: Option<Cell<u64>>,
+    // the seed of the simulation, reported when it's deadlocked
+    #[cfg(feature = "sim")]
+    sim_seed: u64,
+    // not accept new coroutines when set
+    closed: AtomicBool,
+    // all the threads should exit when set
+    stopped: AtomicBool,
+    threads: Mutex<Vec<thread::JoinHandle<()>>>,
+    watchdog: Option<Watchdog>,
+    affinity: CpuAffinity,
+    thread_stack_size: usize,
+    on_thread_start: Option<ThreadHook>,
+    on_thread_stop: Option<ThreadHook>,
+    // number of the live coroutines
+    live: AtomicUsize,
+    // the handles of the live coroutines, used by the shutdown and watchdog
+    pub(crate) coroutines: LiveSet,
+    #[cfg(feature = "task_dump")]
+    pub registry: Registry,
     pub pool: CoroutinePool,
+    pub(crate) blocking_pool: BlockingPool,
 }
 
 impl Scheduler {
-    pub fn new(workers: usize) -> Box<Self> {
+    /// create a scheduler from the runtime settings
+    ///
+    /// the scheduler is leaked so that all the coroutines, timers and io
+    /// objects can always safely refer to it, a runtime reclaims it by
+    /// `release` after it's shut down
+    pub fn new(builder: &RuntimeBuilder) -> io::Result<&'static Self> {
+        // all the per worker resources are allocated for the max workers
+        let workers = builder.get_max_workers();
         #[cfg(not(feature = "work_steal"))]
         let local_queues = Vec::from_iter((0..workers).map(|_| Local::new()));
 
@@ -112,104 +293,906 @@ impl Scheduler {
 
         let global_queues = Vec::from_iter((0..workers).map(|_| Queue::new()));
 
-        Box::new(Scheduler {
-            pool: CoroutinePool::new(),
-            event_loop: EventLoop::new(workers).expect("can't create event_loop"),
+        // the simulation runs the timers on a clock that only moves when idle
+        #[cfg(feature = "sim")]
+        let sim_rng = builder.get_sim_seed().map(Cell::new);
+        #[cfg(feature = "sim")]
+        let clock = match sim_rng {
+            Some(_) => Clock::paused_at(0),
+            None => Clock::new(),
+        };
+        #[cfg(not(feature = "sim"))]
+        let clock = Clock::new();
+        let clock = Arc::new(clock);
+
+        let stack_size = builder.get_stack_size();
+        let (on_thread_start, on_thread_stop) = builder.get_thread_hooks();
+        let sched = Box::new(Scheduler {
+            pool: CoroutinePool::new(builder.get_pool_capacity(), stack_size),
+            blocking_pool: BlockingPool::new(
+                builder.get_blocking_threads(),
+                builder.get_blocking_idle_timeout(),
+            ),
+            event_loop: EventLoop::new(workers, &clock)?,
             local_queues,
             #[cfg(feature = "work_steal")]
             stealers,
             global_queues,
-            timer_thread: TimerThread::new(),
+            pinned_queues: Vec::from_iter((0..workers).map(|_| Queue::new())),
+            next_global: AtomicUsize::new(0),
+            active: AtomicUsize::new(builder.get_workers()),
+            high_queue: SegQueue::new(),
+            low_queue: SegQueue::new(),
+            stats: Vec::from_iter((0..workers).map(|_| Default::default())),
+            locals: Vec::from_iter((0..workers).map(|id| CachePadded::new(WorkerLocal::new(id)))),
+            #[cfg(feature = "work_steal")]
+            steal_batch: builder.get_steal_batch(),
+            lifo_slot: builder.get_lifo_slot(),
+            idle_policy: builder.get_idle_policy(),
+            current_thread: builder.is_current_thread(),
+            #[cfg(feature = "sim")]
+            sim_rng,
+            #[cfg(feature = "sim")]
+            sim_seed: builder.get_sim_seed().unwrap_or(0),
+            timers: Vec::from_iter(
+                (0..workers).map(|_| CachePadded::new(TimerList::with_clock(clock.clone()))),
+            ),
+            stack_size,
+            closed: AtomicBool::new(false),
+            stopped: AtomicBool::new(false),
+            threads: Mutex::new(Vec::new()),
+            watchdog: builder
+                .get_watchdog()
+                .map(|t| Watchdog::new(workers, t, builder.get_watchdog_callback())),
+            affinity: builder.get_cpu_affinity().clone(),
+            thread_stack_size: builder.get_thread_stack_size(),
+            on_thread_start,
+            on_thread_stop,
+            live: AtomicUsize::new(0),
+            coroutines: LiveSet::new(),
+            #[cfg(feature = "task_dump")]
+            registry: Registry::new(),
+        });
+        Ok(Box::leak(sched))
+    }
+
+    /// spawn all the worker threads
+    pub(crate) fn start(&'static self) -> io::Result<()> {
+        let sched = self as *const Scheduler as usize;
+        let mut threads = self.threads.lock();
+        // the current thread scheduler runs the worker by `turn`
+        let workers = if self.current_thread {
+            0
+        } else {
+            self.global_queues.len()
+        };
+        let core_ids = self.affinity.core_ids();
+        // io event loop thread
+        for id in 0..workers {
+            let core = core_ids.as_ref().map(|ids| ids[id % ids.len()]);
+            threads.push(self.spawn_thread(format!("may-worker-{id}"), move || {
+                if let Some(core) = core {
+                    if !core_affinity::set_for_current(core) {
+                        warn!("failed to pin worker {} to core {}", id, core.id);
+                    }
+                }
+                set_current_sched(sched as *const Scheduler);
+                let s = unsafe { &*(sched as *const Scheduler) };
+                s.event_loop.run(id);
+            })?);
+        }
+
+        if self.watchdog.is_some() {
+            threads.push(self.spawn_thread("may-watchdog".to_owned(), move || {
+                let s = unsafe { &*(sched as *const Scheduler) };
+                if let Some(w) = s.watchdog.as_ref() {
+                    w.run(s);
+                }
+            })?);
+        }
+        Ok(())
+    }
+
+    // spawn a runtime thread that runs the thread hooks around `f`
+    pub(crate) fn spawn_thread<F>(&self, name: String, f: F) -> io::Result<thread::JoinHandle<()>>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let mut builder = thread::Builder::new().name(name);
+        if self.thread_stack_size != 0 {
+            builder = builder.stack_size(self.thread_stack_size);
+        }
+        let on_start = self.on_thread_start.clone();
+        let on_stop = self.on_thread_stop.clone();
+        builder.spawn(move || {
+            if let Some(hook) = on_start {
+                hook.call();
+            }
+            f();
+            if let Some(hook) = on_stop {
+                hook.call();
+            }
         })
     }
 
+    /// return true if the scheduler doesn't accept new coroutines
     #[inline]
-    #[cfg(not(feature = "work_steal"))]
-    pub fn run_queued_tasks(&self, id: usize) {
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    /// return true if the scheduler threads should exit
+    #[inline]
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Acquire)
+    }
+
+    /// shutdown the scheduler and join all its threads
+    ///
+    /// the live coroutines are given `timeout` to finish, the remaining ones
+    /// are canceled and given `timeout` again to unwind. the threads are then
+    /// given `timeout` to exit, the ones that are still blocked are detached
+    pub fn shutdown(&self, timeout: Duration) -> io::Result<ShutdownReport> {
+        if ptr::eq(current_sched(), self) {
+            return Err(io::Error::other(
+                "can't shutdown the scheduler from its own threads",
+            ));
+        }
+
+        if self.closed.swap(true, Ordering::AcqRel) {
+            return Err(io::Error::other("the scheduler is already shut down"));
+        }
+
+        // no new blocking tasks, the queued ones are still run
+        self.blocking_pool.shutdown();
+
+        // drain the live coroutines
+        self.wait_coroutines(timeout);
+        let (alive, remaining) = self.cancel_coroutines(timeout);
+
+        // stop all the threads
+        self.stopped.store(true, Ordering::Release);
+        for id in 0..self.max_workers() {
+            self.get_selector().wakeup(id);
+        }
+        let threads = std::mem::take(&mut *self.threads.lock());
+        for t in threads.iter() {
+            t.thread().unpark();
+        }
+        // a thread blocked by a coroutine or a blocking task may never exit
+        let deadline = Instant::now() + timeout;
+        while (threads.iter().any(|t| !t.is_finished()) || self.blocking_pool.threads() != 0)
+            && Instant::now() < deadline
+        {
+            crate::sleep::sleep(Duration::from_millis(1));
+        }
+        let mut detached = Vec::new();
+        for t in threads {
+            if t.is_finished() {
+                t.join().ok();
+            } else {
+                warn!(
+                    "detach the blocked thread {:?} for shutdown",
+                    t.thread().name()
+                );
+                detached.push(t);
+            }
+        }
+        let report = ShutdownReport::new(alive, remaining, detached.len());
+        // keep the detached threads to check if they exit later
+        *self.threads.lock() = detached;
+
+        // release the cached coroutine stacks
+        self.pool.clear();
+        Ok(report)
+    }
+
+    // return true if nothing refers to the scheduler after it's shut down
+    fn is_quiesced(&self) -> bool {
+        // the unix io objects refer to the scheduler until they are dropped
+        #[cfg(unix)]
+        let io_free = (0..self.max_workers()).all(|id| !self.get_selector().has_io(id));
+        #[cfg(windows)]
+        let io_free = true;
+        self.is_stopped()
+            && io_free
+            && self.live_coroutines() == 0
+            && self.blocking_pool.threads() == 0
+            && self.threads.lock().iter().all(|t| t.is_finished())
+    }
+
+    /// free the scheduler that is created by `new` after it's shut down
+    ///
+    /// it's leaked instead if anything may still refer to it, e.g. a live
+    /// coroutine, a thread that is still running or a registered io object
+    ///
+    /// # Safety
+    ///
+    /// the scheduler must not be used by the caller any more
+    pub(crate) unsafe fn release(&'static self) {
+        if !self.is_quiesced() {
+            warn!("leak the scheduler since it's still in use after shutdown");
+            return;
+        }
+        for t in std::mem::take(&mut *self.threads.lock()) {
+            t.join().ok();
+        }
+        drop(Box::from_raw(self as *const Scheduler as *mut Scheduler));
+    }
+
+    // cancel the live coroutines and wait them to unwind, return the canceled
+    // coroutines and the ones still alive after that
+    fn cancel_coroutines(&self, timeout: Duration) -> (Vec<Coroutine>, Vec<Coroutine>) {
+        let alive = self.coroutines.snapshot();
+        if alive.is_empty() {
+            return (alive, Vec::new());
+        }
+        let ids: Vec<_> = alive.iter().map(|co| co.id()).collect();
+        warn!(
+            "cancel {} live coroutines {:?} for shutdown",
+            ids.len(),
+            ids
+        );
+        for co in alive.iter() {
+            unsafe { co.cancel() };
+        }
+        self.wait_coroutines(timeout);
+        (alive, self.coroutines.snapshot())
+    }
+
+    // wait until all the coroutines are finished or timeout
+    fn wait_coroutines(&self, timeout: Duration) {
+        let deadline = Instant::now() + timeout;
+        while self.live_coroutines() != 0 && Instant::now() < deadline {
+            if self.current_thread {
+                self.turn(Some(Duration::from_millis(1)));
+            } else {
+                crate::sleep::sleep(Duration::from_millis(1));
+            }
+        }
+    }
+
+    /// return true if the scheduler is driven by the calling thread
+    #[inline]
+    pub(crate) fn is_current_thread(&self) -> bool {
+        self.current_thread
+    }
+
+    /// Drive the current thread scheduler on the calling thread for one round
+    ///
+    /// The expired timers and the ready coroutines are run first. If none is
+    /// run it then waits for the io events or wakeups at most `timeout`, and
+    /// runs the coroutines that become ready. `None` means waiting until the
+    /// next event or timer.
+    pub(crate) fn turn(&self, timeout: Option<Duration>) {
+        debug_assert!(self.current_thread);
+        assert!(
+            current_sched().is_null(),
+            "can't drive a current thread runtime inside a coroutine or runtime thread"
+        );
+        let prev = set_current_sched(self);
+        let prev_id = set_worker_id(0);
+        #[cfg(feature = "sim")]
+        let deadlocked = match self.sim_rng.as_ref() {
+            Some(rng) => !self.sim_turn(rng, timeout),
+            None => {
+                self.poll_once(timeout);
+                false
+            }
+        };
+        #[cfg(not(feature = "sim"))]
+        self.poll_once(timeout);
+        set_worker_id(prev_id);
+        set_current_sched(prev);
+        #[cfg(feature = "sim")]
+        if deadlocked {
+            panic!(
+                "may simulation is deadlocked with seed {0}, no coroutine is ready and \
+                 nothing could wake any up, rerun with MAY_SIM_SEED={0}",
+                self.sim_seed
+            );
+        }
+    }
+
+    // run the expired timers and the ready coroutines, then wait for the io
+    // events if nothing is run
+    fn poll_once(&self, timeout: Option<Duration>) {
+        let local = unsafe { self.locals.get_unchecked(0) };
+
+        let expired = Cell::new(0);
+        let next_timer = self.poll_timers(0, &|c| {
+            expired.set(expired.get() + 1);
+            on_timer_expired(c);
+        });
+        let ran = expired.get() + self.run_queued_tasks(0);
+        let wait = if ran > 0 {
+            Some(0)
+        } else {
+            [
+                timeout.map(timeout_list::dur_to_ns),
+                next_timer,
+                local.io_expire.get(),
+            ]
+            .into_iter()
+            .flatten()
+            .min()
+        };
+        match self.event_loop.poll(0, wait) {
+            Ok(t) => local.io_expire.set(t),
+            Err(e) => error!("select error = {:?}", e),
+        }
+    }
+
+    // one step of the simulation, run one ready coroutine picked by the PRNG.
+    // If none is ready the virtual clock jumps to the next timer, and without
+    // any timer it waits for the io events and the wakeups at most `timeout`.
+    // Return false if it's deadlocked, that is nothing is ready and only the
+    // other threads could wake up a coroutine, but they don't in a while
+    #[cfg(feature = "sim")]
+    fn sim_turn(&self, rng: &Cell<u64>, timeout: Option<Duration>) -> bool {
+        let ready = RefCell::new(Vec::new());
+        let on_expired = |c: TimerData| ready.borrow_mut().extend(take_expired(c));
+        self.take_ready(0, &mut ready.borrow_mut());
+        while ready.borrow().is_empty() {
+            match self.poll_timers(0, &on_expired) {
+                Some(next) => self.clock().advance(next),
+                None => break,
+            }
+        }
+
+        let mut ready = ready.into_inner();
+        if ready.is_empty() {
+            let local = unsafe { self.locals.get_unchecked(0) };
+            let stuck = timeout.is_none()
+                && local.io_expire.get().is_none()
+                && !self.get_selector().has_io(0)
+                && !self.blocking_pool.is_busy();
+            let wait = if stuck {
+                Some(timeout_list::dur_to_ns(SIM_DEADLOCK_GRACE))
+            } else {
+                [timeout.map(timeout_list::dur_to_ns), local.io_expire.get()]
+                    .into_iter()
+                    .flatten()
+                    .min()
+            };
+            let stats = self.worker_stats(0);
+            let notified = stats.notified.get();
+            match self.event_loop.poll(0, wait) {
+                Ok(t) => local.io_expire.set(t),
+                Err(e) => error!("select error = {:?}", e),
+            }
+            return !stuck || stats.notified.get() != notified;
+        }
+        let co = ready.swap_remove(splitmix64(rng) as usize % ready.len());
+        for co in ready {
+            self.schedule_pinned(co, 0);
+        }
+        run_coroutine(co);
+        true
+    }
+
+    // take all the queued coroutines of the worker
+    #[cfg(feature = "sim")]
+    fn take_ready(&self, id: usize, ready: &mut Vec<CoroutineImpl>) {
+        self.collect_global(id);
+        ready.extend(self.lifo_slot(id).take());
+        let pinned = unsafe { self.pinned_queues.get_unchecked(id) };
+        ready.extend(std::iter::from_fn(|| pinned.pop()));
+        ready.extend(std::iter::from_fn(|| self.pop_local(id)));
+        ready.extend(std::iter::from_fn(|| self.high_queue.pop()));
+        ready.extend(std::iter::from_fn(|| self.low_queue.pop()));
+    }
+
+    /// the time source of the timers
+    #[inline]
+    pub(crate) fn clock(&self) -> &Arc<Clock> {
+        unsafe { self.timers.get_unchecked(0) }.clock()
+    }
+
+    /// move the clock of the timers forward, the due timers are fired
+    ///
+    /// in a thread outside of the runtime, the clock steps through each
+    /// pending deadline of all the workers, and waits for the workers to run
+    /// the woken coroutines before the next step, so that the timers fire in
+    /// the order of their deadlines across the workers. Otherwise the clock
+    /// jumps at once, and the order only holds within a worker
+    pub(crate) fn advance_clock(&self, dur: Duration) {
+        let clock = self.clock();
+        let dur = timeout_list::dur_to_ns(dur);
+        // waiting in a coroutine or a runtime thread could block the timers,
+        // and nobody else runs the timers of the current thread runtime
+        let step = !self.current_thread && current_sched().is_null();
+        if !step {
+            clock.advance(dur);
+            self.wakeup_all();
+            return;
+        }
+
+        let end = clock.now().saturating_add(dur);
+        loop {
+            let next = self.wait_timers(clock.now());
+            let now = clock.now();
+            if next >= end {
+                clock.advance(end.saturating_sub(now));
+                self.wakeup_all();
+                self.wait_timers(clock.now());
+                return;
+            }
+            clock.advance(next.saturating_sub(now));
+            self.wakeup_all();
+        }
+    }
+
+    // wait until all the timers that are due at `now` are fired and the
+    // workers are done with them, return the next time to check the timers
+    fn wait_timers(&self, now: u64) -> u64 {
+        loop {
+            let next = self
+                .timers
+                .iter()
+                .map(|t| t.next_wake())
+                .chain(std::iter::once(self.get_selector().next_timer_wake()))
+                .min()
+                .unwrap_or(u64::MAX);
+            if next > now {
+                return next;
+            }
+            thread::sleep(Duration::from_micros(50));
+        }
+    }
+
+    // let the workers catch up with the timers
+    fn wakeup_all(&self) {
+        for id in 0..self.max_workers() {
+            self.get_selector().wakeup(id);
+        }
+    }
+
+    /// the number of active worker threads
+    #[inline]
+    pub fn workers(&self) -> usize {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    /// the max number of worker threads
+    #[inline]
+    pub fn max_workers(&self) -> usize {
+        self.global_queues.len()
+    }
+
+    /// retire or reactivate the worker threads
+    ///
+    /// all the worker threads are started up front, since the pinned
+    /// coroutines can be put on any of them, the workers with id not less
+    /// than `workers` are retired. A retired worker moves its queued
+    /// coroutines to the active ones. On unix each worker moves the io
+    /// objects that belong to another worker under the new count to that
+    /// worker's selector, so a retired worker polls no io objects. On windows
+    /// the io handles stay on the completion port they are associated with.
+    pub fn set_active_workers(&self, workers: usize) -> io::Result<()> {
+        if workers == 0 || workers > self.max_workers() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("workers must be in 1..={}", self.max_workers()),
+            ));
+        }
+        let old = self.active.swap(workers, Ordering::AcqRel);
+        info!("set active workers {} -> {}", old, workers);
+        // let the retired workers move out their coroutines, and all the
+        // workers move their io objects
+        for id in 0..self.max_workers() {
+            self.get_selector().wakeup(id);
+        }
+        Ok(())
+    }
+
+    /// return true if the worker is retired
+    #[inline]
+    pub(crate) fn is_retired(&self, id: usize) -> bool {
+        id >= self.workers()
+    }
+
+    // move all the queued coroutines of the retired worker to the active ones
+    #[cold]
+    fn drain_retired(&self, id: usize) {
+        self.collect_global(id);
+        #[cfg(feature = "work_steal")]
+        let local = unsafe { &mut *self.local_queues.get_unchecked(id).get() };
+        #[cfg(not(feature = "work_steal"))]
         let local = unsafe { self.local_queues.get_unchecked(id) };
+        let stats = self.worker_stats(id);
         while let Some(co) = local.pop() {
-            run_coroutine(co);
+            stats.popped.add(1);
+            self.schedule_global(co);
+        }
+        if let Some(co) = self.lifo_slot(id).take() {
+            self.schedule_global(co);
         }
     }
 
+    // the lifo slot of the worker, must only be accessed by the owner worker
     #[inline]
-    #[cfg(feature = "work_steal")]
-    pub fn run_queued_tasks(&self, id: usize) {
-        let local = unsafe { &mut *self.local_queues.get_unchecked(id).get() };
+    #[allow(clippy::mut_from_ref)]
+    fn lifo_slot(&self, id: usize) -> &mut Option<CoroutineImpl> {
+        unsafe { &mut *self.locals.get_unchecked(id).lifo_slot.get() }
+    }
 
-        let len = self.local_queues.len();
-        let max_steal = std::cmp::min(len - 1, 3);
-        let mut next_id = id;
-        let mut state = 0;
-        loop {
-            let co = match state {
-                0 => match local.pop() {
-                    Some(co) => co,
-                    None => {
-                        state += 1;
-                        continue;
-                    }
-                },
-                n if n >= max_steal => return,
-                _ => {
-                    let n = next_id + 1;
-                    next_id = if n == len { 0 } else { n };
-                    let stealer = unsafe { self.stealers.get_unchecked(next_id) };
-                    match stealer.steal_into(local) {
-                        Some(co) => {
-                            state = 0;
-                            co
-                        }
-                        None => {
-                            state += 1;
-                            continue;
-                        }
-                    }
-                }
-            };
-            run_coroutine(co);
+    // take the coroutine in the lifo slot, it's moved to the local queue
+    // instead if the slot is picked too many times in a row
+    #[inline]
+    fn pop_lifo(&self, id: usize, lifo_picks: &mut usize) -> Option<CoroutineImpl> {
+        let co = self.lifo_slot(id).take()?;
+        if *lifo_picks < MAX_LIFO_PICKS {
+            *lifo_picks += 1;
+            return Some(co);
         }
+        *lifo_picks = 0;
+        self.push_local(co, id);
+        None
     }
 
-    /// put the coroutine to correct queue so that next time it can be scheduled
+    /// get the counters of the worker
     #[inline]
-    pub fn schedule(&self, co: CoroutineImpl) {
+    pub(crate) fn worker_stats(&self, id: usize) -> &WorkerStats {
+        unsafe { self.stats.get_unchecked(id) }
+    }
+
+    /// get the watchdog clock of the current thread if it's a worker of the
+    /// scheduler and the watchdog is enabled
+    #[inline]
+    pub(crate) fn watchdog_clock(&self, cur: *const Scheduler) -> Option<&WorkerClock> {
+        let watchdog = self.watchdog.as_ref()?;
+        if !ptr::eq(cur, self) {
+            return None;
+        }
         #[cfg(nightly)]
         let id = WORKER_ID.get();
         #[cfg(not(nightly))]
         let id = WORKER_ID.with(|id| id.get());
+        if id == !1 {
+            return None;
+        }
+        Some(watchdog.clock(id))
+    }
 
-        if id != !1 {
-            self.schedule_with_id(co, id);
-        } else {
-            self.schedule_global(co);
+    /// get the worker id of the current thread if it's a worker of the scheduler
+    #[inline]
+    pub(crate) fn current_worker(&self) -> Option<usize> {
+        if !ptr::eq(current_sched(), self) {
+            return None;
         }
+        #[cfg(nightly)]
+        let id = WORKER_ID.get();
+        #[cfg(not(nightly))]
+        let id = WORKER_ID.with(|id| id.get());
+        (id != !1).then_some(id)
+    }
+
+    /// get a snapshot of the scheduler metrics
+    pub fn metrics(&self) -> Metrics {
+        let workers = (0..self.max_workers())
+            .map(|id| {
+                let stats = self.worker_stats(id);
+                WorkerMetrics {
+                    local_queue_len: stats.local_queue_len(),
+                    global_queue_len: unsafe { self.global_queues.get_unchecked(id) }.len(),
+                    steal_count: stats.steals.get(),
+                    stolen_coroutines: stats.stolen.get(),
+                    select_count: stats.selects.get(),
+                    event_count: stats.events.get(),
+                    park_count: stats.parks.get(),
+                    notify_count: stats.notified.get(),
+                    spin_hit_count: stats.spin_hits.get(),
+                    idle_wakeup_count: stats.idle_wakeups.get(),
+                }
+            })
+            .collect();
+
+        Metrics {
+            workers,
+            active_workers: self.workers(),
+            live_coroutines: self.live_coroutines(),
+            pending_timers: self.timers.iter().map(|t| t.pending()).sum::<usize>()
+                + self.get_selector().pending_timers(),
+            pool: self.pool.metrics(),
+        }
+    }
+
+    /// add a newly spawned coroutine, it's registered for the task dump
+    #[inline]
+    pub(crate) fn add_coroutine(&self, co: &Coroutine) {
+        co.set_live_slot(self.coroutines.insert(co));
+        #[cfg(feature = "task_dump")]
+        self.registry.insert(co);
+        self.live.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// remove a finished coroutine
+    #[inline]
+    pub(crate) fn remove_coroutine(&self, co: &Coroutine) {
+        self.coroutines.remove(co.live_slot());
+        #[cfg(feature = "task_dump")]
+        self.registry.remove(co);
+        self.live.fetch_sub(1, Ordering::Release);
+    }
+
+    /// number of the live coroutines
+    #[inline]
+    pub(crate) fn live_coroutines(&self) -> usize {
+        self.live.load(Ordering::Acquire)
+    }
+
+    /// what the idle workers do when there are no ready coroutines
+    #[inline]
+    pub(crate) fn idle_policy(&self) -> IdlePolicy {
+        self.idle_policy
+    }
+
+    /// the default stack size of the coroutines spawned by this scheduler
+    #[inline]
+    pub fn stack_size(&self) -> usize {
+        self.stack_size
+    }
+
+    #[inline]
+    #[cfg(not(feature = "work_steal"))]
+    fn pop_local(&self, id: usize) -> Option<CoroutineImpl> {
+        let local = unsafe { self.local_queues.get_unchecked(id) };
+        let co = local.pop()?;
+        self.worker_stats(id).popped.add(1);
+        Some(co)
     }
 
-    /// called by selector with known id
     #[inline]
     #[cfg(feature = "work_steal")]
-    pub fn schedule_with_id(&self, co: CoroutineImpl, id: usize) {
+    fn pop_local(&self, id: usize) -> Option<CoroutineImpl> {
         let local = unsafe { &mut *self.local_queues.get_unchecked(id).get() };
-        local.push_back(co);
+        let co = local.pop()?;
+        self.worker_stats(id).popped.add(1);
+        Some(co)
     }
 
     #[inline]
     #[cfg(not(feature = "work_steal"))]
+    fn steal(&self, _id: usize) -> Option<CoroutineImpl> {
+        None
+    }
+
+    // try to steal from the other workers, the victims are visited from a
+    // random one
+    #[cfg(feature = "work_steal")]
+    fn steal(&self, id: usize) -> Option<CoroutineImpl> {
+        let local = unsafe { &mut *self.local_queues.get_unchecked(id).get() };
+        let stats = self.worker_stats(id);
+        let len = self.stealers.len();
+        let start = unsafe { self.locals.get_unchecked(id) }.next_rand() as usize % len;
+        for i in 0..len {
+            let victim = (start + i) % len;
+            if victim == id {
+                continue;
+            }
+            let stealer = unsafe { self.stealers.get_unchecked(victim) };
+            if stealer.is_empty() {
+                continue;
+            }
+            if let (Some(co), n) = stealer.steal_batch_into(local, self.steal_batch) {
+                self.worker_stats(victim).taken.add(n);
+                stats.steals.add(1);
+                stats.stolen.add(n);
+                // the rest are pushed to the local queue
+                stats.pushed.add(n - 1);
+                return Some(co);
+            }
+        }
+        None
+    }
+
+    // pop the lifo slot first, then the local and pinned queues in turn, then
+    // the global queue, and finally try to steal from the other workers
+    #[inline]
+    fn pop_normal(&self, id: usize, tick: usize, lifo_picks: &mut usize) -> Option<CoroutineImpl> {
+        if let Some(co) = self.pop_lifo(id, lifo_picks) {
+            return Some(co);
+        }
+        *lifo_picks = 0;
+        let pinned = unsafe { self.pinned_queues.get_unchecked(id) };
+        if tick & 1 == 0 {
+            if let Some(co) = pinned.pop() {
+                return Some(co);
+            }
+        }
+        if let Some(co) = self.pop_local(id).or_else(|| pinned.pop()) {
+            return Some(co);
+        }
+        // the ready normal coroutines may be still in the global queue
+        self.collect_global(id);
+        self.pop_local(id).or_else(|| self.steal(id))
+    }
+
+    // pick the next coroutine to run by priority, the lower priority queues
+    // go first periodically so that they are not starved
+    #[inline]
+    fn pop_next(&self, id: usize, tick: usize, lifo_picks: &mut usize) -> Option<CoroutineImpl> {
+        if tick & (LOW_PRIORITY_INTERVAL - 1) == 0 {
+            if let Some(co) = self.low_queue.pop() {
+                return Some(co);
+            }
+        }
+        if tick & (NORMAL_PRIORITY_INTERVAL - 1) != 0 {
+            if let Some(co) = self.high_queue.pop() {
+                return Some(co);
+            }
+        }
+        self.pop_normal(id, tick, lifo_picks)
+            .or_else(|| self.high_queue.pop())
+            .or_else(|| self.low_queue.pop())
+    }
+
+    /// run all the ready coroutines of the worker, return the number of runs
+    #[inline]
+    pub fn run_queued_tasks(&self, id: usize) -> usize {
+        // the simulation picks the ready coroutines by itself
+        #[cfg(feature = "sim")]
+        if self.sim_rng.is_some() {
+            return 0;
+        }
+        let mut runs = 0;
+        let mut lifo_picks = 0;
+        let mut tick = 0;
+        loop {
+            // the worker may be retired while running the tasks
+            if unlikely(self.is_retired(id)) {
+                return runs + self.run_retired(id);
+            }
+            tick += 1;
+            match self.pop_next(id, tick, &mut lifo_picks) {
+                Some(co) => run_coroutine(co),
+                None => return runs,
+            }
+            runs += 1;
+        }
+    }
+
+    // the retired worker moves out its queued coroutines and still runs its
+    // pinned coroutines
+    #[cold]
+    fn run_retired(&self, id: usize) -> usize {
+        self.drain_retired(id);
+        let mut runs = 0;
+        let pinned = unsafe { self.pinned_queues.get_unchecked(id) };
+        while let Some(co) = pinned.pop() {
+            run_coroutine(co);
+            runs += 1;
+        }
+        runs
+    }
+
+    /// put the pinned coroutine to the queue of its worker
+    #[inline]
+    pub(crate) fn schedule_pinned(&self, co: CoroutineImpl, worker: usize) {
+        #[cfg(feature = "task_dump")]
+        co_set_state(&co, BlockState::Queued);
+        let queue = unsafe { self.pinned_queues.get_unchecked(worker) };
+        queue.push(co);
+        // the worker would pick it up when done with the current one
+        if self.current_worker() != Some(worker) {
+            self.get_selector().wakeup(worker);
+        }
+    }
+
+    // push the high or low priority coroutine to the shared queue
+    #[inline]
+    fn schedule_priority(&self, co: CoroutineImpl, priority: Priority) {
+        #[cfg(feature = "task_dump")]
+        co_set_state(&co, BlockState::Queued);
+        match priority {
+            Priority::High => self.high_queue.push(co),
+            _ => self.low_queue.push(co),
+        }
+    }
+
+    /// put the coroutine to correct queue so that next time it can be scheduled
+    #[inline]
+    pub fn schedule(&self, co: CoroutineImpl) {
+        // the coroutine always goes back to the scheduler that spawned it
+        let sched = co_scheduler(&co);
+        if unlikely(!ptr::eq(sched, self)) {
+            return sched.schedule(co);
+        }
+        if let Some(worker) = co_pinned(&co) {
+            return self.schedule_pinned(co, worker);
+        }
+
+        // only the worker threads of this scheduler can push to the local queues
+        match self.current_worker() {
+            Some(id) if self.lifo_slot && co_priority(&co) == Priority::Normal => {
+                if self.is_retired(id) {
+                    self.schedule_global(co);
+                } else {
+                    self.schedule_lifo(co, id);
+                }
+            }
+            Some(id) => self.schedule_with_id(co, id),
+            None => self.schedule_global(co),
+        }
+    }
+
+    /// put the coroutine to the back of the queue, the lifo slot is skipped
+    #[inline]
+    pub fn schedule_fifo(&self, co: CoroutineImpl) {
+        let sched = co_scheduler(&co);
+        if unlikely(!ptr::eq(sched, self)) {
+            return sched.schedule_fifo(co);
+        }
+        match self.current_worker() {
+            Some(id) => self.schedule_with_id(co, id),
+            None => self.schedule_global(co),
+        }
+    }
+
+    // put the coroutine to the lifo slot so that it runs next on the worker,
+    // the one that was in the slot goes to the local queue
+    #[inline]
+    fn schedule_lifo(&self, co: CoroutineImpl, id: usize) {
+        #[cfg(feature = "task_dump")]
+        co_set_state(&co, BlockState::Queued);
+        if let Some(prev) = self.lifo_slot(id).replace(co) {
+            self.push_local(prev, id);
+        }
+    }
+
+    /// called by selector with known id
+    #[inline]
     pub fn schedule_with_id(&self, co: CoroutineImpl, id: usize) {
-        let local = unsafe { self.local_queues.get_unchecked(id) };
-        local.push(co);
+        let sched = co_scheduler(&co);
+        if unlikely(!ptr::eq(sched, self)) {
+            return sched.schedule_global(co);
+        }
+        if let Some(worker) = co_pinned(&co) {
+            return self.schedule_pinned(co, worker);
+        }
+        if unlikely(self.is_retired(id)) {
+            return self.schedule_global(co);
+        }
+        let priority = co_priority(&co);
+        if priority != Priority::Normal {
+            // the running worker would pick it up
+            return self.schedule_priority(co, priority);
+        }
+        #[cfg(feature = "task_dump")]
+        co_set_state(&co, BlockState::Queued);
+        self.push_local(co, id);
+    }
+
+    // push the coroutine to the back of the local queue
+    #[inline]
+    fn push_local(&self, co: CoroutineImpl, id: usize) {
+        #[cfg(feature = "work_steal")]
+        unsafe { &mut *self.local_queues.get_unchecked(id).get() }.push_back(co);
+        #[cfg(not(feature = "work_steal"))]
+        unsafe { self.local_queues.get_unchecked(id) }.push(co);
+        self.worker_stats(id).pushed.add(1);
     }
 
     /// put the coroutine to global queue so that next time it can be scheduled
     #[inline]
     pub fn schedule_global(&self, co: CoroutineImpl) {
-        static NEXT_THREAD_ID: AtomicUsize = AtomicUsize::new(0);
-        let thread_id = NEXT_THREAD_ID
+        if let Some(worker) = co_pinned(&co) {
+            return self.schedule_pinned(co, worker);
+        }
+        let thread_id = self
+            .next_global
             .fetch_add(1, Ordering::Relaxed)
-            .rem_euclid(self.global_queues.len());
-        let global = unsafe { self.global_queues.get_unchecked(thread_id) };
-        global.push(co);
+            .rem_euclid(self.workers());
+        let priority = co_priority(&co);
+        if priority != Priority::Normal {
+            self.schedule_priority(co, priority);
+        } else {
+            #[cfg(feature = "task_dump")]
+            co_set_state(&co, BlockState::Queued);
+            let global = unsafe { self.global_queues.get_unchecked(thread_id) };
+            global.push(co);
+        }
         // signal one waiting thread if any
         self.get_selector().wakeup(thread_id);
     }
@@ -217,9 +1200,19 @@ impl Scheduler {
     /// put the coroutine to global queue so that next time it can be scheduled
     #[inline]
     pub fn schedule_global_with_id(&self, co: CoroutineImpl, id: usize) {
-        let thread_id = id.rem_euclid(self.global_queues.len());
-        let global = unsafe { self.global_queues.get_unchecked(thread_id) };
-        global.push(co);
+        if let Some(worker) = co_pinned(&co) {
+            return self.schedule_pinned(co, worker);
+        }
+        let thread_id = id.rem_euclid(self.workers());
+        let priority = co_priority(&co);
+        if priority != Priority::Normal {
+            self.schedule_priority(co, priority);
+        } else {
+            #[cfg(feature = "task_dump")]
+            co_set_state(&co, BlockState::Queued);
+            let global = unsafe { self.global_queues.get_unchecked(thread_id) };
+            global.push(co);
+        }
         // signal one waiting thread if any
         self.get_selector().wakeup(thread_id);
     }
@@ -231,8 +1224,10 @@ impl Scheduler {
         #[cfg(not(feature = "work_steal"))]
         let local = unsafe { self.local_queues.get_unchecked(id) };
         let global = unsafe { self.global_queues.get_unchecked(id) };
+        let stats = self.worker_stats(id);
         let mut v = global.bulk_pop();
         while !v.is_empty() {
+            stats.pushed.add(v.len());
             for co in v {
                 #[cfg(feature = "work_steal")]
                 local.push_back(co);
@@ -243,18 +1238,52 @@ impl Scheduler {
         }
     }
 
+    /// add a timer that resumes the coroutine with a timeout error
+    ///
+    /// a worker keeps the timers of its own coroutines, the timers from the
+    /// other threads are spread over the workers
     #[inline]
     pub fn add_timer(
         &self,
         dur: Duration,
         co: Arc<AtomicOption<CoroutineImpl>>,
     ) -> timeout_list::TimeoutHandle<TimerData> {
-        self.timer_thread.add_timer(dur, co)
+        let (id, local) = match self.current_worker() {
+            Some(id) => (id, true),
+            None => {
+                let id = self.next_global.fetch_add(1, Ordering::Relaxed);
+                (id.rem_euclid(self.workers()), false)
+            }
+        };
+        let timers = unsafe { self.timers.get_unchecked(id) };
+        let (h, wake) = timers.add_timer(dur, co);
+        // the worker itself always checks the timers before waiting
+        if wake && !local {
+            self.get_selector().wakeup(id);
+        }
+        h
     }
 
     #[inline]
     pub fn del_timer(&self, handle: timeout_list::TimeoutHandle<TimerData>) {
-        self.timer_thread.del_timer(handle);
+        handle.remove();
+    }
+
+    // fire the expired timers of the worker with `f`
+    // return the time in ns for the next expiration
+    fn poll_timers<F: Fn(TimerData)>(&self, id: usize, f: &F) -> Option<u64> {
+        let timers = unsafe { self.timers.get_unchecked(id) };
+        timers.schedule_timer(timers.clock().now(), f)
+    }
+
+    /// fire the expired timers of the worker, the timed out coroutines are
+    /// scheduled to the worker, return the time in ns for the next expiration
+    pub(crate) fn run_timers(&self, id: usize) -> Option<u64> {
+        self.poll_timers(id, &|c| {
+            if let Some(co) = take_expired(c) {
+                self.schedule_with_id(co, id);
+            }
+        })
     }
 
     #[inline]
@@ -8,11 +8,13 @@ use std::rc::Rc;
 use std::sync::Arc;
 use std::thread;
 
-use crate::coroutine_impl::{spawn, Coroutine};
+use crate::coroutine_impl::{current_cancel_data, is_coroutine, spawn, Coroutine};
 use crate::join::JoinHandle;
-use crate::sync::AtomicOption;
+use crate::local::get_co_local_data;
+use crate::sync::{AtomicOption, CancellationToken};
 
 /// Like `coroutine::spawn`, but without the closure bounds.
+#[track_caller]
 pub unsafe fn spawn_unsafe<'a, F>(f: F) -> JoinHandle<()>
 where
     F: FnOnce() + Send + 'a,
@@ -24,6 +26,8 @@ where
 
 pub struct Scope<'a> {
     dtors: RefCell<Option<DtorChain<'a>>>,
+    // cancelled when the scope ends
+    token: CancellationToken,
 }
 
 struct DtorChain<'a> {
@@ -41,7 +45,16 @@ impl JoinState {
         let mut state = JoinState::Joined;
         mem::swap(self, &mut state);
         if let JoinState::Running(handle) = state {
+            // the coroutine borrows the stack, so wait for it even if the
+            // caller is cancelled or passes its deadline
+            let cancel = is_coroutine().then(current_cancel_data);
+            if let Some(c) = cancel {
+                c.disable_cancel();
+            }
             let res = handle.join();
+            if let Some(c) = cancel {
+                c.enable_cancel();
+            }
 
             // TODO: when panic happened, the logic need to refine
             if !thread::panicking() {
@@ -62,12 +75,23 @@ pub struct ScopedJoinHandle<T> {
 ///
 /// Scopes, in particular, support scoped coroutine spawning.
 ///
+/// The scope owns a [`CancellationToken`] which is cancelled when the scope
+/// ends, or right away when the scope panics so that the scoped coroutines
+/// can stop before they are joined. If the scope is created in a scoped
+/// coroutine, the token is a child of the outer scope token.
+///
+/// [`CancellationToken`]: ../sync/struct.CancellationToken.html
 pub fn scope<'a, F, R>(f: F) -> R
 where
     F: FnOnce(&Scope<'a>) -> R,
 {
+    let token = match cancellation_token() {
+        Some(parent) => parent.child_token(),
+        None => CancellationToken::new(),
+    };
     let mut scope = Scope {
         dtors: RefCell::new(None),
+        token,
     };
     let ret = f(&scope);
     scope.drop_all();
@@ -131,6 +155,7 @@ impl<'a> Scope<'a> {
     /// before the current stack frame goes away, allowing you to reference the parent stack frame
     /// directly. This is ensured by having the parent join on the child coroutine before the
     /// scope exits.
+    #[track_caller]
     fn spawn_impl<F, T>(&self, f: F) -> ScopedJoinHandle<T>
     where
         F: FnOnce() -> T + Send + 'a,
@@ -139,8 +164,16 @@ impl<'a> Scope<'a> {
         let their_packet = Arc::new(AtomicOption::none());
         let my_packet = their_packet.clone();
 
+        let token = self.token.clone();
+        // the scoped coroutines inherit the deadline
+        let deadline = crate::coroutine_impl::deadline();
         let join_handle = unsafe {
             spawn_unsafe(move || {
+                if let Some(local) = get_co_local_data() {
+                    let local = local.as_ref();
+                    local.set_token(token);
+                    local.set_deadline(deadline);
+                }
                 their_packet.store(f());
             })
         };
@@ -168,6 +201,7 @@ impl<'a> Scope<'a> {
     /// before the current stack frame goes away, allowing you to reference the parent stack frame
     /// directly. This is ensured by having the parent join on the child coroutine before the
     /// scope exits.
+    #[track_caller]
     pub unsafe fn spawn<F, T>(&self, f: F) -> ScopedJoinHandle<T>
     where
         F: FnOnce() -> T + Send + 'a,
@@ -175,6 +209,21 @@ impl<'a> Scope<'a> {
     {
         self.spawn_impl(f)
     }
+
+    /// The cancellation token of the scope, cancel it to tell all the scoped
+    /// coroutines to stop
+    pub fn token(&self) -> &CancellationToken {
+        &self.token
+    }
+}
+
+/// get the cancellation token of the scope that spawned the current coroutine
+///
+/// return none if the current coroutine is not spawned by a scope, or it's
+/// called in a thread
+pub fn cancellation_token() -> Option<CancellationToken> {
+    let local = get_co_local_data()?;
+    unsafe { local.as_ref() }.get_token()
 }
 
 impl<T> ScopedJoinHandle<T> {
@@ -192,6 +241,11 @@ impl<T> ScopedJoinHandle<T> {
 
 impl<'a> Drop for Scope<'a> {
     fn drop(&mut self) {
-        self.drop_all()
+        // let the scoped coroutines stop early when unwinding
+        if thread::panicking() {
+            self.token.cancel();
+        }
+        self.drop_all();
+        self.token.cancel();
     }
 }
@@ -0,0 +1,171 @@
+//! Deterministic simulation of the coroutines for reproducible tests
+//!
+//! A [`Simulation`] is a current thread runtime that runs all its coroutines
+//! on the calling thread, and picks the next one to run from all the ready
+//! coroutines with a PRNG seeded by the given seed. The timers, e.g. `sleep`,
+//! `park_timeout` and the io timeouts, run on a paused [clock] that jumps to
+//! the next timer whenever no coroutine is ready, so a long sleep returns right
+//! away. Running the same code with the same seed replays the exact same
+//! interleaving, which turns a rare race into a reproducible test.
+//!
+//! [`run`] picks the seed from the `MAY_SIM_SEED` environment variable or a
+//! random one, and prints it when the simulation panics, so that a failed
+//! test can be replayed by setting `MAY_SIM_SEED` to the printed seed.
+//!
+//! The simulation is only deterministic for the coroutines that communicate
+//! with each other. Real io, `spawn_blocking` and the wakeups from other
+//! threads depend on the os.
+//!
+//! When no coroutine is ready and there is no timer, io object or
+//! `spawn_blocking` job left to wake one up, the simulation waits a short
+//! while for the wakeups from other threads, and then panics as deadlocked
+//! with the seed in the message.
+//!
+//! This module is only available with the `sim` feature.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::time::Duration;
+//! use may::sim;
+//!
+//! let ret = unsafe {
+//!     sim::run(|| {
+//!         // returns at once in the virtual time
+//!         may::coroutine::sleep(Duration::from_secs(3600));
+//!         42
+//!     })
+//! };
+//! assert_eq!(ret, 42);
+//! ```
+//!
+//! [`Simulation`]: struct.Simulation.html
+//! [`run`]: fn.run.html
+//! [clock]: ../time/index.html
+
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::panic;
+use std::time::{Duration, SystemTime};
+
+use crate::join::JoinHandle;
+use crate::runtime::{CurrentThreadRuntime, RuntimeBuilder};
+use crate::timeout_list::ns_to_dur;
+
+/// A deterministic simulation runtime
+///
+/// see the [module level documentation](index.html) for more
+pub struct Simulation {
+    rt: CurrentThreadRuntime,
+    seed: u64,
+}
+
+impl Simulation {
+    /// create a simulation with the given seed
+    pub fn new(seed: u64) -> io::Result<Simulation> {
+        let rt = RuntimeBuilder::new().build_simulation(seed)?;
+        Ok(Simulation { rt, seed })
+    }
+
+    /// the seed of the simulation
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// the virtual time that passed since the simulation is created
+    pub fn elapsed(&self) -> Duration {
+        ns_to_dur(self.rt.scheduler().clock().now())
+    }
+
+    /// Spawns a new coroutine in the simulation, returning a [`JoinHandle`]
+    /// for it. The coroutine doesn't run until the simulation is driven.
+    ///
+    /// # Safety
+    ///
+    /// see [`coroutine::spawn`] for the safety requirements
+    ///
+    /// [`JoinHandle`]: ../coroutine/struct.JoinHandle.html
+    /// [`coroutine::spawn`]: ../coroutine/fn.spawn.html
+    #[track_caller]
+    pub unsafe fn spawn<F, T>(&self, f: F) -> JoinHandle<T>
+    where
+        F: FnOnce() -> T + 'static,
+        T: 'static,
+    {
+        self.rt.spawn(f)
+    }
+
+    /// Runs the closure as a coroutine and drives the simulation until it's
+    /// done, returning the result.
+    ///
+    /// If the coroutine panics, the seed is printed to the stderr and the
+    /// panic is resumed in the caller.
+    ///
+    /// # Safety
+    ///
+    /// see [`coroutine::spawn`] for the safety requirements
+    ///
+    /// [`coroutine::spawn`]: ../coroutine/fn.spawn.html
+    #[track_caller]
+    pub unsafe fn block_on<F, T>(&self, f: F) -> T
+    where
+        F: FnOnce() -> T + 'static,
+        T: 'static,
+    {
+        let ret = panic::catch_unwind(panic::AssertUnwindSafe(|| self.rt.block_on(f)));
+        ret.unwrap_or_else(|e| {
+            eprintln!(
+                "may simulation failed with seed {0}, rerun with MAY_SIM_SEED={0}",
+                self.seed
+            );
+            panic::resume_unwind(e)
+        })
+    }
+}
+
+impl fmt::Debug for Simulation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Simulation")
+            .field("seed", &self.seed)
+            .finish()
+    }
+}
+
+/// get the seed for a new simulation
+///
+/// this is the value of the `MAY_SIM_SEED` environment variable if it's set,
+/// or else a random one
+pub fn seed() -> u64 {
+    if let Ok(s) = std::env::var("MAY_SIM_SEED") {
+        match s.trim().parse() {
+            Ok(seed) => return seed,
+            Err(_) => warn!("invalid MAY_SIM_SEED={:?}, use a random seed", s),
+        }
+    }
+    let mut hasher = RandomState::new().build_hasher();
+    if let Ok(t) = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        hasher.write_u128(t.as_nanos());
+    }
+    hasher.finish()
+}
+
+/// Runs the closure in a new simulation seeded by [`seed`], returning the
+/// result. The seed is printed if the closure panics.
+///
+/// # Safety
+///
+/// see [`coroutine::spawn`] for the safety requirements
+///
+/// [`seed`]: fn.seed.html
+/// [`coroutine::spawn`]: ../coroutine/fn.spawn.html
+#[track_caller]
+pub unsafe fn run<F, T>(f: F) -> T
+where
+    F: FnOnce() -> T + 'static,
+    T: 'static,
+{
+    let sim = Simulation::new(seed()).expect("failed to create the simulation");
+    sim.block_on(f)
+}
@@ -1,9 +1,13 @@
 use crate::sync::AtomicOption;
 use std::sync::Arc;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use crate::coroutine_impl::{co_cancel_data, is_coroutine, CoroutineImpl, EventSource};
+use crate::coroutine_impl::{
+    co_cancel_data, current_timeout, is_coroutine, CoroutineImpl, EventSource,
+};
+#[cfg(feature = "task_dump")]
+use crate::dump::BlockState;
 use crate::likely::unlikely;
 use crate::scheduler::get_scheduler;
 use crate::yield_now::{get_co_para, yield_with};
@@ -13,6 +17,11 @@ struct Sleep {
 }
 
 impl EventSource for Sleep {
+    #[cfg(feature = "task_dump")]
+    fn block_state(&self) -> BlockState {
+        BlockState::Sleeping(crate::timeout_list::now() + self.dur.as_nanos() as u64)
+    }
+
     // register the coroutine to the park
     fn subscribe(&mut self, co: CoroutineImpl) {
         let cancel = co_cancel_data(&co);
@@ -35,8 +44,20 @@ pub fn sleep(dur: Duration) {
         return thread::sleep(dur);
     }
 
+    // the deadline of the coroutine caps the sleep
+    let dur = current_timeout(Some(dur)).unwrap_or(dur);
     let sleeper = Sleep { dur };
     yield_with(&sleeper);
     // consume the timeout error
     get_co_para();
 }
+
+/// block the current coroutine until the deadline of the runtime clock
+///
+/// it returns at once if the deadline is already passed
+pub fn sleep_until(deadline: Instant) {
+    let now = crate::time::now();
+    if deadline > now {
+        sleep(deadline - now);
+    }
+}
@@ -0,0 +1,217 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Weak};
+
+use parking_lot::Mutex;
+
+use super::Blocker;
+use crate::cancel::trigger_cancel_panic;
+use crate::park::ParkError;
+
+struct State {
+    waiters: Vec<Arc<Blocker>>,
+    children: Vec<Weak<Inner>>,
+}
+
+struct Inner {
+    cancelled: AtomicBool,
+    state: Mutex<State>,
+}
+
+impl Inner {
+    fn new() -> Arc<Self> {
+        Arc::new(Inner {
+            cancelled: AtomicBool::new(false),
+            state: Mutex::new(State {
+                waiters: Vec::new(),
+                children: Vec::new(),
+            }),
+        })
+    }
+
+    fn cancel(&self) {
+        if self.cancelled.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let state = std::mem::replace(
+            &mut *self.state.lock(),
+            State {
+                waiters: Vec::new(),
+                children: Vec::new(),
+            },
+        );
+        for w in state.waiters {
+            w.unpark();
+        }
+        for child in state.children.iter().filter_map(Weak::upgrade) {
+            child.cancel();
+        }
+    }
+}
+
+/// A token for the cooperative cancellation
+///
+/// Unlike [`Coroutine::cancel`], which panics the coroutine at its next
+/// blocking point, the token only tells the coroutines that they should
+/// stop, and they stop at the point of their choice by checking
+/// [`is_cancelled`] or waiting for [`cancelled`], e.g. in a `select!` with
+/// the real work, so that the shared state is never left half updated.
+///
+/// The clones of a token share the same state. A child token is cancelled
+/// when its parent is cancelled, but cancelling the child doesn't affect the
+/// parent.
+///
+/// Each [`scope`] owns a token that is cancelled when the scope ends, and
+/// the coroutines spawned by the scope get it from
+/// [`coroutine::cancellation_token`]. The token of a scope inside a scoped
+/// coroutine is a child of the outer scope token.
+///
+/// # Examples
+///
+/// ```rust
+/// #[macro_use]
+/// extern crate may;
+///
+/// use may::coroutine;
+/// use may::sync::{mpsc, CancellationToken};
+///
+/// fn main() {
+///     let token = CancellationToken::new();
+///     let (tx, rx) = mpsc::channel::<u32>();
+///     let child = token.child_token();
+///     let h = go!(move || {
+///         let mut n = 0;
+///         // the select returns the index of the branch that completes first
+///         while select!(_ = child.cancelled() => {}, _ = rx.recv() => {}) == 1 {
+///             n += 1;
+///         }
+///         n
+///     });
+///     tx.send(1).unwrap();
+///     tx.send(2).unwrap();
+///     coroutine::sleep(std::time::Duration::from_millis(10));
+///     token.cancel();
+///     assert_eq!(h.join().unwrap(), 2);
+/// }
+/// ```
+///
+/// [`Coroutine::cancel`]: ../coroutine/struct.Coroutine.html#method.cancel
+/// [`is_cancelled`]: struct.CancellationToken.html#method.is_cancelled
+/// [`cancelled`]: struct.CancellationToken.html#method.cancelled
+/// [`scope`]: ../coroutine/fn.scope.html
+/// [`coroutine::cancellation_token`]: ../coroutine/fn.cancellation_token.html
+#[derive(Clone)]
+pub struct CancellationToken {
+    inner: Arc<Inner>,
+}
+
+impl CancellationToken {
+    /// create a new token that is not cancelled
+    pub fn new() -> Self {
+        CancellationToken {
+            inner: Inner::new(),
+        }
+    }
+
+    /// create a child token that is cancelled with this token
+    pub fn child_token(&self) -> CancellationToken {
+        let child = Inner::new();
+        let mut state = self.inner.state.lock();
+        if self.is_cancelled() {
+            child.cancelled.store(true, Ordering::Release);
+        } else {
+            let children = &mut state.children;
+            // drop the dead children before growing
+            if children.len() == children.capacity() {
+                children.retain(|c| c.strong_count() > 0);
+            }
+            children.push(Arc::downgrade(&child));
+        }
+        CancellationToken { inner: child }
+    }
+
+    /// cancel the token and all its children, wake up the waiters
+    pub fn cancel(&self) {
+        self.inner.cancel();
+    }
+
+    /// return true if the token is cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::Acquire)
+    }
+
+    /// block the current coroutine or thread until the token is cancelled
+    ///
+    /// it returns at once if the token is already cancelled
+    pub fn cancelled(&self) {
+        while !self.is_cancelled() {
+            let blocker = Blocker::current();
+            {
+                let mut state = self.inner.state.lock();
+                // re-check after the lock since `cancel` takes the waiters
+                if self.is_cancelled() {
+                    return;
+                }
+                state.waiters.push(blocker.clone());
+            }
+            if let Err(ParkError::Canceled) = blocker.park(None) {
+                let mut state = self.inner.state.lock();
+                state.waiters.retain(|w| !Arc::ptr_eq(w, &blocker));
+                drop(state);
+                trigger_cancel_panic();
+            }
+        }
+    }
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for CancellationToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CancellationToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn cancel_tree() {
+        let root = CancellationToken::new();
+        let child = root.child_token();
+        let grand = child.child_token();
+        let other = root.child_token();
+        child.cancel();
+        assert!(child.is_cancelled() && grand.is_cancelled());
+        assert!(!root.is_cancelled() && !other.is_cancelled());
+        root.cancel();
+        assert!(other.is_cancelled());
+        // the child of a cancelled token is cancelled
+        assert!(root.child_token().is_cancelled());
+    }
+
+    #[test]
+    fn wait_cancelled() {
+        let token = CancellationToken::new();
+        let t1 = token.child_token();
+        let h1 = go!(move || t1.cancelled());
+        let t2 = token.clone();
+        let h2 = thread::spawn(move || t2.cancelled());
+        thread::sleep(Duration::from_millis(10));
+        assert!(!h1.is_done());
+        token.cancel();
+        h1.join().unwrap();
+        h2.join().unwrap();
+        // return at once when cancelled
+        token.cancelled();
+    }
+}
@@ -0,0 +1,302 @@
+//! a queue of the items that expire at their deadlines
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use super::{Condvar, Mutex, MutexGuard};
+use crate::scheduler::get_scheduler;
+use crate::timeout_list::{instant_to_ns, ns_to_dur, TimeOutList, TimeoutHandle};
+
+/// The key of an item in the [`DelayQueue`], it's never reused by the queue
+///
+/// [`DelayQueue`]: struct.DelayQueue.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Key(u64);
+
+struct Entry<T> {
+    data: T,
+    deadline: Instant,
+    timer: TimeoutHandle<Key>,
+}
+
+struct Inner<T> {
+    entries: HashMap<Key, Entry<T>>,
+    // the keys of the fired timers, in the order of the deadlines
+    expired: VecDeque<Key>,
+    next_key: u64,
+}
+
+/// A queue that hands back the items when their deadlines are reached
+///
+/// Items are inserted with a deadline, and [`recv`] blocks until the next
+/// item expires. An item can be reset to a new deadline or removed by the key
+/// returned from the insert, so it fits the idle expiry of the connections
+/// and the retry queues without a sleeping coroutine for each item.
+///
+/// The deadlines follow the clock of the runtime that creates the queue.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::time::Duration;
+/// use may::sync::DelayQueue;
+///
+/// let queue = DelayQueue::new();
+/// let retry = queue.insert("retry", Duration::from_millis(20));
+/// queue.insert("expire", Duration::from_millis(10));
+/// queue.reset(retry, Duration::from_millis(5));
+///
+/// assert_eq!(queue.recv(), "retry");
+/// assert_eq!(queue.recv(), "expire");
+/// assert!(queue.is_empty());
+/// ```
+///
+/// [`recv`]: struct.DelayQueue.html#method.recv
+pub struct DelayQueue<T> {
+    inner: Mutex<Inner<T>>,
+    // the timers run by the receivers under the lock of `inner`
+    timers: TimeOutList<Key>,
+    cond: Condvar,
+}
+
+impl<T> DelayQueue<T> {
+    /// create an empty queue
+    pub fn new() -> Self {
+        let clock = get_scheduler().clock().clone();
+        DelayQueue {
+            inner: Mutex::new(Inner {
+                entries: HashMap::new(),
+                expired: VecDeque::new(),
+                next_key: 0,
+            }),
+            timers: TimeOutList::with_clock(clock),
+            cond: Condvar::new(),
+        }
+    }
+
+    /// insert an item that expires after `timeout`, return its key
+    pub fn insert(&self, data: T, timeout: Duration) -> Key {
+        self.insert_at(data, crate::time::now() + timeout)
+    }
+
+    /// insert an item that expires at `deadline`, return its key
+    pub fn insert_at(&self, data: T, deadline: Instant) -> Key {
+        let mut inner = self.inner.lock().unwrap();
+        let key = Key(inner.next_key);
+        inner.next_key += 1;
+        let (timer, _) = self.timers.add_timer_at(instant_to_ns(deadline), key);
+        let entry = Entry {
+            data,
+            deadline,
+            timer,
+        };
+        inner.entries.insert(key, entry);
+        drop(inner);
+        // let a receiver recall the next deadline
+        self.cond.notify_one();
+        key
+    }
+
+    /// let the item expire after `timeout` from now
+    ///
+    /// return false if the item is already removed or handed back
+    pub fn reset(&self, key: Key, timeout: Duration) -> bool {
+        self.reset_at(key, crate::time::now() + timeout)
+    }
+
+    /// let the item expire at `deadline`
+    ///
+    /// return false if the item is already removed or handed back
+    pub fn reset_at(&self, key: Key, deadline: Instant) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        let entry = match inner.entries.get_mut(&key) {
+            Some(entry) => entry,
+            None => return false,
+        };
+        let (timer, _) = self.timers.add_timer_at(instant_to_ns(deadline), key);
+        let old = std::mem::replace(&mut entry.timer, timer);
+        entry.deadline = deadline;
+        if old.remove().is_none() {
+            // the old timer is fired but the item is not handed back yet
+            inner.expired.retain(|k| *k != key);
+        }
+        drop(inner);
+        self.cond.notify_one();
+        true
+    }
+
+    /// remove the item from the queue before it expires
+    pub fn remove(&self, key: Key) -> Option<T> {
+        let mut inner = self.inner.lock().unwrap();
+        let entry = inner.entries.remove(&key)?;
+        if entry.timer.remove().is_none() {
+            inner.expired.retain(|k| *k != key);
+        }
+        Some(entry.data)
+    }
+
+    /// the deadline of the item, none if it's removed or handed back
+    pub fn deadline(&self, key: Key) -> Option<Instant> {
+        let inner = self.inner.lock().unwrap();
+        inner.entries.get(&key).map(|e| e.deadline)
+    }
+
+    /// the number of items in the queue
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().entries.len()
+    }
+
+    /// return true if there is no item in the queue
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // fire the timers, return the expired item if any, or else the time in
+    // ns for the next expiration
+    fn poll(&self, inner: &mut Inner<T>) -> Result<T, Option<u64>> {
+        loop {
+            if let Some(key) = inner.expired.pop_front() {
+                let entry = inner.entries.remove(&key).expect("no entry for the key");
+                return Ok(entry.data);
+            }
+            let fired = RefCell::new(Vec::new());
+            let now = self.timers.clock().now();
+            let next = self
+                .timers
+                .schedule_timer(now, &|key| fired.borrow_mut().push(key));
+            let fired = fired.into_inner();
+            if fired.is_empty() && next != Some(0) {
+                return Err(next);
+            }
+            inner.expired.extend(fired);
+        }
+    }
+
+    // block until an item expires or timeout
+    fn recv_impl(&self, timeout: Option<Duration>) -> Option<T> {
+        let deadline = timeout.map(|dur| crate::time::now() + dur);
+        let mut inner = self.inner.lock().unwrap();
+        loop {
+            let next = match self.poll(&mut inner) {
+                Ok(data) => {
+                    self.notify_next(inner);
+                    return Some(data);
+                }
+                Err(next) => next.map(ns_to_dur),
+            };
+            let wait = match deadline {
+                Some(d) => {
+                    let left = d.checked_duration_since(crate::time::now())?;
+                    Some(next.map_or(left, |n| n.min(left)))
+                }
+                None => next,
+            };
+            inner = match wait {
+                Some(Duration::ZERO) => return None,
+                Some(dur) => self.cond.wait_timeout(inner, dur).unwrap().0,
+                None => self.cond.wait(inner).unwrap(),
+            };
+        }
+    }
+
+    // let the next receiver take over the remaining items
+    fn notify_next(&self, inner: MutexGuard<Inner<T>>) {
+        let remaining = !inner.entries.is_empty();
+        drop(inner);
+        if remaining {
+            self.cond.notify_one();
+        }
+    }
+
+    /// block until the next item expires, and hand it back
+    pub fn recv(&self) -> T {
+        self.recv_impl(None).expect("recv without timeout")
+    }
+
+    /// same as `recv` except that it returns none if no item expires within
+    /// `timeout`
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<T> {
+        self.recv_impl(Some(timeout))
+    }
+
+    /// hand back an expired item if any without blocking
+    pub fn try_recv(&self) -> Option<T> {
+        let mut inner = self.inner.lock().unwrap();
+        let data = self.poll(&mut inner).ok()?;
+        self.notify_next(inner);
+        Some(data)
+    }
+}
+
+impl<T> Default for DelayQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for DelayQueue<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DelayQueue")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn expire_in_order() {
+        let q = DelayQueue::new();
+        let a = q.insert("a", Duration::from_millis(30));
+        q.insert("b", Duration::from_millis(10));
+        q.insert("c", Duration::from_millis(20));
+        let d = q.insert("d", Duration::from_millis(15));
+        assert_eq!(q.len(), 4);
+        assert_eq!(q.remove(d), Some("d"));
+        assert!(q.reset(a, Duration::from_millis(5)));
+
+        assert_eq!(q.try_recv(), None);
+        assert_eq!(q.recv(), "a");
+        assert_eq!(q.recv(), "b");
+        assert_eq!(q.recv(), "c");
+        assert!(q.is_empty());
+        assert_eq!(q.recv_timeout(Duration::from_millis(10)), None);
+        // the keys are never reused
+        assert!(!q.reset(a, Duration::from_millis(5)));
+        assert_eq!(q.remove(a), None);
+        assert_eq!(q.deadline(a), None);
+    }
+
+    #[test]
+    fn reset_expired() {
+        let q = DelayQueue::new();
+        q.insert(1, Duration::from_millis(1));
+        let b = q.insert(2, Duration::from_millis(2));
+        thread::sleep(Duration::from_millis(10));
+        assert_eq!(q.try_recv(), Some(1));
+        // the item is expired but not handed back yet
+        assert!(q.reset(b, Duration::from_secs(10)));
+        assert_eq!(q.try_recv(), None);
+        assert_eq!(q.remove(b), Some(2));
+        assert!(q.is_empty());
+    }
+
+    #[test]
+    fn wake_up_receiver() {
+        let q = Arc::new(DelayQueue::new());
+        let q1 = q.clone();
+        let h = go!(move || (q1.recv(), q1.recv()));
+        thread::sleep(Duration::from_millis(10));
+        // the earlier item wakes up the blocked receiver
+        q.insert(1, Duration::from_secs(10));
+        q.insert(2, Duration::from_millis(10));
+        q.insert(3, Duration::from_millis(20));
+        assert_eq!(h.join().unwrap(), (2, 3));
+        assert_eq!(q.len(), 1);
+    }
+}
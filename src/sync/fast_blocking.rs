@@ -10,6 +10,8 @@ use super::{blocking::ThreadPark, AtomicOption};
 use crate::coroutine_impl::{
     co_cancel_data, is_coroutine, run_coroutine, CoroutineImpl, EventSource,
 };
+#[cfg(feature = "task_dump")]
+use crate::dump::BlockState;
 use crate::park::ParkError;
 use crate::scheduler::get_scheduler;
 use crate::yield_now::{get_co_para, yield_with};
@@ -64,6 +66,11 @@ impl Park {
 }
 
 impl EventSource for Park {
+    #[cfg(feature = "task_dump")]
+    fn block_state(&self) -> BlockState {
+        BlockState::Parked
+    }
+
     // register the coroutine to the park
     fn subscribe(&mut self, co: CoroutineImpl) {
         let cancel = co_cancel_data(&co);
@@ -1,5 +1,6 @@
 mod atomic_option;
 mod blocking;
+mod cancellation;
 mod condvar;
 mod mutex;
 mod poison;
@@ -11,12 +12,15 @@ pub(crate) mod atomic_dur;
 #[cfg(not(unix))]
 pub(crate) mod delay_drop;
 // pub(crate) mod fast_blocking;
+pub mod delay_queue;
 pub mod mpmc;
 pub mod mpsc;
 pub mod spsc;
 pub use self::atomic_option::AtomicOption;
 pub use self::blocking::{Blocker, FastBlocker};
+pub use self::cancellation::CancellationToken;
 pub use self::condvar::{Condvar, WaitTimeoutResult};
+pub use self::delay_queue::DelayQueue;
 pub use self::mutex::{Mutex, MutexGuard};
 pub use self::rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 pub use self::semphore::Semphore;
@@ -5,10 +5,11 @@ use std::panic::{RefUnwindSafe, UnwindSafe};
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::mpsc::{RecvError, RecvTimeoutError, SendError, TryRecvError};
 use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
 use super::{AtomicOption, Blocker};
 use crate::likely::{likely, unlikely};
+use crate::time;
 
 use may_queue::mpsc::Queue;
 
@@ -212,7 +213,7 @@ impl<T> Receiver<T> {
     }
 
     fn recv_max_until(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
-        let deadline = Instant::now() + timeout;
+        let deadline = time::now() + timeout;
         loop {
             match self.inner.recv(Some(timeout)) {
                 Ok(t) => return Ok(t),
@@ -222,7 +223,7 @@ impl<T> Receiver<T> {
 
             // If we're already passed the deadline, and we're here without
             // data, return a timeout, else try again.
-            if Instant::now() >= deadline {
+            if time::now() >= deadline {
                 return Err(RecvTimeoutError::Timeout);
             }
         }
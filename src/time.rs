@@ -0,0 +1,524 @@
+//! The clock of the coroutine timers
+//!
+//! All the timers of a runtime, e.g. `sleep`, `park_timeout`, the
+//! `wait_timeout` of the sync primitives and the io timeouts, run on the
+//! clock of the runtime. It follows the monotonic wall clock by default, and
+//! can be paused and moved forward manually, so that the tests of the time
+//! based logic, e.g. retry and backoff, don't need to really wait. The
+//! pending timers fire in the order of their deadlines as the clock moves.
+//!
+//! The free functions work on the clock of the current runtime, which is the
+//! default runtime in the thread context.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::time::Duration;
+//! use may::{coroutine, CurrentThreadRuntime};
+//!
+//! let rt = CurrentThreadRuntime::new().unwrap();
+//! let clock = rt.clock();
+//! clock.pause();
+//! let start = clock.now();
+//! let h = unsafe { rt.spawn(|| coroutine::sleep(Duration::from_secs(3600))) };
+//! // run the coroutine until it sleeps
+//! rt.turn(Duration::ZERO);
+//! assert!(!h.is_done());
+//!
+//! clock.advance(Duration::from_secs(3600));
+//! rt.turn(Duration::ZERO);
+//! assert!(h.is_done());
+//! assert_eq!(clock.now() - start, Duration::from_secs(3600));
+//! ```
+//!
+//! [`Interval`] and [`Timer`] are built on the same timers, so they follow
+//! the runtime clock as well.
+//!
+//! [`Interval`]: struct.Interval.html
+//! [`Timer`]: struct.Timer.html
+
+use std::fmt;
+use std::sync::Arc;
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
+
+use parking_lot::{Mutex, MutexGuard};
+
+use crate::coroutine_impl::{co_cancel_data, is_coroutine, CoroutineImpl, EventSource};
+#[cfg(feature = "task_dump")]
+use crate::dump::BlockState;
+use crate::scheduler::{get_scheduler, try_get_scheduler, Scheduler};
+use crate::sleep::sleep_until;
+use crate::sync::AtomicOption;
+#[cfg(feature = "task_dump")]
+use crate::timeout_list::instant_to_ns;
+use crate::timeout_list::{self, ns_to_instant, TimeoutHandle};
+use crate::yield_now::{get_co_para, yield_with};
+
+/// A handle to the clock of a runtime
+///
+/// it borrows the runtime, the clock of the current runtime from [`clock`]
+/// is always valid
+///
+/// [`clock`]: fn.clock.html
+#[derive(Clone, Copy)]
+pub struct Clock<'a> {
+    sched: &'a Scheduler,
+}
+
+impl<'a> Clock<'a> {
+    pub(crate) fn new(sched: &'a Scheduler) -> Self {
+        Clock { sched }
+    }
+
+    /// the current time of the clock
+    pub fn now(&self) -> Instant {
+        ns_to_instant(self.sched.clock().now())
+    }
+
+    /// stop the clock at the current time, the timers don't fire any more
+    /// until the clock is moved by `advance` or `resume`
+    pub fn pause(&self) {
+        self.sched.clock().pause();
+    }
+
+    /// let the paused clock run again from the paused time
+    pub fn resume(&self) {
+        self.sched.clock().resume();
+    }
+
+    /// return true if the clock is paused
+    pub fn is_paused(&self) -> bool {
+        self.sched.clock().is_paused()
+    }
+
+    /// move the clock forward by `dur`, the timers that are due fire in the
+    /// order of their deadlines
+    ///
+    /// In a thread outside of the runtime, the clock steps through the
+    /// deadlines across all the workers, and waits at each step until the
+    /// workers have run the woken coroutines to their next blocking point, so
+    /// it returns after all the due timers are done. In a coroutine the clock
+    /// jumps at once, and the order only holds within each worker.
+    ///
+    /// this works for both the paused and the running clock
+    pub fn advance(&self, dur: Duration) {
+        self.sched.advance_clock(dur);
+    }
+}
+
+impl fmt::Debug for Clock<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Clock")
+            .field("paused", &self.is_paused())
+            .finish()
+    }
+}
+
+/// get the clock of the current runtime
+pub fn clock() -> Clock<'static> {
+    Clock::new(get_scheduler())
+}
+
+/// the current time of the current runtime clock
+///
+/// this is the wall clock if no runtime is started yet
+pub fn now() -> Instant {
+    match try_get_scheduler() {
+        Some(sched) => Clock::new(sched).now(),
+        None => ns_to_instant(timeout_list::now()),
+    }
+}
+
+/// stop the clock of the current runtime, see [`Clock::pause`]
+///
+/// [`Clock::pause`]: struct.Clock.html#method.pause
+pub fn pause() {
+    clock().pause();
+}
+
+/// let the clock of the current runtime run again, see [`Clock::resume`]
+///
+/// [`Clock::resume`]: struct.Clock.html#method.resume
+pub fn resume() {
+    clock().resume();
+}
+
+/// move the clock of the current runtime forward, see [`Clock::advance`]
+///
+/// [`Clock::advance`]: struct.Clock.html#method.advance
+pub fn advance(dur: Duration) {
+    clock().advance(dur);
+}
+
+/// What an [`Interval`] does when a tick is missed, e.g. the job between the
+/// ticks takes longer than the period
+///
+/// [`Interval`]: struct.Interval.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MissedTickBehavior {
+    /// fire the missed ticks right away until it catches up with the
+    /// original schedule, this is the default
+    #[default]
+    Burst,
+    /// fire the missed tick right away, and schedule the next ticks a
+    /// period after it
+    Delay,
+    /// drop the missed ticks, and fire at the next tick of the original
+    /// schedule
+    Skip,
+}
+
+/// A ticker that fires every period for the periodic jobs
+///
+/// The first tick completes right away. Created by [`interval`] or
+/// [`interval_at`].
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use may::time;
+///
+/// let mut interval = time::interval(Duration::from_millis(10));
+/// let start = interval.tick();
+/// for i in 1..4 {
+///     assert_eq!(interval.tick() - start, Duration::from_millis(10 * i));
+/// }
+/// ```
+///
+/// [`interval`]: fn.interval.html
+/// [`interval_at`]: fn.interval_at.html
+#[derive(Debug)]
+pub struct Interval {
+    // the deadline of the next tick
+    next: Instant,
+    period: Duration,
+    missed_tick_behavior: MissedTickBehavior,
+}
+
+impl Interval {
+    /// block until the next tick, return the scheduled time of the tick
+    pub fn tick(&mut self) -> Instant {
+        let deadline = self.next;
+        sleep_until(deadline);
+        let now = now();
+        self.next = match self.missed_tick_behavior {
+            _ if now <= deadline => deadline + self.period,
+            MissedTickBehavior::Burst => deadline + self.period,
+            MissedTickBehavior::Delay => now + self.period,
+            MissedTickBehavior::Skip => {
+                let period = self.period.as_nanos();
+                let ticks = (now - deadline).as_nanos() / period + 1;
+                deadline + Duration::from_nanos((ticks * period) as u64)
+            }
+        };
+        deadline
+    }
+
+    /// schedule the next tick a period from now
+    pub fn reset(&mut self) {
+        self.next = now() + self.period;
+    }
+
+    /// the period of the interval
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// the behavior of the missed ticks
+    pub fn missed_tick_behavior(&self) -> MissedTickBehavior {
+        self.missed_tick_behavior
+    }
+
+    /// set the behavior of the missed ticks
+    pub fn set_missed_tick_behavior(&mut self, behavior: MissedTickBehavior) {
+        self.missed_tick_behavior = behavior;
+    }
+}
+
+/// create an [`Interval`] that ticks every `period`, the first tick
+/// completes right away
+///
+/// # Panics
+///
+/// panics if `period` is zero
+///
+/// [`Interval`]: struct.Interval.html
+pub fn interval(period: Duration) -> Interval {
+    interval_at(now(), period)
+}
+
+/// create an [`Interval`] that ticks every `period`, the first tick
+/// completes at `start`
+///
+/// # Panics
+///
+/// panics if `period` is zero
+///
+/// [`Interval`]: struct.Interval.html
+pub fn interval_at(start: Instant, period: Duration) -> Interval {
+    assert!(
+        !period.is_zero(),
+        "the period of the interval must be non-zero"
+    );
+    Interval {
+        next: start,
+        period,
+        missed_tick_behavior: MissedTickBehavior::default(),
+    }
+}
+
+type TimerData = Arc<AtomicOption<CoroutineImpl>>;
+
+// a waiter blocked in `Timer::wait`
+enum Waiter {
+    // a coroutine with a timer on the wheel of its scheduler, the timer is
+    // none once it's deleted
+    Co {
+        co: TimerData,
+        sched: &'static Scheduler,
+        timer: Option<TimeoutHandle<TimerData>>,
+    },
+    // a thread that parks until the deadline
+    Thread(Thread),
+}
+
+// the coroutine is only taken once through the atomic option, and the
+// scheduler is shared by all the threads as the runtime does
+unsafe impl Send for Waiter {}
+
+struct TimerState {
+    // none if the timer is cancelled
+    deadline: Option<Instant>,
+    waiters: Vec<Waiter>,
+}
+
+impl TimerState {
+    // return the wait result if the timer is already fired or cancelled
+    fn done(&self) -> Option<bool> {
+        match self.deadline {
+            None => Some(false),
+            Some(d) if d <= now() => Some(true),
+            Some(_) => None,
+        }
+    }
+
+    // re-arm the timers of the waiters on the new deadline, or delete them
+    // and wake up the waiters if the timer is cancelled or passed
+    fn update(&mut self) {
+        let dur = match self.done() {
+            None => self.deadline.map(|d| d.saturating_duration_since(now())),
+            Some(_) => None,
+        };
+        for waiter in self.waiters.iter_mut() {
+            match waiter {
+                Waiter::Co { co, sched, timer } => {
+                    if let Some(h) = timer.take() {
+                        sched.del_timer(h);
+                    }
+                    match dur {
+                        // the coroutine may be resumed by the fired timer
+                        // already, then the new timer finds nothing to run
+                        Some(dur) => *timer = Some(sched.add_timer(dur, co.clone())),
+                        None => {
+                            if let Some(co) = co.take() {
+                                sched.schedule(co);
+                            }
+                        }
+                    }
+                }
+                Waiter::Thread(t) => t.unpark(),
+            }
+        }
+    }
+}
+
+// the event source of a coroutine waiting for the timer
+struct Wait<'a> {
+    state: &'a Mutex<TimerState>,
+    // the registered coroutine data, it's removed when the wait is done
+    co: Option<TimerData>,
+}
+
+impl EventSource for Wait<'_> {
+    #[cfg(feature = "task_dump")]
+    fn block_state(&self) -> BlockState {
+        match self.state.lock().deadline {
+            Some(d) => BlockState::Sleeping(instant_to_ns(d)),
+            None => BlockState::Parked,
+        }
+    }
+
+    fn subscribe(&mut self, co: CoroutineImpl) {
+        let cancel = co_cancel_data(&co);
+        let sched = get_scheduler();
+        let co = Arc::new(AtomicOption::some(co));
+        {
+            let mut state = self.state.lock();
+            let dur = match (state.done(), state.deadline) {
+                (None, Some(d)) => d.saturating_duration_since(now()),
+                // the timer is changed since the last check
+                _ => return sched.schedule(co.take().unwrap()),
+            };
+            let timer = Some(sched.add_timer(dur, co.clone()));
+            state.waiters.push(Waiter::Co {
+                co: co.clone(),
+                sched,
+                timer,
+            });
+        }
+        self.co = Some(co.clone());
+
+        // register the cancel data
+        cancel.set_co(co);
+        // re-check the cancel status
+        if cancel.is_canceled() {
+            unsafe { cancel.cancel() };
+        }
+    }
+}
+
+impl Drop for Wait<'_> {
+    fn drop(&mut self) {
+        // the waiter may leave by a cancel panic, so remove it here
+        let Some(co) = self.co.take() else { return };
+        let mut state = self.state.lock();
+        let pos = state.waiters.iter().position(|w| match w {
+            Waiter::Co { co: c, .. } => Arc::ptr_eq(c, &co),
+            Waiter::Thread(_) => false,
+        });
+        let waiter = pos.map(|i| state.waiters.swap_remove(i));
+        if let Some(Waiter::Co {
+            sched,
+            timer: Some(h),
+            ..
+        }) = waiter
+        {
+            sched.del_timer(h);
+        }
+    }
+}
+
+/// A one shot timer that can be reset or cancelled by other coroutines
+///
+/// The clones of a timer share the same deadline, so one coroutine can wait
+/// for the timer while the others push it back or cancel it. A waiting
+/// coroutine is put on the timers of its runtime, reset moves the timer to
+/// the new deadline and cancel deletes it.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use may::{coroutine, go, time::Timer};
+///
+/// let timer = Timer::new(Duration::from_secs(3600));
+/// let t = timer.clone();
+/// let h = go!(move || t.wait());
+/// timer.cancel();
+/// // the timer is cancelled before it fires
+/// assert!(!h.join().unwrap());
+/// ```
+#[derive(Clone)]
+pub struct Timer {
+    state: Arc<Mutex<TimerState>>,
+}
+
+impl Timer {
+    /// create a timer that fires after `dur`
+    pub fn new(dur: Duration) -> Self {
+        Timer::at(now() + dur)
+    }
+
+    /// create a timer that fires at `deadline`
+    pub fn at(deadline: Instant) -> Self {
+        Timer {
+            state: Arc::new(Mutex::new(TimerState {
+                deadline: Some(deadline),
+                waiters: Vec::new(),
+            })),
+        }
+    }
+
+    /// block until the timer fires or is cancelled, return false if it's
+    /// cancelled
+    ///
+    /// it returns at once if the timer is already fired
+    pub fn wait(&self) -> bool {
+        if !is_coroutine() {
+            return self.wait_thread();
+        }
+        loop {
+            if let Some(fired) = self.state.lock().done() {
+                return fired;
+            }
+            let wait = Wait {
+                state: &self.state,
+                co: None,
+            };
+            yield_with(&wait);
+            // consume the timeout error
+            get_co_para();
+        }
+    }
+
+    // the thread parks until the deadline, and is unparked when the timer
+    // is reset or cancelled
+    fn wait_thread(&self) -> bool {
+        let mut state = self.state.lock();
+        loop {
+            let dur = match (state.done(), state.deadline) {
+                (Some(fired), _) => return fired,
+                (None, Some(d)) => d.saturating_duration_since(now()),
+                (None, None) => unreachable!(),
+            };
+            let me = thread::current();
+            let id = me.id();
+            state.waiters.push(Waiter::Thread(me));
+            MutexGuard::unlocked(&mut state, || thread::park_timeout(dur));
+            state
+                .waiters
+                .retain(|w| !matches!(w, Waiter::Thread(t) if t.id() == id));
+        }
+    }
+
+    /// let the timer fire after `dur` from now, this also re-arms a fired
+    /// or cancelled timer
+    pub fn reset(&self, dur: Duration) {
+        self.reset_at(now() + dur);
+    }
+
+    /// let the timer fire at `deadline`, this also re-arms a fired or
+    /// cancelled timer
+    pub fn reset_at(&self, deadline: Instant) {
+        let mut state = self.state.lock();
+        state.deadline = Some(deadline);
+        state.update();
+    }
+
+    /// cancel the timer, the waiters return false
+    pub fn cancel(&self) {
+        let mut state = self.state.lock();
+        state.deadline = None;
+        state.update();
+    }
+
+    /// the time that the timer fires, none if it's cancelled
+    pub fn deadline(&self) -> Option<Instant> {
+        self.state.lock().deadline
+    }
+
+    /// return true if the timer is fired
+    pub fn is_elapsed(&self) -> bool {
+        self.deadline().is_some_and(|d| d <= now())
+    }
+}
+
+impl fmt::Debug for Timer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Timer")
+            .field("deadline", &self.deadline())
+            .finish()
+    }
+}
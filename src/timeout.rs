@@ -0,0 +1,111 @@
+use std::error::Error;
+use std::fmt;
+use std::panic;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::coroutine_impl::{current_cancel_data, deadline, is_coroutine};
+use crate::join::JoinHandle;
+use crate::local::get_co_local_data;
+use crate::scoped::{cancellation_token, spawn_unsafe};
+use crate::sync::AtomicOption;
+
+/// The error returned by [`timeout`] when the deadline is passed
+///
+/// [`timeout`]: fn.timeout.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Elapsed(());
+
+impl fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("deadline has elapsed")
+    }
+}
+
+impl Error for Elapsed {}
+
+// abort the coroutine if the caller leaves before it's done, e.g. when the
+// caller is cancelled, so that the borrowed data outlives the coroutine
+struct AbortGuard(Option<JoinHandle<()>>);
+
+impl Drop for AbortGuard {
+    fn drop(&mut self) {
+        let h = match self.0.take() {
+            Some(h) => h,
+            None => return,
+        };
+        let cancel = is_coroutine().then(current_cancel_data);
+        // we must wait for the coroutine even if the caller is cancelled
+        if let Some(c) = cancel {
+            c.disable_cancel();
+        }
+        h.abort().ok();
+        if let Some(c) = cancel {
+            c.enable_cancel();
+        }
+    }
+}
+
+/// Run the closure with a timeout
+///
+/// The closure runs in a new coroutine, and if it's not done within `dur`
+/// the coroutine is cancelled, so it panics at its next blocking point, e.g.
+/// a channel `recv`, a `Mutex` lock, a `sleep` or an io operation when the
+/// `io_cancel` feature is enabled, and unwinds cleanly before this returns
+/// `Err(Elapsed)`. The closure can borrow from the caller's stack like a
+/// scoped coroutine.
+///
+/// The timeouts nest: when an outer timeout fires first, the inner ones are
+/// cancelled together with the closure. A panic in the closure is resumed in
+/// the caller.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::time::Duration;
+/// use may::coroutine;
+///
+/// let ret = coroutine::timeout(Duration::from_millis(10), || {
+///     coroutine::sleep(Duration::from_secs(10));
+/// });
+/// assert!(ret.is_err());
+///
+/// let ret = coroutine::timeout(Duration::from_secs(10), || 42);
+/// assert_eq!(ret, Ok(42));
+/// ```
+#[track_caller]
+pub fn timeout<F, T>(dur: Duration, f: F) -> Result<T, Elapsed>
+where
+    F: FnOnce() -> T + Send,
+    T: Send,
+{
+    let their_packet = Arc::new(AtomicOption::none());
+    let my_packet = their_packet.clone();
+    // the closure sees the same scope token and deadline as the caller
+    let token = cancellation_token();
+    let deadline = deadline();
+    // safe since the guard waits for the coroutine before we leave
+    let h = unsafe {
+        spawn_unsafe(move || {
+            if let Some(local) = get_co_local_data() {
+                let local = local.as_ref();
+                if let Some(token) = token {
+                    local.set_token(token);
+                }
+                local.set_deadline(deadline);
+            }
+            their_packet.store(f());
+        })
+    };
+
+    let mut guard = AbortGuard(Some(h));
+    if !guard.0.as_ref().expect("no join handle").wait_timeout(dur) {
+        // the guard aborts the coroutine
+        return Err(Elapsed(()));
+    }
+    let h = guard.0.take().expect("no join handle");
+    match h.join() {
+        Ok(()) => Ok(my_packet.take().expect("no result of the coroutine")),
+        Err(panic) => panic::resume_unwind(panic),
+    }
+}
@@ -1,25 +1,18 @@
-use std::cmp;
-use std::collections::{BinaryHeap, HashMap};
+use std::cell::UnsafeCell;
 use std::mem;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{fence, AtomicBool, AtomicI64, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
-use std::thread;
 use std::time::{Duration, Instant};
 
 use may_queue::mpsc::Queue;
-use may_queue::mpsc_list_v1::Entry;
-use may_queue::mpsc_list_v1::Queue as TimeoutQueue;
-use parking_lot::{Mutex, RwLock};
 
 use crate::sync::AtomicOption;
 
 const NANOS_PER_MILLI: u64 = 1_000_000;
 const NANOS_PER_SEC: u64 = 1_000_000_000;
 
-const HASH_CAP: usize = 1024;
-
 #[inline]
-fn dur_to_ns(dur: Duration) -> u64 {
+pub fn dur_to_ns(dur: Duration) -> u64 {
     // Note that a duration is a (u64, u32) (seconds, nanoseconds) pair
     dur.as_secs()
         .saturating_mul(NANOS_PER_SEC)
@@ -31,12 +24,6 @@ pub const fn ns_to_dur(ns: u64) -> Duration {
     Duration::new(ns / NANOS_PER_SEC, (ns % NANOS_PER_SEC) as u32)
 }
 
-#[allow(dead_code)]
-#[inline]
-pub const fn ns_to_ms(ns: u64) -> u64 {
-    (ns + NANOS_PER_MILLI - 1) / NANOS_PER_MILLI
-}
-
 #[inline]
 fn get_instant() -> &'static Instant {
     // TODO: wait for MaybeUninit::zero stable https://github.com/rust-lang/rust/issues/91850
@@ -55,307 +42,561 @@ pub fn now() -> u64 {
     get_instant().elapsed().as_nanos() as u64
 }
 
-// timeout event data
-pub struct TimeoutData<T> {
-    time: u64,   // the wall clock in ns that the timer expires
-    pub data: T, // the data associate with the timeout event
+// convert the wall clock in ns back to an instant
+#[inline]
+pub fn ns_to_instant(ns: u64) -> Instant {
+    *get_instant() + Duration::from_nanos(ns)
 }
 
-// timeout handler which can be removed/cancelled
-pub type TimeoutHandle<T> = Entry<TimeoutData<T>>;
+// convert an instant to the wall clock in ns
+#[inline]
+pub fn instant_to_ns(t: Instant) -> u64 {
+    dur_to_ns(t.saturating_duration_since(*get_instant()))
+}
 
-struct TimeoutQueueWrapper<T> {
-    inner: TimeoutQueue<TimeoutData<T>>,
-    in_use: AtomicUsize,
+// the frozen time of a running clock
+const RUNNING: u64 = u64::MAX;
+
+// the time source of the timers, it follows the monotonic wall clock but can
+// be paused and moved forward
+pub struct Clock {
+    // the time in ns when the clock is paused, RUNNING if not paused
+    frozen: AtomicU64,
+    // the ns added to the wall clock
+    offset: AtomicI64,
 }
 
-impl<T> TimeoutQueueWrapper<T> {
-    fn new() -> Self {
-        TimeoutQueueWrapper {
-            inner: TimeoutQueue::new(),
-            in_use: AtomicUsize::new(0),
+impl Clock {
+    pub fn new() -> Self {
+        Clock {
+            frozen: AtomicU64::new(RUNNING),
+            offset: AtomicI64::new(0),
+        }
+    }
+
+    // create a clock that is paused at the given time
+    #[cfg(any(test, feature = "sim"))]
+    pub fn paused_at(ns: u64) -> Self {
+        Clock {
+            frozen: AtomicU64::new(ns),
+            offset: AtomicI64::new(0),
+        }
+    }
+
+    #[inline]
+    pub fn now(&self) -> u64 {
+        let frozen = self.frozen.load(Ordering::Acquire);
+        if frozen != RUNNING {
+            return frozen;
+        }
+        (now() as i64).wrapping_add(self.offset.load(Ordering::Acquire)) as u64
+    }
+
+    #[inline]
+    pub fn is_paused(&self) -> bool {
+        self.frozen.load(Ordering::Acquire) != RUNNING
+    }
+
+    // stop the clock at the current time
+    pub fn pause(&self) {
+        let t = self.now();
+        self.frozen
+            .compare_exchange(RUNNING, t, Ordering::AcqRel, Ordering::Acquire)
+            .ok();
+    }
+
+    // let the clock run again from the paused time
+    pub fn resume(&self) {
+        let frozen = self.frozen.load(Ordering::Acquire);
+        if frozen != RUNNING {
+            // set the offset first so that the time never goes back
+            let offset = (frozen as i64).wrapping_sub(now() as i64);
+            self.offset.store(offset, Ordering::Release);
+            self.frozen.store(RUNNING, Ordering::Release);
+        }
+    }
+
+    // move the clock forward by `dur` ns
+    pub fn advance(&self, dur: u64) {
+        let paused = self
+            .frozen
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |t| {
+                (t != RUNNING).then(|| t.saturating_add(dur).min(RUNNING - 1))
+            })
+            .is_ok();
+        if !paused {
+            self.offset.fetch_add(dur as i64, Ordering::AcqRel);
         }
     }
 }
 
-type IntervalList<T> = Arc<TimeoutQueueWrapper<T>>;
+// the timers are kept in a hierarchical timing wheel. A level has 64 slots,
+// and a slot of level n covers 64^n ticks of 1ms, so the 6 levels cover
+// about 2 years. A timer is put in the lowest level that can tell it from
+// the current tick, and moves down the levels as the time goes by, so adding
+// a timer and firing it are both O(1)
+const TICK_NS: u64 = NANOS_PER_MILLI;
+const SLOT_BITS: u32 = 6;
+const SLOTS: usize = 1 << SLOT_BITS;
+const LEVELS: usize = 6;
+// the max ticks from now that the wheel can tell apart
+const MAX_TICKS: u64 = (1 << (SLOT_BITS as usize * LEVELS)) - 1;
 
-// this is the data type that used by the binary heap to get the latest timer
-struct IntervalEntry<T> {
-    time: u64,             // the head timeout value in the list, should be latest
-    list: IntervalList<T>, // point to the interval list
-    interval: u64,
+// timeout event data
+pub struct TimeoutData<T> {
+    time: u64, // the clock time in ns that the timer expires
+    // set when the timer is removed, the wheel drops it at the next visit of
+    // its slot or at the next purge
+    removed: AtomicBool,
+    // the data associate with the timeout event, taken by who comes first of
+    // the wheel and the remover
+    data: AtomicOption<T>,
+    // the pending timer count of the list, decreased by who takes the data
+    pending: Arc<AtomicUsize>,
 }
 
-impl<T> IntervalEntry<T> {
-    // trigger the timeout event with the supplying function
-    // return next expire time
-    pub fn pop_timeout<F>(&self, now: u64, f: &F) -> Option<u64>
-    where
-        F: Fn(T),
-    {
-        let p = |v: &TimeoutData<T>| v.time <= now;
-        while let Some(timeout) = self.list.inner.pop_if(&p) {
-            f(timeout.data);
+impl<T> TimeoutData<T> {
+    #[inline]
+    fn take(&self) -> Option<T> {
+        let data = self.data.take();
+        if data.is_some() {
+            self.pending.fetch_sub(1, Ordering::Relaxed);
         }
-        unsafe { self.list.inner.peek() }.map(|t| t.time)
+        data
     }
 }
 
-impl<T> PartialEq for IntervalEntry<T> {
-    fn eq(&self, other: &Self) -> bool {
-        self.time == other.time
+type TimerEntry<T> = Arc<TimeoutData<T>>;
+
+// timeout handler which can be removed/cancelled
+pub struct TimeoutHandle<T>(TimerEntry<T>);
+
+impl<T> TimeoutHandle<T> {
+    // remove the timer, return the data if the timer is not fired yet
+    // this can be called in any thread
+    #[inline]
+    pub fn remove(self) -> Option<T> {
+        self.0.removed.store(true, Ordering::Relaxed);
+        self.0.take()
+    }
+
+    #[inline]
+    pub fn into_ptr(self) -> *mut Self {
+        Arc::into_raw(self.0) as *mut Self
+    }
+
+    /// # Safety
+    /// the ptr must be returned by `into_ptr`, and can only be used once
+    #[inline]
+    pub unsafe fn from_ptr(ptr: *mut Self) -> Self {
+        TimeoutHandle(Arc::from_raw(ptr as *const TimeoutData<T>))
     }
 }
 
-impl<T> Eq for IntervalEntry<T> {}
+// the level that the timer should be put in, which is the highest 6 bits
+// group that the expire tick differs from the elapsed tick
+#[inline]
+fn level_for(elapsed: u64, tick: u64) -> usize {
+    let masked = ((elapsed ^ tick) | (SLOTS as u64 - 1)).min(MAX_TICKS - 1);
+    let significant = 63 - masked.leading_zeros();
+    (significant / SLOT_BITS) as usize
+}
+
+struct Level<T> {
+    slots: Vec<Vec<TimerEntry<T>>>,
+    // bit n is set when the slot n is not empty
+    occupied: u64,
+}
 
-impl<T> PartialOrd for IntervalEntry<T> {
-    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
-        Some(self.cmp(other))
+impl<T> Level<T> {
+    fn new() -> Self {
+        Level {
+            slots: (0..SLOTS).map(|_| Vec::new()).collect(),
+            occupied: 0,
+        }
     }
+
+    // return the first occupied slot after the elapsed tick, and the tick
+    // that the slot should be processed
+    fn next_expiration(&self, level: usize, elapsed: u64) -> Option<(usize, u64)> {
+        if self.occupied == 0 {
+            return None;
+        }
+        let slot_range = 1u64 << (level as u32 * SLOT_BITS);
+        let level_range = slot_range << SLOT_BITS;
+        // the slots are visited in a round from the current one
+        let now_slot = elapsed / slot_range;
+        let zeros = self.occupied.rotate_right(now_slot as u32).trailing_zeros();
+        let slot = (now_slot + zeros as u64) as usize % SLOTS;
+        let mut tick = (elapsed & !(level_range - 1)) + slot as u64 * slot_range;
+        if tick <= elapsed {
+            // only the top level wraps around, the timer is in the next round
+            tick += level_range;
+        }
+        Some((slot, tick))
+    }
+}
+
+// the timing wheel, it's only accessed by the owner thread
+struct Wheel<T> {
+    levels: Vec<Level<T>>,
+    // all the slots before this tick are processed
+    elapsed: u64,
+    // the timers that expire in the elapsed tick but not yet
+    due: Vec<TimerEntry<T>>,
+    // a spare slot to swap with the processed one
+    spare: Vec<TimerEntry<T>>,
+    // number of the timers in the slots and `due`, including the removed
+    // ones that are not dropped yet
+    len: usize,
 }
 
-impl<T> cmp::Ord for IntervalEntry<T> {
-    fn cmp(&self, other: &Self) -> cmp::Ordering {
-        other.time.cmp(&self.time)
+impl<T> Wheel<T> {
+    fn new(now: u64) -> Self {
+        Wheel {
+            levels: (0..LEVELS).map(|_| Level::new()).collect(),
+            elapsed: now / TICK_NS,
+            due: Vec::new(),
+            spare: Vec::new(),
+            len: 0,
+        }
+    }
+
+    // put the timer into the slot, or into `expired` if it's expired at `now`
+    fn insert(&mut self, entry: TimerEntry<T>, now: u64, expired: &mut Vec<TimerEntry<T>>) {
+        let tick = entry.time / TICK_NS;
+        if tick <= self.elapsed {
+            if entry.time <= now {
+                expired.push(entry);
+            } else {
+                self.due.push(entry);
+                self.len += 1;
+            }
+            return;
+        }
+        let level = level_for(self.elapsed, tick);
+        let slot = (tick >> (level as u32 * SLOT_BITS)) as usize % SLOTS;
+        let level = &mut self.levels[level];
+        level.slots[slot].push(entry);
+        level.occupied |= 1 << slot;
+        self.len += 1;
+    }
+
+    // drop all the removed timers, so that the cancelled timers don't pile
+    // up in the slots until their expire time
+    fn purge(&mut self) {
+        let mut len = 0;
+        for level in self.levels.iter_mut() {
+            let mut occupied = level.occupied;
+            while occupied != 0 {
+                let slot = occupied.trailing_zeros() as usize;
+                occupied &= occupied - 1;
+                let entries = &mut level.slots[slot];
+                entries.retain(|e| !e.removed.load(Ordering::Relaxed));
+                if entries.is_empty() {
+                    level.occupied &= !(1 << slot);
+                }
+                len += entries.len();
+            }
+        }
+        self.due.retain(|e| !e.removed.load(Ordering::Relaxed));
+        self.len = len + self.due.len();
+    }
+
+    // the first slot to process, in (level, slot, tick)
+    fn next_expiration(&self) -> Option<(usize, usize, u64)> {
+        // the timers in the lower level always expire first
+        self.levels.iter().enumerate().find_map(|(i, level)| {
+            level
+                .next_expiration(i, self.elapsed)
+                .map(|(slot, tick)| (i, slot, tick))
+        })
+    }
+
+    // move the wheel to `now` and collect the expired timers, the timers in
+    // the passed slots of the upper levels are moved down
+    fn advance(&mut self, now: u64, expired: &mut Vec<TimerEntry<T>>) {
+        let now_tick = now / TICK_NS;
+        while let Some((level, slot, tick)) = self.next_expiration() {
+            if tick > now_tick {
+                break;
+            }
+            self.elapsed = tick;
+            let mut entries = mem::take(&mut self.spare);
+            let level = &mut self.levels[level];
+            mem::swap(&mut entries, &mut level.slots[slot]);
+            level.occupied &= !(1 << slot);
+            self.len -= entries.len();
+            for entry in entries.drain(..) {
+                if !entry.removed.load(Ordering::Relaxed) {
+                    self.insert(entry, now, expired);
+                }
+            }
+            self.spare = entries;
+        }
+        self.elapsed = self.elapsed.max(now_tick);
+
+        let mut i = 0;
+        while i < self.due.len() {
+            let entry = &self.due[i];
+            if entry.removed.load(Ordering::Relaxed) {
+                self.due.swap_remove(i);
+                self.len -= 1;
+            } else if entry.time <= now {
+                expired.push(self.due.swap_remove(i));
+                self.len -= 1;
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    // the clock time in ns that the wheel needs to be advanced again
+    fn next_time(&self) -> Option<u64> {
+        // the due timers are always earlier than those in the slots
+        let due = self.due.iter().map(|e| e.time).min();
+        due.or_else(|| self.next_expiration().map(|(_, _, tick)| tick * TICK_NS))
     }
 }
 
 // the timeout list data structure
+//
+// any thread can add a timer to the list through a lock free queue, but only
+// the owner thread, e.g. a worker or an io selector, runs the timers
 pub struct TimeOutList<T> {
-    // interval based hash map, protected by rw lock
-    interval_map: RwLock<HashMap<u64, IntervalList<T>>>,
-    // a priority queue, each element is the head of a mpsc queue
-    timer_bh: Mutex<BinaryHeap<IntervalEntry<T>>>,
+    // the newly added timers
+    added: Queue<TimerEntry<T>>,
+    // only touched by the owner thread
+    wheel: UnsafeCell<Wheel<T>>,
+    // the clock time in ns that the owner thread would check the timers
+    // again, the adders lower it and wake up the owner for an earlier timer,
+    // so it's never later than any pending timer
+    next_wake: AtomicU64,
+    // the time source of the timers
+    clock: Arc<Clock>,
+    // number of the timers that are neither fired nor removed
+    pending: Arc<AtomicUsize>,
 }
 
+unsafe impl<T: Send> Send for TimeOutList<T> {}
+unsafe impl<T: Send> Sync for TimeOutList<T> {}
+
 impl<T> TimeOutList<T> {
-    pub fn new() -> Self {
+    pub fn with_clock(clock: Arc<Clock>) -> Self {
         TimeOutList {
-            interval_map: RwLock::new(HashMap::with_capacity(HASH_CAP)),
-            timer_bh: Mutex::new(BinaryHeap::new()),
+            added: Queue::new(),
+            wheel: UnsafeCell::new(Wheel::new(clock.now())),
+            next_wake: AtomicU64::new(u64::MAX),
+            clock,
+            pending: Arc::new(AtomicUsize::new(0)),
         }
     }
 
-    fn install_timer_bh(&self, entry: IntervalEntry<T>) {
-        if entry.list.in_use.fetch_add(1, Ordering::AcqRel) == 0 {
-            self.timer_bh.lock().push(entry);
-        }
+    #[inline]
+    pub fn clock(&self) -> &Arc<Clock> {
+        &self.clock
+    }
+
+    // the clock time in ns that the owner thread would check the timers
+    // again, no pending timer expires earlier than it, and it's 0 while the
+    // owner is running the expired timers
+    #[inline]
+    pub fn next_wake(&self) -> u64 {
+        self.next_wake.load(Ordering::Acquire)
+    }
+
+    // number of the timers that are neither fired nor removed
+    #[inline]
+    pub fn pending(&self) -> usize {
+        self.pending.load(Ordering::Relaxed)
     }
 
     // add a timeout event to the list
     // this can be called in any thread
-    // return true if we need to recall next expire
+    // return true if the owner thread needs to be woken up to recall the
+    // next expire
     pub fn add_timer(&self, dur: Duration, data: T) -> (TimeoutHandle<T>, bool) {
-        let interval = dur_to_ns(dur);
-        let time = now() + interval; // TODO: deal with overflow?
-                                     //println!("add timer = {:?}", time);
-
-        let timeout = TimeoutData { time, data };
-
-        let interval_list = {
-            // use the read lock protect
-            let interval_map_r = self.interval_map.read();
-            (*interval_map_r).get(&interval).cloned()
-            // drop the read lock here
-        };
-
-        if let Some(interval_list) = interval_list {
-            let (handle, is_head) = interval_list.inner.push(timeout);
-            if is_head {
-                // install the interval list to the binary heap
-                self.install_timer_bh(IntervalEntry {
-                    time,
-                    interval,
-                    list: interval_list,
-                });
-            }
-            return (handle, is_head);
-        }
-
-        // if the interval list is not there, get the write locker to install the list
-        // use the write lock protect
-        let mut interval_map_w = self.interval_map.write();
-        // recheck the interval list in case other thread may install it
-        if let Some(interval_list) = (*interval_map_w).get(&interval) {
-            let (handle, is_head) = interval_list.inner.push(timeout);
-            if is_head {
-                // this rarely happens
-                self.install_timer_bh(IntervalEntry {
-                    time,
-                    interval,
-                    list: interval_list.clone(),
-                });
-            }
-            return (handle, is_head);
-        }
-
-        let interval_list = Arc::new(TimeoutQueueWrapper::<T>::new());
-        let ret = interval_list.inner.push(timeout).0;
-        (*interval_map_w).insert(interval, interval_list.clone());
-        // drop the write lock here
-        mem::drop(interval_map_w);
+        self.add_timer_at(self.clock.now().saturating_add(dur_to_ns(dur)), data)
+    }
 
-        // install the new interval list to the binary heap
-        self.install_timer_bh(IntervalEntry {
+    // add a timeout event that expires at the clock time in ns
+    pub fn add_timer_at(&self, time: u64, data: T) -> (TimeoutHandle<T>, bool) {
+        let entry = Arc::new(TimeoutData {
             time,
-            interval,
-            list: interval_list,
+            removed: AtomicBool::new(false),
+            data: AtomicOption::some(data),
+            pending: self.pending.clone(),
         });
-
-        (ret, true)
+        self.pending.fetch_add(1, Ordering::Relaxed);
+        self.added.push(entry.clone());
+        // pairs with the fence in `schedule_timer`, either the owner sees the
+        // new timer or we see the time it would wake up
+        fence(Ordering::SeqCst);
+        let wake = time < self.next_wake.fetch_min(time, Ordering::Relaxed);
+        (TimeoutHandle(entry), wake)
     }
 
-    // schedule in the timer thread
-    // this will remove all the expired timeout event
-    // and call the supplied function with registered data
-    // return the time in ns for the next expiration
+    // run the expired timers in the owner thread, this will call the supplied
+    // function with the registered data of all the expired timers
+    // return the time in ns for the next expiration, it's 0 if any timer is
+    // fired since the function may add new timers
     pub fn schedule_timer<F: Fn(T)>(&self, now: u64, f: &F) -> Option<u64> {
-        loop {
-            // first peek the BH to see if there is any timeout event
-            let mut entry = {
-                let mut timer_bh = self.timer_bh.lock();
-                let top_entry = timer_bh.peek();
-                match top_entry {
-                    // the latest timeout event not happened yet
-                    Some(entry) => {
-                        if entry.time > now {
-                            return Some(entry.time - now);
-                        } else {
-                            // find out one entry
-                        }
+        let mut expired = Vec::new();
+        {
+            // safety: only the owner thread can get here
+            let wheel = unsafe { &mut *self.wheel.get() };
+            loop {
+                while let Some(entry) = self.added.pop() {
+                    if !entry.removed.load(Ordering::Relaxed) {
+                        wheel.insert(entry, now, &mut expired);
                     }
-                    None => return None,
                 }
-                let entry = timer_bh.pop().unwrap();
-                entry.list.in_use.store(0, Ordering::Release);
-                entry
-            };
-
-            // consume all the timeout event
-            // the binary heap can be modified here
-            // during running the timeout handler
-            match entry.pop_timeout(now, f) {
-                Some(time) => {
-                    if entry.list.in_use.fetch_add(1, Ordering::AcqRel) == 0 {
-                        // re-push the entry
-                        entry.time = time;
-                        self.timer_bh.lock().push(entry);
-                    }
+                wheel.advance(now, &mut expired);
+                // the removed timers are dropped at once when they are more
+                // than the pending ones, so the cost of a purge is shared by
+                // the removals and the memory is bounded by the pending timers
+                if wheel.len > 2 * self.pending() + SLOTS {
+                    wheel.purge();
+                }
+                if !expired.is_empty() {
+                    break;
                 }
 
-                None => {
-                    // if the interval list is empty, need to delete it
-                    let mut interval_map_w = self.interval_map.write();
-                    // recheck if the interval list is empty, other thread may append data to it
-                    if entry.list.inner.is_empty() {
-                        // if the len of the hash map is big enough just leave the queue there
-                        if (*interval_map_w).len() > HASH_CAP {
-                            // the list is really empty now, we can safely remove it
-                            (*interval_map_w).remove(&entry.interval);
-                        }
-                    } else if entry.list.in_use.fetch_add(1, Ordering::AcqRel) == 0 {
-                        // release the w lock first, we don't need it any more
-                        mem::drop(interval_map_w);
-                        // the list is push some data by other thread
-                        entry.time = unsafe { entry.list.inner.peek() }.unwrap().time;
-                        self.timer_bh.lock().push(entry);
-                    }
+                let next = wheel.next_time();
+                self.next_wake
+                    .store(next.unwrap_or(u64::MAX), Ordering::Relaxed);
+                fence(Ordering::SeqCst);
+                // recheck the timers that are added before the store
+                if self.added.is_empty() {
+                    return next.map(|t| t.saturating_sub(now));
                 }
             }
         }
+
+        // the owner would check again right after the timers run
+        self.next_wake.store(0, Ordering::Relaxed);
+        expired.sort_unstable_by_key(|e| e.time);
+        for entry in expired {
+            if let Some(data) = entry.take() {
+                f(data);
+            }
+        }
+        Some(0)
     }
 }
 
-pub struct TimerThread<T> {
-    timer_list: TimeOutList<T>,
-    // collect the remove request
-    remove_list: Queue<TimeoutHandle<T>>,
-    // the timer thread wakeup handler
-    wakeup: AtomicOption<thread::Thread>,
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::thread;
 
-impl<T> TimerThread<T> {
-    pub fn new() -> Self {
-        TimerThread {
-            timer_list: TimeOutList::new(),
-            remove_list: Queue::new(),
-            wakeup: AtomicOption::none(),
+    const MS: u64 = NANOS_PER_MILLI;
+
+    // fire all the timers that expire at `now`, return the fired data and
+    // the time to check the timers again
+    fn fire(list: &TimeOutList<usize>, now: u64) -> (Vec<usize>, Option<u64>) {
+        let fired = RefCell::new(Vec::new());
+        let f = |d| fired.borrow_mut().push(d);
+        let mut next = list.schedule_timer(now, &f);
+        while next == Some(0) {
+            next = list.schedule_timer(now, &f);
         }
+        let mut fired = fired.into_inner();
+        fired.sort_unstable();
+        (fired, next)
     }
 
-    pub fn add_timer(&self, dur: Duration, data: T) -> TimeoutHandle<T> {
-        let (h, is_recal) = self.timer_list.add_timer(dur, data);
-        // wake up the timer thread if it's a new queue
-        if is_recal {
-            if let Some(t) = self.wakeup.take() {
-                t.unpark();
-            }
-        }
-        h
+    #[test]
+    fn test_timeout_list() {
+        let list = TimeOutList::with_clock(Arc::new(Clock::paused_at(0)));
+        list.add_timer(Duration::from_millis(1000), 10);
+        list.add_timer(Duration::from_millis(500), 40);
+        list.add_timer(Duration::from_millis(1200), 20);
+        list.add_timer(Duration::from_secs(3600), 30);
+        list.add_timer(Duration::from_nanos(1500), 50);
+
+        assert_eq!(fire(&list, 0), (vec![], Some(1500)));
+        let (fired, next) = fire(&list, 1500);
+        assert_eq!(fired, vec![50]);
+        // never wake up later than the next timer
+        assert!(next.unwrap() <= 500 * MS - 1500);
+        assert_eq!(fire(&list, 500 * MS - 1).0, vec![]);
+        assert_eq!(fire(&list, 500 * MS).0, vec![40]);
+        assert_eq!(fire(&list, 1200 * MS).0, vec![10, 20]);
+        assert_eq!(fire(&list, 3600 * 1000 * MS), (vec![30], None));
+    }
+
+    #[test]
+    fn test_remove_timer() {
+        let list = TimeOutList::with_clock(Arc::new(Clock::paused_at(0)));
+        let h1 = list.add_timer(Duration::from_millis(10), 1).0;
+        let h2 = list.add_timer(Duration::from_secs(100), 2).0;
+        list.add_timer(Duration::from_secs(100), 3);
+        assert_eq!(list.pending(), 3);
+        assert_eq!(h1.remove(), Some(1));
+        assert_eq!(list.pending(), 2);
+        assert_eq!(fire(&list, 100 * MS).0, vec![]);
+        assert_eq!(h2.remove(), Some(2));
+        assert_eq!(fire(&list, 100_000 * MS), (vec![3], None));
+        assert_eq!(list.pending(), 0);
     }
 
-    pub fn del_timer(&self, handle: TimeoutHandle<T>) {
-        self.remove_list.push(handle);
-        if let Some(t) = self.wakeup.take() {
-            t.unpark();
+    #[test]
+    fn test_purge_removed() {
+        let list = TimeOutList::with_clock(Arc::new(Clock::paused_at(0)));
+        let keep = list.add_timer(Duration::from_secs(60), 0).0;
+        for i in 1..10_000 {
+            let h = list.add_timer(Duration::from_secs(30), i).0;
+            assert_eq!(h.remove(), Some(i));
+            fire(&list, i as u64 * MS);
         }
+        // the removed timers don't wait for their slots to drop
+        let len = unsafe { (*list.wheel.get()).len };
+        assert!(len <= 2 + SLOTS, "{len}");
+        assert!(Arc::strong_count(&keep.0) > 1);
+        assert_eq!(fire(&list, 60_000 * MS), (vec![0], None));
     }
 
-    // the timer thread function
-    pub fn run<F: Fn(T)>(&self, f: &F) {
-        let current_thread = thread::current();
+    #[test]
+    fn test_wheel_levels() {
+        // the timers of all the levels fire in order and right in time
+        let start = 7 * MS + 3;
+        let list = TimeOutList::with_clock(Arc::new(Clock::paused_at(start)));
+        let durs: Vec<u64> = (0..LEVELS as u32)
+            .map(|l| 1 << (l * SLOT_BITS + 2))
+            .collect();
+        for (i, &d) in durs.iter().enumerate() {
+            list.add_timer(Duration::from_millis(d), i);
+        }
+        let mut now = start;
+        let mut fired = Vec::new();
         loop {
-            while let Some(h) = self.remove_list.pop() {
-                h.remove();
-            }
-            // we must register the thread handle first
-            // or there will be no signal to wakeup the timer thread
-            unsafe { self.wakeup.unsync_store(current_thread.clone()) };
-
-            if !self.remove_list.is_empty() {
-                if let Some(t) = self.wakeup.take() {
-                    t.unpark();
-                }
+            let (v, next) = fire(&list, now);
+            for i in v {
+                assert_eq!(now, start + durs[i] * MS);
+                fired.push(i);
             }
-
-            match self.timer_list.schedule_timer(now(), f) {
-                Some(time) => thread::park_timeout(ns_to_dur(time)),
-                None => thread::park(),
+            match next {
+                Some(next) => now += next,
+                None => break,
             }
         }
+        assert_eq!(fired, (0..LEVELS).collect::<Vec<_>>());
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::sync::Arc;
-    use std::thread;
-    use std::time::Duration;
 
     #[test]
-    fn test_timeout_list() {
-        let timer = Arc::new(TimerThread::<usize>::new());
-        let t = timer.clone();
-        let f = |data: usize| {
-            println!("timeout data:{data:?}");
-        };
-        thread::spawn(move || t.run(&f));
-        let t1 = timer.clone();
-        thread::spawn(move || {
-            t1.add_timer(Duration::from_millis(1000), 50);
-            t1.add_timer(Duration::from_millis(1000), 60);
-            t1.add_timer(Duration::from_millis(1400), 70);
-        });
-        thread::sleep(Duration::from_millis(10));
-        timer.add_timer(Duration::from_millis(1000), 10);
-        timer.add_timer(Duration::from_millis(500), 40);
-        timer.add_timer(Duration::from_millis(1200), 20);
-        thread::sleep(Duration::from_millis(100));
-        timer.add_timer(Duration::from_millis(1000), 30);
-
-        thread::sleep(Duration::from_millis(1500));
+    fn test_add_wakeup() {
+        let list = Arc::new(TimeOutList::with_clock(Arc::new(Clock::paused_at(0))));
+        // nothing is scheduled, always wake up the owner
+        assert!(list.add_timer(Duration::from_secs(10), 1).1);
+        assert_eq!(fire(&list, 0).0, vec![]);
+        // the later timers don't need to wake up the owner
+        let l = list.clone();
+        let wake = thread::spawn(move || l.add_timer(Duration::from_secs(20), 2).1);
+        assert!(!wake.join().unwrap());
+        assert!(list.add_timer(Duration::from_secs(5), 3).1);
+        assert_eq!(fire(&list, 0).0, vec![]);
+        assert_eq!(fire(&list, 20_000 * MS), (vec![1, 2, 3], None));
     }
 }
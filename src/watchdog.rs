@@ -0,0 +1,201 @@
+//! watchdog for the coroutines that block a worker thread for a long time
+//!
+//! Each worker records the coroutine it's running and the time it switched
+//! in. The watchdog thread checks the records periodically and reports the
+//! coroutines that have been running longer than the threshold.
+
+use std::fmt;
+use std::sync::atomic::{fence, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::coroutine_impl::Coroutine;
+use crate::scheduler::Scheduler;
+use crate::timeout_list::now;
+
+use crossbeam::utils::CachePadded;
+
+/// The report of a coroutine that blocks a worker thread for too long
+#[derive(Debug, Clone)]
+pub struct WatchdogReport {
+    /// the running coroutine, `None` if it's already finished
+    pub coroutine: Option<Coroutine>,
+    /// the id of the blocked worker thread
+    pub worker: usize,
+    /// how long the coroutine has been running since last switched in
+    pub elapsed: Duration,
+}
+
+impl fmt::Display for WatchdogReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.coroutine.as_ref() {
+            Some(co) => write!(
+                f,
+                "coroutine {} {:?}",
+                co.id(),
+                co.name().unwrap_or("<unnamed>")
+            )?,
+            None => f.write_str("coroutine \"<unnamed>\"")?,
+        }
+        write!(
+            f,
+            " has been running on worker {} for {:?}",
+            self.worker, self.elapsed
+        )
+    }
+}
+
+/// The user callback that is called for each watchdog report
+#[derive(Clone)]
+pub struct WatchdogCallback(Arc<dyn Fn(&WatchdogReport) + Send + Sync>);
+
+impl WatchdogCallback {
+    pub(crate) fn new<F>(f: F) -> Self
+    where
+        F: Fn(&WatchdogReport) + Send + Sync + 'static,
+    {
+        WatchdogCallback(Arc::new(f))
+    }
+}
+
+impl fmt::Debug for WatchdogCallback {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("WatchdogCallback")
+    }
+}
+
+/// the record of the coroutine that is running on a worker
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Running {
+    // the live set slot of the coroutine
+    slot: usize,
+    // the coroutine id, tells if the slot is taken by another one later
+    id: u64,
+    // the time in ns that the coroutine is switched in, 0 means idle
+    since: u64,
+}
+
+/// the coroutine that is running on a worker
+///
+/// only the worker writes the record, the watchdog reads it under a seqlock
+#[derive(Default)]
+pub(crate) struct WorkerClock {
+    // odd while the worker is writing the record
+    seq: AtomicUsize,
+    slot: AtomicUsize,
+    id: AtomicU64,
+    since: AtomicU64,
+}
+
+impl WorkerClock {
+    /// record the coroutine that is switched in, return the previous record
+    #[inline]
+    pub fn enter(&self, co: &Coroutine) -> Running {
+        // the worker is the only writer, no need to check the seq
+        let prev = Running {
+            slot: self.slot.load(Ordering::Relaxed),
+            id: self.id.load(Ordering::Relaxed),
+            since: self.since.load(Ordering::Relaxed),
+        };
+        self.store(Running {
+            slot: co.live_slot(),
+            id: co.id().as_u64().get(),
+            since: now().max(1),
+        });
+        prev
+    }
+
+    /// restore the previous record after the coroutine is switched out
+    #[inline]
+    pub fn leave(&self, prev: Running) {
+        self.store(prev);
+    }
+
+    #[inline]
+    fn store(&self, running: Running) {
+        let seq = self.seq.load(Ordering::Relaxed);
+        self.seq.store(seq.wrapping_add(1), Ordering::Relaxed);
+        fence(Ordering::Release);
+        self.slot.store(running.slot, Ordering::Relaxed);
+        self.id.store(running.id, Ordering::Relaxed);
+        self.since.store(running.since, Ordering::Relaxed);
+        self.seq.store(seq.wrapping_add(2), Ordering::Release);
+    }
+
+    // read a consistent record, retry if the worker is writing it
+    fn load(&self) -> Running {
+        loop {
+            let seq = self.seq.load(Ordering::Acquire);
+            if seq & 1 == 1 {
+                std::hint::spin_loop();
+                continue;
+            }
+            let running = Running {
+                slot: self.slot.load(Ordering::Relaxed),
+                id: self.id.load(Ordering::Relaxed),
+                since: self.since.load(Ordering::Relaxed),
+            };
+            fence(Ordering::Acquire);
+            if self.seq.load(Ordering::Relaxed) == seq {
+                return running;
+            }
+        }
+    }
+}
+
+pub(crate) struct Watchdog {
+    threshold: Duration,
+    callback: Option<WatchdogCallback>,
+    clocks: Vec<CachePadded<WorkerClock>>,
+}
+
+impl Watchdog {
+    pub fn new(workers: usize, threshold: Duration, callback: Option<WatchdogCallback>) -> Self {
+        Watchdog {
+            threshold,
+            callback,
+            clocks: (0..workers).map(|_| Default::default()).collect(),
+        }
+    }
+
+    #[inline]
+    pub fn clock(&self, id: usize) -> &WorkerClock {
+        unsafe { self.clocks.get_unchecked(id) }
+    }
+
+    /// the watchdog thread main loop, exit when the scheduler is stopped
+    pub fn run(&self, sched: &Scheduler) {
+        let threshold = self.threshold.as_nanos() as u64;
+        // check a few times within the threshold
+        let interval =
+            (self.threshold / 4).clamp(Duration::from_millis(1), Duration::from_millis(100));
+        // the last reported record of each worker, report it only once
+        let mut reported = vec![Running::default(); self.clocks.len()];
+
+        while !sched.is_stopped() {
+            thread::park_timeout(interval);
+            let now = now();
+            for (id, clock) in self.clocks.iter().enumerate() {
+                let running = clock.load();
+                let since = running.since;
+                if since == 0 || now < since + threshold || reported[id] == running {
+                    continue;
+                }
+                reported[id] = running;
+
+                // the slot may be taken by another coroutine after it's done
+                let co = sched.coroutines.get(running.slot);
+                let report = WatchdogReport {
+                    coroutine: co.filter(|co| co.id().as_u64().get() == running.id),
+                    worker: id,
+                    elapsed: Duration::from_nanos(now - since),
+                };
+                warn!("{report}, it may be blocking the worker thread");
+                if let Some(WatchdogCallback(f)) = self.callback.as_ref() {
+                    f(&report);
+                }
+            }
+        }
+    }
+}
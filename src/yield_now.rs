@@ -1,5 +1,9 @@
+#[cfg(feature = "task_dump")]
+use crate::coroutine_impl::current_set_state;
 use crate::coroutine_impl::{current_cancel_data, is_coroutine};
 use crate::coroutine_impl::{CoroutineImpl, EventResult, EventSource, EventSubscriber};
+#[cfg(feature = "task_dump")]
+use crate::dump::BlockState;
 use crate::likely::{likely, unlikely};
 use crate::scheduler::get_scheduler;
 
@@ -9,8 +13,13 @@ struct Yield {}
 
 impl EventSource for Yield {
     fn subscribe(&mut self, co: CoroutineImpl) {
-        // just re-push the coroutine to the ready list
-        get_scheduler().schedule(co);
+        // just re-push the coroutine to the back of the ready list
+        get_scheduler().schedule_fifo(co);
+    }
+
+    #[cfg(feature = "task_dump")]
+    fn block_state(&self) -> BlockState {
+        BlockState::Queued
     }
 }
 
@@ -30,6 +39,8 @@ pub fn yield_with<T: EventSource>(resource: &T) {
         return resource.yield_back(cancel);
     }
 
+    #[cfg(feature = "task_dump")]
+    current_set_state(resource.block_state());
     let r = resource as &dyn EventSource as *const _ as *mut _;
     let es = EventSubscriber::new(r);
     co_yield_with(es);
@@ -45,6 +56,8 @@ pub fn yield_with_io<T: EventSource>(resource: &T, is_coroutine: bool) {
         yield_with(resource);
         #[cfg(not(feature = "io_cancel"))]
         {
+            #[cfg(feature = "task_dump")]
+            current_set_state(resource.block_state());
             let r = resource as &dyn EventSource as *const _ as *mut _;
             let es = EventSubscriber::new(r);
             co_yield_with(es);
@@ -0,0 +1,55 @@
+extern crate may;
+
+use std::env;
+use std::fs;
+
+use may::CpuAffinity;
+use tempdir::TempDir;
+
+// the settings are loaded only once, so all the checks are in one test
+#[test]
+fn config_env_and_file() {
+    let dir = TempDir::new("may_config").unwrap();
+    let path = dir.path().join("may.conf");
+    fs::write(
+        &path,
+        "# the file settings\n\
+         MAY_WORKERS = 3\n\
+         MAY_STACK_SIZE = 0x4000\n\
+         MAY_POOL_CAPACITY = 7\n",
+    )
+    .unwrap();
+    env::set_var("MAY_CONFIG", &path);
+    // the env variables take precedence over the file
+    env::set_var("MAY_POOL_CAPACITY", "9");
+    env::set_var("MAY_STEAL_BATCH", "16");
+    env::set_var("MAY_CPU_AFFINITY", "0,2");
+    env::set_var("MAY_LIFO_SLOT", "off");
+
+    let config = may::config();
+    assert_eq!(config.get_workers(), 3);
+    assert_eq!(config.get_stack_size(), 0x4000);
+    assert_eq!(config.get_pool_capacity(), 9);
+    assert_eq!(config.get_steal_batch(), 16);
+    assert_eq!(config.get_cpu_affinity(), CpuAffinity::Cores(vec![0, 2]));
+    assert!(!config.get_lifo_slot());
+
+    // the explicit setters take precedence over the env variables
+    config.set_pool_capacity(20).set_steal_batch(8);
+    assert_eq!(config.get_pool_capacity(), 20);
+    assert_eq!(config.get_steal_batch(), 8);
+
+    // a loaded file overrides the earlier settings
+    let other = dir.path().join("other.conf");
+    fs::write(&other, "MAY_WORKERS=2\nMAY_POOL_CAPACITY=5\n").unwrap();
+    config.load_file(&other).unwrap();
+    assert_eq!(config.get_workers(), 2);
+    assert_eq!(config.get_pool_capacity(), 5);
+    assert_eq!(config.get_steal_batch(), 8);
+
+    let bad = dir.path().join("bad.conf");
+    fs::write(&bad, "MAY_WORKERS 4\n").unwrap();
+    assert!(config.load_file(&bad).is_err());
+    assert!(config.load_file(dir.path().join("missing.conf")).is_err());
+    assert_eq!(config.get_workers(), 2);
+}
@@ -50,6 +50,218 @@ fn cancel_coroutine() {
     }
 }
 
+#[test]
+fn join_timeout_and_abort() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    struct Guard(Arc<AtomicBool>);
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::Release);
+        }
+    }
+
+    let dropped = Arc::new(AtomicBool::new(false));
+    let guard = Guard(dropped.clone());
+    let j = go!(move || {
+        let _guard = guard;
+        coroutine::sleep(Duration::from_secs(1000000));
+    });
+
+    // the handle is given back on timeout
+    let j = j.join_timeout(Duration::from_millis(10)).unwrap_err();
+    assert!(!j.is_done());
+
+    // the coroutine is unwound when abort returns
+    match j.abort() {
+        Ok(_) => panic!("test should return panic"),
+        Err(panic) => match panic.downcast_ref::<generator::Error>() {
+            Some(&generator::Error::Cancel) => {}
+            _ => panic!("panic type wrong"),
+        },
+    }
+    assert!(dropped.load(Ordering::Acquire));
+
+    // a finished coroutine returns its result
+    let j = go!(|| 42);
+    assert_eq!(
+        j.join_timeout(Duration::from_secs(10)).unwrap().unwrap(),
+        42
+    );
+    let j = go!(|| 42);
+    j.wait();
+    assert_eq!(j.abort().unwrap(), 42);
+}
+
+#[test]
+fn join_timeout_race() {
+    // the coroutines finish at about the timeout
+    fn check(i: u64) {
+        let j = go!(move || {
+            coroutine::sleep(Duration::from_micros(i % 3 * 500));
+            i
+        });
+        match j.join_timeout(Duration::from_millis(1)) {
+            Ok(ret) => assert_eq!(ret.unwrap(), i),
+            Err(j) => assert_eq!(j.join().unwrap(), i),
+        }
+    }
+
+    for i in 0..200 {
+        check(i);
+    }
+
+    go!(|| {
+        for i in 0..200 {
+            check(i);
+            // no stale wakeup is left for the next park
+            let now = Instant::now();
+            coroutine::park_timeout(Duration::from_millis(1));
+            assert!(now.elapsed() >= Duration::from_millis(1));
+        }
+    })
+    .join()
+    .unwrap();
+}
+
+#[test]
+fn coroutine_timeout() {
+    use may::sync::{mpsc, Mutex};
+
+    // the closure can borrow the stack and is unwound at the deadline
+    let lock = Mutex::new(0);
+    let (tx, rx) = mpsc::channel::<()>();
+    let g = lock.lock().unwrap();
+    let ret = coroutine::timeout(Duration::from_millis(10), || {
+        let _g = lock.lock().unwrap();
+        rx.recv().unwrap();
+    });
+    assert!(ret.is_err());
+    drop(g);
+    assert!(lock.try_lock().is_ok());
+    let ret = coroutine::timeout(Duration::from_millis(10), || rx.recv());
+    assert_eq!(ret.unwrap_err().to_string(), "deadline has elapsed");
+    drop(tx);
+
+    // the timeouts nest
+    let start = Instant::now();
+    let ret = coroutine::timeout(Duration::from_millis(20), || {
+        coroutine::timeout(Duration::from_secs(10), || {
+            coroutine::sleep(Duration::from_secs(10));
+        })
+    });
+    assert!(ret.is_err());
+    let ret = coroutine::timeout(Duration::from_secs(10), || {
+        coroutine::timeout(Duration::from_millis(10), || {
+            coroutine::sleep(Duration::from_secs(10));
+        })
+    });
+    assert!(ret.unwrap().is_err());
+    assert!(start.elapsed() < Duration::from_secs(5));
+
+    // works in a coroutine and returns the result
+    let j = go!(|| coroutine::timeout(Duration::from_secs(10), || 42));
+    assert_eq!(j.join().unwrap(), Ok(42));
+
+    // the panic is resumed in the caller
+    let ret = std::panic::catch_unwind(|| {
+        coroutine::timeout(Duration::from_secs(10), || panic!("timeout panic"))
+    });
+    assert!(ret.is_err());
+}
+
+#[test]
+fn coroutine_deadline() {
+    use may::sync::mpsc;
+    use std::io::Read;
+
+    fn is_cancel(panic: Box<dyn std::any::Any + Send>) -> bool {
+        matches!(
+            panic.downcast_ref::<generator::Error>(),
+            Some(generator::Error::Cancel)
+        )
+    }
+
+    let start = Instant::now();
+    let (tx, rx) = mpsc::channel::<()>();
+    let j = go!(move || {
+        assert_eq!(coroutine::deadline(), None);
+        coroutine::set_deadline(may::time::now() + Duration::from_millis(20));
+        // the earlier own timeout is kept
+        let ret = rx.recv_timeout(Duration::from_millis(5));
+        assert_eq!(ret, Err(std::sync::mpsc::RecvTimeoutError::Timeout));
+
+        // the io call without timeout returns a timeout error at the deadline
+        #[cfg(feature = "io_timeout")]
+        {
+            let listener = may::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            let mut s = may::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+            let ret = s.read(&mut [0; 8]);
+            assert_eq!(ret.unwrap_err().kind(), std::io::ErrorKind::TimedOut);
+        }
+
+        // the scoped coroutines inherit the deadline
+        let deadline = coroutine::deadline();
+        coroutine::scope(|scope| {
+            go!(scope, || assert_eq!(coroutine::deadline(), deadline));
+        });
+
+        // the call without timeout unwinds at the deadline
+        rx.recv().unwrap();
+    });
+    assert!(is_cancel(j.join().unwrap_err()));
+
+    // the sleep is capped at the deadline and returns normally
+    let j = go!(|| {
+        coroutine::set_deadline(may::time::now() + Duration::from_millis(10));
+        coroutine::sleep(Duration::from_secs(10));
+    });
+    j.join().unwrap();
+    assert!(start.elapsed() < Duration::from_secs(5));
+    drop(tx);
+
+    // the cleared deadline has no effect
+    let j = go!(|| {
+        coroutine::set_deadline(may::time::now());
+        coroutine::clear_deadline();
+        coroutine::sleep(Duration::from_millis(1));
+    });
+    j.join().unwrap();
+
+    // the io in the cleanup of the unwinding coroutine ignores the deadline
+    #[cfg(feature = "io_timeout")]
+    {
+        use std::io::Write;
+        use std::sync::{Arc, Mutex};
+
+        struct Cleanup(
+            may::net::TcpStream,
+            Arc<Mutex<Option<std::io::Result<usize>>>>,
+        );
+        impl Drop for Cleanup {
+            fn drop(&mut self) {
+                *self.1.lock().unwrap() = Some(self.0.read(&mut [0; 8]));
+            }
+        }
+
+        let listener = may::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let s = may::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut peer, _) = listener.accept().unwrap();
+        let ret = Arc::new(Mutex::new(None));
+        let r = ret.clone();
+        let j = go!(move || {
+            let _cleanup = Cleanup(s, r);
+            coroutine::set_deadline(may::time::now() + Duration::from_millis(10));
+            coroutine::park();
+        });
+        thread::sleep(Duration::from_millis(50));
+        peer.write_all(b"done").unwrap();
+        assert!(is_cancel(j.join().unwrap_err()));
+        assert_eq!(ret.lock().unwrap().take().unwrap().unwrap(), 4);
+    }
+}
+
 #[test]
 #[cfg(feature = "io_cancel")]
 fn cancel_io_coroutine() {
@@ -192,6 +404,37 @@ fn scoped_coroutine() {
     assert_eq!(array[2], 4);
 }
 
+#[test]
+fn scoped_cancellation_token() {
+    assert!(coroutine::cancellation_token().is_none());
+    let mut done = 0;
+    let outer = coroutine::scope(|scope| {
+        // the scope token stops the coroutines waiting for it
+        go!(scope, || {
+            let token = coroutine::cancellation_token().unwrap();
+            token.cancelled();
+            done += 1;
+        });
+        go!(scope, || {
+            let outer = coroutine::cancellation_token().unwrap();
+            // the token of the inner scope is a child of the outer one
+            coroutine::scope(|inner| {
+                go!(inner, || coroutine::cancellation_token()
+                    .unwrap()
+                    .cancelled());
+                outer.cancel();
+            });
+        });
+        scope.token().clone()
+    });
+    assert_eq!(done, 1);
+    assert!(outer.is_cancelled());
+
+    // the token is cancelled when the scope ends
+    let token = coroutine::scope(|scope| scope.token().clone());
+    assert!(token.is_cancelled());
+}
+
 #[test]
 fn yield_from_gen() {
     let mut a = 0;
@@ -215,6 +458,43 @@ fn yield_from_gen() {
     assert_eq!(a, 10);
 }
 
+#[test]
+fn coroutine_id() {
+    use std::collections::HashSet;
+
+    let handles = (0..10)
+        .map(|_| go!(|| coroutine::current().id()))
+        .collect::<Vec<_>>();
+    let ids = handles
+        .iter()
+        .map(|h| h.coroutine().id())
+        .collect::<Vec<_>>();
+    // the ids are increasing in the spawn order
+    assert!(ids.windows(2).all(|w| w[0] < w[1]));
+    for (h, id) in handles.into_iter().zip(&ids) {
+        assert_eq!(h.join().unwrap(), *id);
+    }
+
+    // the ids of the finished coroutines are never reused
+    let more = (0..10).map(|_| go!(|| {})).collect::<Vec<_>>();
+    let mut seen = ids.into_iter().collect::<HashSet<_>>();
+    for h in more {
+        assert!(seen.insert(h.coroutine().id()));
+        h.join().unwrap();
+    }
+
+    let builder = coroutine::Builder::new().name("named".to_owned());
+    let h = unsafe { builder.spawn(|| {}).unwrap() };
+    let id = h.coroutine().id();
+    let debug = format!("{:?}", h.coroutine());
+    assert_eq!(
+        debug,
+        format!("Coroutine {{ id: {id:?}, name: Some(\"named\") }}")
+    );
+    assert_eq!(id.to_string(), id.as_u64().to_string());
+    h.join().unwrap();
+}
+
 #[test]
 #[allow(unused_assignments)]
 fn unpark() {
@@ -267,6 +547,31 @@ fn park_timeout() {
     assert_eq!(a, 10);
 }
 
+#[test]
+fn park_timeout_zero() {
+    let j = go!(|| {
+        // a zero timeout returns at once instead of parking for 1ms
+        let now = Instant::now();
+        for _ in 0..100 {
+            coroutine::park_timeout(Duration::ZERO);
+        }
+        assert!(now.elapsed() < Duration::from_millis(100));
+
+        // the pending unpark is still consumed
+        coroutine::current().unpark();
+        coroutine::park_timeout(Duration::ZERO);
+        let now = Instant::now();
+        coroutine::park_timeout(Duration::from_millis(10));
+        assert!(now.elapsed() >= Duration::from_millis(10));
+
+        // the passed deadline returns at once for a park with timeout
+        coroutine::set_deadline(may::time::now());
+        coroutine::park_timeout(Duration::from_secs(10));
+        coroutine::clear_deadline();
+    });
+    j.join().unwrap();
+}
+
 #[test]
 fn test_sleep() {
     let now = Instant::now();
@@ -346,3 +651,972 @@ fn go_with_macro() {
         assert_eq!(stack_size, 10240);
     }
 }
+
+#[test]
+fn runtime_block_on() {
+    use may::sync::mpsc::channel;
+    use may::RuntimeBuilder;
+
+    let rt1 = RuntimeBuilder::new().workers(1).build().unwrap();
+    let rt2 = RuntimeBuilder::new()
+        .workers(2)
+        .stack_size(0x2000)
+        .build()
+        .unwrap();
+    assert_eq!(rt1.workers(), 1);
+    assert_eq!(rt2.workers(), 2);
+
+    let (tx, rx) = channel();
+    let j = unsafe {
+        rt1.spawn(move || {
+            // the child coroutine would run on the same runtime
+            let tx1 = tx.clone();
+            go!(move || {
+                coroutine::sleep(Duration::from_millis(10));
+                tx1.send(1).unwrap();
+            })
+            .join()
+            .unwrap();
+            tx.send(2).unwrap();
+        })
+    };
+
+    let stack_size = unsafe {
+        rt2.block_on(move || {
+            let a = rx.recv().unwrap();
+            let b = rx.recv().unwrap();
+            assert_eq!((a, b), (1, 2));
+            coroutine::current().stack_size()
+        })
+    };
+    assert_eq!(stack_size, 0x2000);
+    j.join().unwrap();
+}
+
+#[test]
+fn runtime_shutdown() {
+    use may::RuntimeBuilder;
+
+    let rt = RuntimeBuilder::new().workers(2).build().unwrap();
+    let builder = coroutine::Builder::new().name("parked".to_owned());
+    let parked = unsafe { rt.spawn_with(builder, coroutine::park).unwrap() };
+    let done = unsafe {
+        rt.spawn(|| {
+            coroutine::sleep(Duration::from_millis(10));
+            1
+        })
+    };
+
+    let report = rt.shutdown(Duration::from_millis(100)).unwrap();
+    assert!(!report.is_clean());
+    assert_eq!(report.alive_count(), 1);
+    assert_eq!(report.detached_threads(), 0);
+    assert_eq!(done.join().unwrap(), 1);
+
+    // the parked coroutine is canceled
+    assert_eq!(report.alive()[0].name(), Some("parked"));
+    assert!(report.remaining().is_empty());
+    assert_eq!(report.remaining_count(), 0);
+    assert!(parked.is_done());
+    assert!(parked.join().is_err());
+}
+
+#[test]
+fn runtime_shutdown_blocked_thread() {
+    use may::RuntimeBuilder;
+
+    let rt = RuntimeBuilder::new().workers(1).build().unwrap();
+    let builder = coroutine::Builder::new().name("blocking".to_owned());
+    // the cancel can't stop a thread blocking call
+    let blocking = unsafe {
+        rt.spawn_with(builder, || thread::sleep(Duration::from_millis(500)))
+            .unwrap()
+    };
+    thread::sleep(Duration::from_millis(10));
+
+    let start = Instant::now();
+    let report = rt.shutdown(Duration::from_millis(20)).unwrap();
+    assert!(start.elapsed() < Duration::from_millis(400));
+    assert_eq!(report.alive_count(), 1);
+    assert_eq!(report.remaining_count(), 1);
+    assert_eq!(report.remaining()[0].name(), Some("blocking"));
+    assert_eq!(report.detached_threads(), 1);
+
+    // the detached thread still finishes the coroutine
+    blocking.join().unwrap();
+}
+
+#[test]
+fn runtime_metrics() {
+    use may::RuntimeBuilder;
+
+    let rt = RuntimeBuilder::new()
+        .workers(2)
+        .max_workers(2)
+        .pool_capacity(10)
+        .build()
+        .unwrap();
+    let mut handles: Vec<_> = (0..4)
+        .map(|_| unsafe { rt.spawn(|| coroutine::sleep(Duration::from_millis(200))) })
+        .collect();
+    // the io timeouts are counted as well
+    #[cfg(feature = "io_timeout")]
+    handles.push(unsafe {
+        rt.spawn(|| {
+            let sock = may::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+            sock.set_read_timeout(Some(Duration::from_millis(200)))
+                .unwrap();
+            assert!(sock.recv(&mut [0; 8]).is_err());
+        })
+    });
+    let n = handles.len();
+    thread::sleep(Duration::from_millis(50));
+
+    let metrics = rt.metrics();
+    assert_eq!(metrics.workers.len(), 2);
+    assert_eq!(metrics.live_coroutines, n);
+    assert_eq!(metrics.pending_timers, n);
+    assert_eq!(metrics.pool.hits, n);
+    assert_eq!(metrics.pool.capacity, 10);
+    let selects: usize = metrics.workers.iter().map(|w| w.select_count).sum();
+    assert!(selects > 0);
+
+    for h in handles {
+        h.join().unwrap();
+    }
+    // the coroutines go back to the pool after the join handles are triggered
+    let start = Instant::now();
+    while rt.metrics().pool.size < 10 && start.elapsed() < Duration::from_secs(1) {
+        thread::sleep(Duration::from_millis(1));
+    }
+    let metrics = rt.metrics();
+    assert_eq!(metrics.live_coroutines, 0);
+    assert_eq!(metrics.pending_timers, 0);
+    assert_eq!(metrics.pool.size, 10);
+}
+
+#[test]
+#[cfg(feature = "task_dump")]
+fn runtime_dump() {
+    use may::coroutine::CoroutineState;
+    use may::RuntimeBuilder;
+
+    let rt = RuntimeBuilder::new().workers(1).build().unwrap();
+    let builder = coroutine::Builder::new().name("parked".to_owned());
+    let parked = unsafe { rt.spawn_with(builder, coroutine::park).unwrap() };
+    let sleeping = unsafe { rt.spawn(|| coroutine::sleep(Duration::from_millis(200))) };
+    thread::sleep(Duration::from_millis(50));
+
+    let dump = rt.dump();
+    assert_eq!(dump.coroutines().len(), 2);
+    let info = dump
+        .coroutines()
+        .iter()
+        .find(|info| info.co.name() == Some("parked"))
+        .unwrap();
+    assert_eq!(info.location.file(), file!());
+    assert_eq!(info.co.id(), parked.coroutine().id());
+    let line = format!(
+        "coroutine {} \"parked\" spawned at",
+        parked.coroutine().id()
+    );
+    assert!(info.to_string().starts_with(&line));
+    assert_eq!(info.state, CoroutineState::Parked);
+    let info = dump
+        .coroutines()
+        .iter()
+        .find(|info| info.co.name().is_none())
+        .unwrap();
+    assert!(matches!(info.state, CoroutineState::Sleeping(_)));
+    println!("{dump}");
+
+    parked.coroutine().unpark();
+    parked.join().unwrap();
+    sleeping.join().unwrap();
+}
+
+#[test]
+fn runtime_watchdog() {
+    use may::RuntimeBuilder;
+    use std::sync::mpsc::channel;
+    use std::sync::Mutex;
+
+    let (tx, rx) = channel();
+    let tx = Mutex::new(tx);
+    let rt = RuntimeBuilder::new()
+        .workers(2)
+        .watchdog(Some(Duration::from_millis(50)))
+        .watchdog_callback(move |report| {
+            let name = report
+                .coroutine
+                .as_ref()
+                .and_then(|co| co.name().map(String::from));
+            tx.lock()
+                .unwrap()
+                .send((name, report.worker, report.elapsed))
+                .unwrap();
+        })
+        .build()
+        .unwrap();
+
+    let builder = coroutine::Builder::new().name("blocking".to_owned());
+    let h = unsafe {
+        rt.spawn_with(builder, || thread::sleep(Duration::from_millis(300)))
+            .unwrap()
+    };
+    let (name, worker, elapsed) = rx.recv_timeout(Duration::from_secs(1)).unwrap();
+    assert_eq!(name.as_deref(), Some("blocking"));
+    assert!(worker < 2);
+    assert!(elapsed >= Duration::from_millis(50));
+    h.join().unwrap();
+    // report only once for each blocking
+    assert!(rx.try_recv().is_err());
+}
+
+#[test]
+fn spawn_blocking() {
+    use may::RuntimeBuilder;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let rt = RuntimeBuilder::new()
+        .workers(1)
+        .blocking_threads(4)
+        .build()
+        .unwrap();
+    let now = Instant::now();
+    let handles: Vec<_> = (0..4)
+        .map(|i| unsafe {
+            rt.spawn(move || {
+                let h = coroutine::spawn_blocking(move || {
+                    thread::sleep(Duration::from_millis(100));
+                    i
+                })
+                .unwrap();
+                h.join().unwrap()
+            })
+        })
+        .collect();
+    for (i, h) in handles.into_iter().enumerate() {
+        assert_eq!(h.join().unwrap(), i);
+    }
+    // the single worker is not blocked by the sleeps
+    assert!(now.elapsed() < Duration::from_millis(300));
+
+    let ret = unsafe {
+        rt.block_on(|| {
+            coroutine::spawn_blocking(|| panic!("blocking panic"))
+                .unwrap()
+                .join()
+        })
+    };
+    assert!(ret.is_err());
+
+    // the pool threads run the thread hooks
+    let started = Arc::new(AtomicUsize::new(0));
+    let s = started.clone();
+    let rt = RuntimeBuilder::new()
+        .workers(1)
+        .max_workers(1)
+        .on_thread_start(move || {
+            s.fetch_add(1, Ordering::SeqCst);
+        })
+        .build()
+        .unwrap();
+    let name = unsafe {
+        rt.block_on(|| {
+            let h = coroutine::spawn_blocking(|| thread::current().name().map(str::to_owned));
+            h.unwrap().join().unwrap()
+        })
+    };
+    assert_eq!(name.as_deref(), Some("may-blocking"));
+    assert_eq!(started.load(Ordering::SeqCst), 2);
+
+    // no new blocking function is accepted once the shutdown is started
+    let h = unsafe {
+        rt.spawn(|| {
+            coroutine::sleep(Duration::from_millis(50));
+            coroutine::spawn_blocking(|| ()).is_err()
+        })
+    };
+    rt.shutdown(Duration::from_secs(1)).unwrap();
+    assert!(h.join().unwrap());
+}
+
+#[test]
+fn runtime_cpu_affinity() {
+    use may::{CpuAffinity, RuntimeBuilder};
+
+    for affinity in [
+        CpuAffinity::Disabled,
+        CpuAffinity::Cores(vec![0]),
+        CpuAffinity::ProcessMask,
+    ] {
+        let rt = RuntimeBuilder::new()
+            .workers(2)
+            .cpu_affinity(affinity)
+            .build()
+            .unwrap();
+        let name = unsafe { rt.block_on(|| thread::current().name().map(String::from)) };
+        let name = name.unwrap();
+        assert!(name == "may-worker-0" || name == "may-worker-1");
+    }
+}
+
+#[test]
+fn runtime_thread_hooks() {
+    use may::RuntimeBuilder;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let started = Arc::new(AtomicUsize::new(0));
+    let stopped = Arc::new(AtomicUsize::new(0));
+    let (s1, s2) = (started.clone(), stopped.clone());
+    let rt = RuntimeBuilder::new()
+        .workers(2)
+        .max_workers(2)
+        .thread_stack_size(0x40_0000)
+        .on_thread_start(move || {
+            s1.fetch_add(1, Ordering::SeqCst);
+        })
+        .on_thread_stop(move || {
+            s2.fetch_add(1, Ordering::SeqCst);
+        })
+        .build()
+        .unwrap();
+    assert_eq!(unsafe { rt.block_on(|| 1) }, 1);
+    assert!(started.load(Ordering::SeqCst) > 0);
+    assert_eq!(stopped.load(Ordering::SeqCst), 0);
+
+    rt.shutdown(Duration::from_millis(100)).unwrap();
+    // the workers run the timers, there is no timer thread
+    assert_eq!(started.load(Ordering::SeqCst), 2);
+    assert_eq!(stopped.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn runtime_release() {
+    use may::{CurrentThreadRuntime, RuntimeBuilder};
+    use std::sync::Arc;
+
+    // the hooks are owned by the scheduler, so they are dropped with it
+    let hook = Arc::new(());
+    let h = hook.clone();
+    let rt = RuntimeBuilder::new()
+        .workers(2)
+        .on_thread_start(move || assert!(Arc::strong_count(&h) > 1))
+        .build()
+        .unwrap();
+    assert_eq!(unsafe { rt.block_on(|| 1) }, 1);
+    assert_eq!(Arc::strong_count(&hook), 2);
+    drop(rt);
+    assert_eq!(Arc::strong_count(&hook), 1);
+
+    let h = hook.clone();
+    let rt = RuntimeBuilder::new()
+        .on_thread_start(move || assert!(Arc::strong_count(&h) > 1))
+        .build_current_thread()
+        .unwrap();
+    assert_eq!(unsafe { rt.block_on(|| 1) }, 1);
+    rt.shutdown(Duration::from_millis(100)).unwrap();
+    assert_eq!(Arc::strong_count(&hook), 1);
+
+    // a live io object still refers to the scheduler, so it's leaked
+    let h = hook.clone();
+    let rt = RuntimeBuilder::new()
+        .on_thread_start(move || assert!(Arc::strong_count(&h) > 1))
+        .build()
+        .unwrap();
+    let listener = unsafe { rt.block_on(|| may::net::TcpListener::bind("127.0.0.1:0").unwrap()) };
+    drop(rt);
+    assert_eq!(Arc::strong_count(&hook), 2);
+    drop(listener);
+
+    drop(CurrentThreadRuntime::new().unwrap());
+}
+
+#[test]
+fn coroutine_priority() {
+    use may::coroutine::Priority;
+    use may::RuntimeBuilder;
+    use std::sync::{Arc, Mutex};
+
+    let rt = RuntimeBuilder::new().workers(1).build().unwrap();
+    let order = unsafe {
+        rt.block_on(|| {
+            let order = Arc::new(Mutex::new(Vec::new()));
+            let mut handles = Vec::new();
+            // spawned while the only worker is busy, so they are all queued
+            for priority in [Priority::Low, Priority::Normal, Priority::High] {
+                for _ in 0..2 {
+                    let order = order.clone();
+                    let builder = coroutine::Builder::new().priority(priority);
+                    let h = builder.spawn(move || {
+                        order.lock().unwrap().push(coroutine::current().priority());
+                        // keep the priority after rescheduled
+                        coroutine::yield_now();
+                        order.lock().unwrap().push(coroutine::current().priority());
+                    });
+                    handles.push(h.unwrap());
+                }
+            }
+            for h in handles {
+                h.join().unwrap();
+            }
+            Arc::try_unwrap(order).unwrap().into_inner().unwrap()
+        })
+    };
+    use Priority::*;
+    assert_eq!(
+        order,
+        vec![High, High, High, High, Normal, Normal, Normal, Normal, Low, Low, Low, Low]
+    );
+}
+
+#[test]
+fn coroutine_priority_no_starvation() {
+    use may::coroutine::Priority;
+    use may::RuntimeBuilder;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    let rt = RuntimeBuilder::new().workers(1).build().unwrap();
+    let flag = Arc::new(AtomicBool::new(false));
+    let f = flag.clone();
+    let high = coroutine::Builder::new().priority(Priority::High);
+    let busy = unsafe {
+        rt.spawn_with(high, move || {
+            while !f.load(Ordering::Acquire) {
+                coroutine::yield_now();
+            }
+        })
+        .unwrap()
+    };
+    let low = coroutine::Builder::new().priority(Priority::Low);
+    let h = unsafe { rt.spawn_with(low, move || flag.store(true, Ordering::Release)) };
+    h.unwrap().join().unwrap();
+    busy.join().unwrap();
+}
+
+#[test]
+fn runtime_set_active_workers() {
+    use may::RuntimeBuilder;
+
+    let rt = RuntimeBuilder::new()
+        .workers(1)
+        .max_workers(3)
+        .build()
+        .unwrap();
+    assert_eq!(rt.workers(), 1);
+    assert!(rt.set_active_workers(0).is_err());
+    assert!(rt.set_active_workers(4).is_err());
+
+    // the blocking coroutines run on different workers in parallel
+    rt.set_active_workers(3).unwrap();
+    assert_eq!(rt.metrics().active_workers, 3);
+    let now = Instant::now();
+    let handles: Vec<_> = (0..3)
+        .map(|_| unsafe { rt.spawn(|| thread::sleep(Duration::from_millis(100))) })
+        .collect();
+    for h in handles {
+        h.join().unwrap();
+    }
+    assert!(now.elapsed() < Duration::from_millis(250));
+
+    // only the first worker is left
+    rt.set_active_workers(1).unwrap();
+    let handles: Vec<_> = (0..10)
+        .map(|_| unsafe {
+            rt.spawn(|| {
+                coroutine::yield_now();
+                thread::current().name().map(String::from)
+            })
+        })
+        .collect();
+    for h in handles {
+        assert_eq!(h.join().unwrap().as_deref(), Some("may-worker-0"));
+    }
+
+    // the new sockets are registered on the active worker only, each of them
+    // gets a writable event right after the registration
+    let io_events = |rt: &may::Runtime| -> Vec<usize> {
+        let m = rt.metrics();
+        m.workers
+            .iter()
+            .map(|w| w.event_count - w.notify_count)
+            .collect()
+    };
+    let before = io_events(&rt);
+    let h = unsafe {
+        rt.spawn(|| {
+            (0..8)
+                .map(|_| may::net::UdpSocket::bind("127.0.0.1:0").unwrap())
+                .collect::<Vec<_>>()
+        })
+    };
+    let sockets = h.join().unwrap();
+    thread::sleep(Duration::from_millis(50));
+    let after = io_events(&rt);
+    assert!(after[0] >= before[0] + sockets.len());
+    assert_eq!(after[1..], before[1..]);
+
+    // the workers can grow to twice the initial number by default
+    let rt = RuntimeBuilder::new().workers(2).build().unwrap();
+    rt.set_active_workers(4).unwrap();
+    assert_eq!(rt.metrics().active_workers, 4);
+    assert!(rt.set_active_workers(5).is_err());
+    let rt = RuntimeBuilder::new()
+        .workers(2)
+        .max_workers(2)
+        .build()
+        .unwrap();
+    assert!(rt.set_active_workers(3).is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn runtime_retired_worker_io() {
+    use std::io::{Read, Write};
+    use std::os::unix::io::AsRawFd;
+
+    use may::RuntimeBuilder;
+
+    let rt = RuntimeBuilder::new().workers(2).build().unwrap();
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    // get a socket that is registered on worker 1
+    let h = unsafe {
+        rt.spawn(move || {
+            let mut streams = Vec::new();
+            loop {
+                let stream = may::net::TcpStream::connect(addr).unwrap();
+                if stream.as_raw_fd() % 2 == 1 {
+                    return (stream, streams);
+                }
+                streams.push(stream);
+            }
+        })
+    };
+    let (mut stream, streams) = h.join().unwrap();
+    let mut peers: Vec<_> = (0..=streams.len())
+        .map(|_| listener.accept().unwrap().0)
+        .collect();
+    let mut peer = peers.pop().unwrap();
+
+    let reader = unsafe {
+        rt.spawn(move || {
+            let mut buf = [0; 4];
+            stream.read_exact(&mut buf).unwrap();
+            buf
+        })
+    };
+    thread::sleep(Duration::from_millis(50));
+
+    // retire worker 1 and block its thread
+    rt.set_active_workers(1).unwrap();
+    thread::sleep(Duration::from_millis(50));
+    let pinned = coroutine::Builder::new().pin_to_worker(1);
+    let blocker = unsafe { rt.spawn_with(pinned, || thread::sleep(Duration::from_secs(2))) };
+    let blocker = blocker.unwrap();
+    thread::sleep(Duration::from_millis(50));
+
+    // the socket is polled by worker 0 now
+    let now = Instant::now();
+    peer.write_all(b"ping").unwrap();
+    assert_eq!(&reader.join().unwrap(), b"ping");
+    assert!(now.elapsed() < Duration::from_secs(1));
+    blocker.join().unwrap();
+}
+
+#[test]
+fn runtime_lifo_slot() {
+    use may::RuntimeBuilder;
+    use std::sync::{Arc, Mutex};
+
+    fn wake_order(lifo_slot: bool) -> Vec<&'static str> {
+        let rt = RuntimeBuilder::new()
+            .workers(1)
+            .lifo_slot(lifo_slot)
+            .build()
+            .unwrap();
+        unsafe {
+            rt.block_on(|| {
+                let order = Arc::new(Mutex::new(Vec::new()));
+                let (tx1, rx1) = may::sync::mpsc::channel();
+                let (tx2, rx2) = may::sync::mpsc::channel();
+                let o = order.clone();
+                let h1 = go!(move || {
+                    rx1.recv().unwrap();
+                    o.lock().unwrap().push("first");
+                });
+                let o = order.clone();
+                let h2 = go!(move || {
+                    rx2.recv().unwrap();
+                    o.lock().unwrap().push("second");
+                });
+                // wait both receivers block, then get back to the worker
+                coroutine::sleep(Duration::from_millis(50));
+                coroutine::yield_now();
+
+                // the last woken one runs next with the lifo slot
+                tx1.send(()).unwrap();
+                tx2.send(()).unwrap();
+                h1.join().unwrap();
+                h2.join().unwrap();
+                let order = order.lock().unwrap();
+                order.clone()
+            })
+        }
+    }
+
+    assert_eq!(wake_order(true), ["second", "first"]);
+    assert_eq!(wake_order(false), ["first", "second"]);
+}
+
+#[test]
+fn runtime_idle_policy() {
+    use may::{IdlePolicy, RuntimeBuilder};
+
+    let run = |policy| {
+        let rt = RuntimeBuilder::new()
+            .workers(1)
+            .idle_policy(policy)
+            .build()
+            .unwrap();
+        for _ in 0..10 {
+            unsafe { rt.spawn(|| {}) }.join().unwrap();
+            thread::sleep(Duration::from_millis(1));
+        }
+        rt.metrics().workers[0].clone()
+    };
+
+    // the spinning worker picks up the new coroutines without blocking
+    let m = run(IdlePolicy::Spin(Duration::from_millis(100)));
+    assert!(m.spin_hit_count > 0);
+
+    // the blocked worker only wakes up for the notifications
+    let m = run(IdlePolicy::Block);
+    assert!(m.park_count > 0);
+    assert!(m.notify_count > 0);
+    assert_eq!(m.idle_wakeup_count, 0);
+}
+
+#[test]
+fn coroutine_pin_to_worker() {
+    use may::RuntimeBuilder;
+
+    fn worker_name() -> Option<String> {
+        thread::current().name().map(String::from)
+    }
+
+    let rt = RuntimeBuilder::new()
+        .workers(3)
+        .max_workers(3)
+        .build()
+        .unwrap();
+    let (tx, rx) = may::sync::mpsc::channel::<()>();
+    let h = unsafe {
+        rt.spawn(move || {
+            let builder = coroutine::Builder::new().pin_to_worker(3);
+            assert!(builder.spawn(|| {}).is_err());
+            assert!(coroutine::migrate_to(3).is_err());
+
+            let builder = coroutine::Builder::new().pin_to_worker(1);
+            let h = builder
+                .spawn(move || {
+                    assert_eq!(coroutine::current().pinned_worker(), Some(1));
+                    assert_eq!(worker_name().as_deref(), Some("may-worker-1"));
+                    // woken up by the timer thread
+                    coroutine::sleep(Duration::from_millis(10));
+                    assert_eq!(worker_name().as_deref(), Some("may-worker-1"));
+                    // woken up by a normal thread
+                    rx.recv().unwrap();
+                    assert_eq!(worker_name().as_deref(), Some("may-worker-1"));
+                    coroutine::yield_now();
+                    assert_eq!(worker_name().as_deref(), Some("may-worker-1"));
+
+                    // the pinned coroutine is pinned to the new worker
+                    coroutine::migrate_to(2).unwrap();
+                    assert_eq!(coroutine::current().pinned_worker(), Some(2));
+                    coroutine::sleep(Duration::from_millis(10));
+                    assert_eq!(worker_name().as_deref(), Some("may-worker-2"));
+                })
+                .unwrap();
+
+            // the unpinned coroutine is moved once
+            coroutine::migrate_to(2).unwrap();
+            assert_eq!(worker_name().as_deref(), Some("may-worker-2"));
+            assert_eq!(coroutine::current().pinned_worker(), None);
+            h
+        })
+    }
+    .join()
+    .unwrap();
+
+    thread::sleep(Duration::from_millis(50));
+    tx.send(()).unwrap();
+    h.join().unwrap();
+}
+
+#[test]
+fn current_thread_runtime() {
+    use may::CurrentThreadRuntime;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    let rt = CurrentThreadRuntime::new().unwrap();
+    let main_id = thread::current().id();
+    let (tx, rx) = may::sync::mpsc::channel();
+    thread::spawn(move || {
+        thread::sleep(Duration::from_millis(20));
+        tx.send(10).unwrap();
+    });
+
+    // the !Send data is shared by the coroutines of the runtime
+    let count = Rc::new(Cell::new(0));
+    let c = count.clone();
+    let ret = unsafe {
+        rt.block_on(move || {
+            assert_eq!(thread::current().id(), main_id);
+            let h = go!(move || {
+                assert_eq!(thread::current().id(), main_id);
+                coroutine::sleep(Duration::from_millis(10));
+                1
+            });
+            c.set(c.get() + 1);
+            coroutine::sleep(Duration::from_millis(10));
+            let v = rx.recv().unwrap();
+            assert_eq!(thread::current().id(), main_id);
+            v + h.join().unwrap()
+        })
+    };
+    assert_eq!(ret, 11);
+    assert_eq!(count.get(), 1);
+
+    // drive the runtime from an external loop
+    let c = count.clone();
+    let h = unsafe {
+        rt.spawn(move || {
+            coroutine::sleep(Duration::from_millis(10));
+            c.set(c.get() + 1);
+        })
+    };
+    assert_eq!(count.get(), 1);
+    rt.turn(Duration::ZERO);
+    let start = Instant::now();
+    while !h.is_done() {
+        rt.turn(Duration::from_millis(100));
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+    assert_eq!(count.get(), 2);
+
+    // the panic is resumed in the caller
+    let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| unsafe {
+        rt.block_on(|| panic!("current thread panic"))
+    }));
+    assert!(r.is_err());
+
+    let report = rt.shutdown(Duration::from_millis(100)).unwrap();
+    assert_eq!(report.alive_count(), 0);
+}
+
+#[test]
+fn runtime_clock_pause_advance() {
+    use may::net::{TcpListener, TcpStream};
+    use may::sync::{mpsc, Semphore};
+    use std::io::Read;
+    use std::sync::{Arc, Mutex};
+
+    let rt = may::RuntimeBuilder::new().workers(2).build().unwrap();
+    let clock = rt.clock();
+    clock.pause();
+    assert!(clock.is_paused());
+    let start = clock.now();
+
+    let order = Arc::new(Mutex::new(Vec::new()));
+    let mut handles = Vec::new();
+    for secs in [30u64, 10, 20] {
+        let order = order.clone();
+        handles.push(unsafe {
+            rt.spawn(move || {
+                coroutine::sleep(Duration::from_secs(secs));
+                order.lock().unwrap().push(secs);
+            })
+        });
+    }
+    let sem_h = unsafe {
+        rt.spawn(|| {
+            let sem = Semphore::new(0);
+            assert!(!sem.wait_timeout(Duration::from_secs(15)));
+        })
+    };
+    let recv_h = unsafe {
+        rt.spawn(|| {
+            let (_tx, rx) = mpsc::channel::<()>();
+            rx.recv_timeout(Duration::from_secs(15)).unwrap_err();
+        })
+    };
+    let io_h = unsafe {
+        rt.spawn(|| {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let mut s = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+            let _peer = listener.accept().unwrap();
+            s.set_read_timeout(Some(Duration::from_secs(15))).unwrap();
+            let err = s.read(&mut [0; 8]).unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+        })
+    };
+
+    // nothing fires while the clock is paused
+    thread::sleep(Duration::from_millis(100));
+    assert!(!sem_h.is_done() && !recv_h.is_done() && !io_h.is_done());
+    assert!(order.lock().unwrap().is_empty());
+
+    clock.advance(Duration::from_secs(25));
+    sem_h.join().unwrap();
+    recv_h.join().unwrap();
+    io_h.join().unwrap();
+    // the timers of all the workers fire in the order of their deadlines
+    assert_eq!(*order.lock().unwrap(), vec![10, 20]);
+
+    clock.advance(Duration::from_secs(5));
+    for h in handles {
+        h.join().unwrap();
+    }
+    assert_eq!(*order.lock().unwrap(), vec![10, 20, 30]);
+    assert_eq!(clock.now() - start, Duration::from_secs(30));
+
+    // the clock runs again from the paused time
+    clock.resume();
+    assert!(!clock.is_paused());
+    let now = clock.now();
+    assert!(now - start >= Duration::from_secs(30));
+    assert!(now - start < Duration::from_secs(31));
+    let t = Instant::now();
+    unsafe { rt.block_on(|| coroutine::sleep(Duration::from_millis(20))) };
+    assert!(t.elapsed() >= Duration::from_millis(20));
+}
+
+#[test]
+fn time_interval_missed_ticks() {
+    use may::time::{self, MissedTickBehavior};
+    use may::CurrentThreadRuntime;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let ms = Duration::from_millis;
+    let cases = [
+        (MissedTickBehavior::Burst, [0, 10, 20, 30, 40]),
+        (MissedTickBehavior::Delay, [0, 10, 45, 55, 65]),
+        (MissedTickBehavior::Skip, [0, 10, 40, 50, 60]),
+    ];
+    for (behavior, expected) in cases {
+        let rt = CurrentThreadRuntime::new().unwrap();
+        let clock = rt.clock();
+        clock.pause();
+        let ticks = Rc::new(RefCell::new(Vec::new()));
+        let t = ticks.clone();
+        let h = unsafe {
+            rt.spawn(move || {
+                let start = time::now();
+                let mut interval = time::interval(ms(10));
+                interval.set_missed_tick_behavior(behavior);
+                for i in 0..5 {
+                    let tick = interval.tick();
+                    assert!(time::now() >= tick);
+                    t.borrow_mut().push((tick - start).as_millis());
+                    if i == 0 {
+                        // the job is slow and misses the next tick
+                        time::advance(ms(35));
+                    }
+                }
+            })
+        };
+        while !h.is_done() {
+            rt.turn(Duration::ZERO);
+            clock.advance(ms(1));
+        }
+        h.join().unwrap();
+        assert_eq!(*ticks.borrow(), expected, "{behavior:?}");
+    }
+}
+
+#[test]
+fn time_timer_reset_cancel() {
+    use may::time::{self, Timer};
+    use may::CurrentThreadRuntime;
+
+    let ms = Duration::from_millis;
+    let rt = CurrentThreadRuntime::new().unwrap();
+    let clock = rt.clock();
+    clock.pause();
+    let h = unsafe {
+        rt.spawn(move || {
+            let start = time::now();
+            let timer = Timer::new(ms(10));
+            let t = timer.clone();
+            go!(move || {
+                coroutine::sleep(ms(5));
+                t.reset(ms(20));
+            });
+            assert!(timer.wait());
+            assert_eq!(time::now() - start, ms(25));
+            assert!(timer.is_elapsed());
+
+            // re-arm the fired timer and cancel it before it fires
+            timer.reset(ms(10));
+            let t = timer.clone();
+            go!(move || {
+                coroutine::sleep(ms(5));
+                t.cancel();
+            });
+            assert!(!timer.wait());
+            assert_eq!(time::now() - start, ms(30));
+            assert_eq!(timer.deadline(), None);
+        })
+    };
+    while !h.is_done() {
+        rt.turn(Duration::ZERO);
+        clock.advance(ms(1));
+    }
+    h.join().unwrap();
+}
+
+#[test]
+fn time_timer_on_wheel() {
+    use may::time::Timer;
+    use may::CurrentThreadRuntime;
+
+    let rt = CurrentThreadRuntime::new().unwrap();
+    let clock = rt.clock();
+    clock.pause();
+    let timer = Timer::new(Duration::from_secs(3600));
+    let t = timer.clone();
+    let h = unsafe { rt.spawn(move || t.wait()) };
+    rt.turn(Duration::ZERO);
+    // the waiter keeps one timer on the wheel
+    assert_eq!(rt.metrics().pending_timers, 1);
+
+    // reset moves the timer instead of adding another one
+    timer.reset(Duration::from_secs(7200));
+    rt.turn(Duration::ZERO);
+    assert_eq!(rt.metrics().pending_timers, 1);
+    clock.advance(Duration::from_secs(3600));
+    rt.turn(Duration::ZERO);
+    assert!(!h.is_done());
+
+    // cancel deletes the timer and wakes up the waiter
+    timer.cancel();
+    assert_eq!(rt.metrics().pending_timers, 0);
+    rt.turn(Duration::ZERO);
+    assert!(!h.join().unwrap());
+
+    // a thread waiter is unparked by the cancel
+    let timer = Timer::new(Duration::from_secs(3600));
+    let t = timer.clone();
+    let h = std::thread::spawn(move || t.wait());
+    std::thread::sleep(Duration::from_millis(10));
+    timer.cancel();
+    assert!(!h.join().unwrap());
+}
@@ -0,0 +1,26 @@
+#[macro_use]
+extern crate may;
+
+use std::time::Duration;
+
+use may::coroutine;
+
+// the default runtime is started only once, so all the checks are in one test
+#[test]
+fn shutdown_default_runtime() {
+    // nothing is started for the shutdown
+    let report = may::shutdown(Duration::from_millis(100)).unwrap();
+    assert!(report.is_clean());
+    assert_eq!(report.alive_count(), 0);
+    assert_eq!(report.detached_threads(), 0);
+
+    let h = go!(|| 1);
+    assert_eq!(h.join().unwrap(), 1);
+    let report = may::shutdown(Duration::from_millis(100)).unwrap();
+    assert!(report.is_clean());
+
+    // no new coroutines after the shutdown
+    let r = unsafe { coroutine::Builder::new().spawn(|| {}) };
+    assert!(r.is_err());
+    assert!(may::shutdown(Duration::from_millis(100)).is_err());
+}
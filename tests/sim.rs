@@ -0,0 +1,127 @@
+#[macro_use]
+extern crate may;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use may::coroutine;
+use may::sim::{self, Simulation};
+use may::sync::{mpsc, Semphore};
+
+// run a few coroutines that race on a shared log, return the interleaving
+fn interleaving(seed: u64) -> Vec<(usize, usize)> {
+    let sim = Simulation::new(seed).unwrap();
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let l = log.clone();
+    unsafe {
+        sim.block_on(move || {
+            let (tx, rx) = mpsc::channel();
+            let handles: Vec<_> = (0..4)
+                .map(|id| {
+                    let tx = tx.clone();
+                    go!(move || {
+                        for step in 0..5 {
+                            tx.send((id, step)).unwrap();
+                            coroutine::yield_now();
+                        }
+                    })
+                })
+                .collect();
+            drop(tx);
+            for v in rx.iter() {
+                l.borrow_mut().push(v);
+            }
+            for h in handles {
+                h.join().unwrap();
+            }
+        })
+    };
+    let log = log.borrow().clone();
+    log
+}
+
+#[test]
+fn sim_replay_same_seed() {
+    for seed in 0..10 {
+        let a = interleaving(seed);
+        assert_eq!(a.len(), 20);
+        assert_eq!(a, interleaving(seed));
+    }
+    // different seeds explore different interleavings
+    let first = interleaving(0);
+    assert!((1..10).any(|seed| interleaving(seed) != first));
+}
+
+#[test]
+fn sim_virtual_clock() {
+    let sim = Simulation::new(sim::seed()).unwrap();
+    let start = std::time::Instant::now();
+    let order = unsafe {
+        sim.block_on(|| {
+            let order = Arc::new(Mutex::new(Vec::new()));
+            let handles: Vec<_> = [3u64, 1, 2]
+                .iter()
+                .map(|&secs| {
+                    let order = order.clone();
+                    go!(move || {
+                        coroutine::sleep(Duration::from_secs(secs * 3600));
+                        order.lock().unwrap().push(secs);
+                    })
+                })
+                .collect();
+
+            // the timeouts also run on the virtual clock
+            let sem = Semphore::new(0);
+            assert!(!sem.wait_timeout(Duration::from_secs(600)));
+
+            for h in handles {
+                h.join().unwrap();
+            }
+            let v = order.lock().unwrap().clone();
+            v
+        })
+    };
+    assert_eq!(order, vec![1, 2, 3]);
+    assert!(sim.elapsed() >= Duration::from_secs(3 * 3600));
+    assert!(start.elapsed() < Duration::from_secs(10));
+}
+
+#[test]
+fn sim_panic_is_resumed() {
+    let r = std::panic::catch_unwind(|| unsafe {
+        sim::run(|| {
+            coroutine::sleep(Duration::from_secs(1));
+            panic!("simulation failure");
+        })
+    });
+    assert!(r.is_err());
+}
+
+#[test]
+fn sim_deadlock_reports_seed() {
+    let sim = Simulation::new(42).unwrap();
+    let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| unsafe {
+        sim.block_on(|| {
+            // the sender is alive, but nobody would send
+            let (_tx, rx) = mpsc::channel::<()>();
+            rx.recv().ok();
+        })
+    }));
+    let e = r.unwrap_err();
+    let msg = e.downcast_ref::<String>().unwrap();
+    assert!(msg.contains("deadlocked"));
+    assert!(msg.contains("MAY_SIM_SEED=42"));
+
+    // the wakeups from other threads are still waited for
+    let sim = Simulation::new(sim::seed()).unwrap();
+    let ret = unsafe {
+        sim.block_on(|| {
+            let (tx, rx) = mpsc::channel();
+            std::thread::spawn(move || tx.send(7).unwrap());
+            rx.recv().unwrap()
+        })
+    };
+    assert_eq!(ret, 7);
+}